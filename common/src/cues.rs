@@ -0,0 +1,23 @@
+use serde::{Deserialize, Serialize};
+
+/// Whether a cue point starts or ends an ad break.
+#[derive(Serialize, Deserialize, Clone, Copy, Debug, PartialEq, Eq)]
+#[serde(rename_all = "snake_case")]
+pub enum CueKind {
+    Out,
+    In,
+}
+
+/// An ad marker sent by an encoder as an AMF `onCuePoint` message.
+#[derive(Serialize, Deserialize, Clone, Debug, PartialEq)]
+pub struct CuePoint {
+    pub kind: CueKind,
+    pub id: Option<String>,
+    /// Stream time of the cue in milliseconds, as carried by the RTMP
+    /// timestamp of the message.
+    pub time_ms: u32,
+    /// Planned length of the ad break in seconds.
+    pub duration: Option<f64>,
+    /// The SCTE-35 `splice_info_section`, hex (`0x...`) or base64 encoded.
+    pub scte35: Option<String>,
+}
@@ -1,4 +1,7 @@
+use std::cell::Cell;
 use std::net::IpAddr;
+use std::panic::AssertUnwindSafe;
+use std::sync::atomic::{AtomicBool, Ordering};
 
 use anyhow::Result;
 use axum::{Json, http::HeaderMap, response::IntoResponse};
@@ -9,6 +12,7 @@ use tokio_postgres_rustls::MakeRustlsConnect;
 
 pub mod args;
 pub mod cors;
+pub mod cues;
 pub mod metrics;
 pub mod postgres;
 pub mod rbac;
@@ -19,6 +23,10 @@ pub mod annotations {
     pub const STABLE_ID: &str = "strim.beebs.dev/stable-id";
     pub const CREATED_BY: &str = "strim.beebs.dev/created-by";
     pub const SPEC_HASH: &str = "strim.beebs.dev/spec-hash";
+    pub const VIDEO: &str = "strim.beebs.dev/video";
+    pub const AUDIO: &str = "strim.beebs.dev/audio";
+    /// Set by moderators on a `Strim` to stop its stream, e.g. `banned`.
+    pub const MODERATION: &str = "strim.beebs.dev/moderation";
 }
 
 pub fn init() {
@@ -39,10 +47,44 @@ pub fn install_rustls_provider() {
         .expect("install aws-lc-rs provider");
 }
 
+/// Set once the process starts draining, failing its readiness probes.
+static DRAINING: AtomicBool = AtomicBool::new(false);
+
 pub fn signal_ready() {
     std::fs::write("/etc/ready", b"ready").expect("could not write /etc/ready");
 }
 
+/// Marks the process as not ready so it is taken out of its Service
+/// while it finishes serving existing clients.
+pub fn signal_draining() {
+    DRAINING.store(true, Ordering::SeqCst);
+    let _ = std::fs::remove_file("/etc/ready");
+}
+
+pub fn is_draining() -> bool {
+    DRAINING.load(Ordering::SeqCst)
+}
+
+thread_local! {
+    /// Set while [`contain_panic`] runs on this thread.
+    static CONTAINING_PANIC: Cell<bool> = const { Cell::new(false) };
+}
+
+/// Runs `f`, returning a panic in it as an error instead of letting the
+/// panic hook exit the process. For work whose failure only concerns one
+/// client, like parsing its input.
+pub fn contain_panic<R>(f: impl FnOnce() -> R) -> std::thread::Result<R> {
+    let containing = CONTAINING_PANIC.replace(true);
+    let result = std::panic::catch_unwind(AssertUnwindSafe(f));
+    CONTAINING_PANIC.set(containing);
+    result
+}
+
+/// Whether a panic on this thread is caught by [`contain_panic`].
+pub fn is_containing_panic() -> bool {
+    CONTAINING_PANIC.get()
+}
+
 pub fn make_rustls(certs: Vec<CertificateDer<'_>>) -> Result<MakeRustlsConnect> {
     let mut roots = RootCertStore::empty();
     for cert in rustls_native_certs::load_native_certs().expect("could not load platform certs") {
@@ -44,10 +44,13 @@ pub fn maybe_spawn_metrics_server() {
     // when a panic occurs on any thread. This is desired behavior when
     // running in a container, as the metrics server or controller may
     // panic and we always want to restart the container in that case.
+    // Panics caught by `contain_panic` only concern one client.
     let default_panic = std::panic::take_hook();
     std::panic::set_hook(Box::new(move |info| {
         default_panic(info);
-        std::process::exit(1);
+        if !crate::is_containing_panic() {
+            std::process::exit(1);
+        }
     }));
 
     let Some(port) = metric_port_env() else {
@@ -61,6 +64,14 @@ pub fn maybe_spawn_metrics_server() {
     }
 }
 
+async fn readyz() -> (StatusCode, &'static str) {
+    if crate::is_draining() {
+        (StatusCode::SERVICE_UNAVAILABLE, "draining")
+    } else {
+        (StatusCode::OK, "ok")
+    }
+}
+
 pub async fn run_metrics_server(port: u16, node_id: String) {
     let handle = install_recorder_once().clone();
     let metrics_route = {
@@ -69,7 +80,7 @@ pub async fn run_metrics_server(port: u16, node_id: String) {
     };
     let app = Router::new()
         .route("/healthz", get(|| async { "ok" }))
-        .route("/readyz", get(|| async { "ok" }))
+        .route("/readyz", get(readyz))
         .route("/metrics", metrics_route)
         .layer(MetricsLayer::new(node_id));
     let addr = format!("0.0.0.0:{}", port);
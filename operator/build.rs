@@ -9,4 +9,9 @@ fn main() {
         serde_yaml::to_string(&Strim::crd()).unwrap(),
     )
     .unwrap();
+    fs::write(
+        "../crds/strim.beebs.dev_strimingest_crd.yaml",
+        serde_yaml::to_string(&StrimIngest::crd()).unwrap(),
+    )
+    .unwrap();
 }
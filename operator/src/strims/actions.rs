@@ -4,7 +4,7 @@ use k8s_openapi::api::core::v1::{
     VolumeMount,
 };
 use kube::{
-    Api, Client,
+    Api, Client, ResourceExt,
     api::{ObjectMeta, Resource},
 };
 use strim_common::annotations;
@@ -90,6 +90,69 @@ pub async fn terminating(client: Client, instance: &Strim, reason: String) -> Re
     Ok(())
 }
 
+/// Environment of the ffmpeg container overriding its HLS defaults.
+fn hls_env(instance: &Strim) -> Vec<EnvVar> {
+    let mut env = recording_env(instance);
+    let Some(ref hls) = instance.spec.hls else {
+        return env;
+    };
+    env.extend(
+        [
+            ("HLS_TIME", hls.segment_duration),
+            ("HLS_LIST_SIZE", hls.list_size),
+        ]
+        .into_iter()
+        .filter_map(|(name, value)| {
+            value.map(|value| EnvVar {
+                name: name.to_string(),
+                value: Some(value.to_string()),
+                ..Default::default()
+            })
+        }),
+    );
+    env
+}
+
+/// Environment telling ffmpeg and peggy to keep every segment of a
+/// recorded stream.
+fn recording_env(instance: &Strim) -> Vec<EnvVar> {
+    let recording = match instance.spec.recording {
+        Some(StrimRecording::Record) => "record",
+        Some(StrimRecording::Append) => "append",
+        None => return Vec::new(),
+    };
+    vec![EnvVar {
+        name: "HLS_RECORDING".to_string(),
+        value: Some(recording.to_string()),
+        ..Default::default()
+    }]
+}
+
+/// Environment of the peggy container subscribing it to the cue points
+/// of its stream, when the operator is configured with a NATS server.
+fn cue_env(instance: &Strim) -> Vec<EnvVar> {
+    let Some(nats_url) = std::env::var("NATS_URL").ok().filter(|url| !url.is_empty()) else {
+        return Vec::new();
+    };
+    let Some(stable_id) = instance.annotations().get(annotations::STABLE_ID) else {
+        return Vec::new();
+    };
+    let subject_prefix =
+        std::env::var("NATS_SUBJECT_PREFIX").unwrap_or_else(|_| "strim".to_string());
+    [
+        ("NATS_URL", nats_url),
+        ("NATS_SUBJECT_PREFIX", subject_prefix),
+        ("STABLE_ID", stable_id.clone()),
+    ]
+    .into_iter()
+    .map(|(name, value)| EnvVar {
+        name: name.to_string(),
+        value: Some(value),
+        ..Default::default()
+    })
+    .collect()
+}
+
 pub fn pod_resource(instance: &Strim) -> Result<Pod, Error> {
     // For simplicity, we create a pod spec with a single container
     // that runs ffmpeg to stream from the source to the destination
@@ -133,18 +196,23 @@ pub fn pod_resource(instance: &Strim) -> Result<Pod, Error> {
                         mount_path: HLS_DIR.to_string(),
                         ..Default::default()
                     }]),
-                    env: Some(vec![
-                        EnvVar {
-                            name: "HLS_DIR".to_string(),
-                            value: Some(HLS_DIR.to_string()),
-                            ..Default::default()
-                        },
-                        EnvVar {
-                            name: "RTMP_URL".to_string(),
-                            value: Some(instance.spec.source.internal_url.clone()),
-                            ..Default::default()
-                        },
-                    ]),
+                    env: Some(
+                        vec![
+                            EnvVar {
+                                name: "HLS_DIR".to_string(),
+                                value: Some(HLS_DIR.to_string()),
+                                ..Default::default()
+                            },
+                            EnvVar {
+                                name: "RTMP_URL".to_string(),
+                                value: Some(instance.spec.source.internal_url.clone()),
+                                ..Default::default()
+                            },
+                        ]
+                        .into_iter()
+                        .chain(hls_env(instance))
+                        .collect(),
+                    ),
                     ..Default::default()
                 },
                 Container {
@@ -228,6 +296,8 @@ pub fn pod_resource(instance: &Strim) -> Result<Pod, Error> {
                                 ..Default::default()
                             });
                         }
+                        env.extend(recording_env(instance));
+                        env.extend(cue_env(instance));
                         env
                     }),
                     ..Default::default()
@@ -268,7 +338,7 @@ pub async fn create_pod(client: Client, instance: &Strim) -> Result<(), Error> {
         Ok(_) => Ok(()),
         Err(e) => match e {
             kube::Error::Api(ae) if ae.code == 409 => Ok(()),
-            _ => return Err(Error::from(e)),
+            _ => Err(Error::from(e)),
         },
     }
 }
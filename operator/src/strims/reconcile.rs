@@ -438,7 +438,7 @@ fn determine_phase_action(pod: &Pod) -> Option<StrimAction> {
     match pod.status.as_ref().and_then(|s| s.phase.as_deref()) {
         Some("Running") => None,
         Some("Pending") => {
-            return if let Some(status) = &pod.status
+            if let Some(status) = &pod.status
                 && let Some(cond) = status
                     .conditions
                     .as_ref()
@@ -455,7 +455,7 @@ fn determine_phase_action(pod: &Pod) -> Option<StrimAction> {
                 Some(StrimAction::Pending {
                     reason: format!("Pod '{}' is still in Pending phase", pod.name_any()),
                 })
-            };
+            }
         }
         Some(v) if ["Succeeded", "Failed"].contains(&v) => Some(StrimAction::DeletePod {
             reason: format!("Pod unexpectedly terminated with '{}' phase", v),
@@ -535,7 +535,7 @@ fn check_container_status(
     if let Some(ref waiting) = state.waiting {
         // Note: there may not be a waiting reason, in which case we treat it as not existing.
         let reason_str = waiting.reason.as_deref().unwrap_or("");
-        const FATAL_WAITING: &[&'static str] = &[
+        const FATAL_WAITING: &[&str] = &[
             "ImagePullBackOff",
             "ErrImageNeverPull",
             "RegistryUnavailable",
@@ -701,9 +701,7 @@ pub fn get_phase(instance: &Strim) -> Option<StrimPhase> {
 }
 
 pub fn get_last_updated(instance: &Strim) -> Option<Duration> {
-    let Some(status) = instance.status.as_ref() else {
-        return None;
-    };
+    let status = instance.status.as_ref()?;
     let Ok(Some(last_updated)) = status
         .last_updated
         .as_ref()
@@ -21,11 +21,7 @@ pub trait Status {
 
 impl Object<StrimStatus> for Strim {
     fn mut_status(&mut self) -> &mut StrimStatus {
-        if self.status.is_some() {
-            return self.status.as_mut().unwrap();
-        }
-        self.status = Some(Default::default());
-        self.status.as_mut().unwrap()
+        self.status.get_or_insert_with(Default::default)
     }
 }
 
@@ -3,11 +3,43 @@ set -euo pipefail
 
 : "${RTMP_URL:?RTMP_URL env var is required}"
 HLS_DIR="${HLS_DIR:-/hls}"
+HLS_TIME="${HLS_TIME:-8}"
+HLS_LIST_SIZE="${HLS_LIST_SIZE:-450}"
+HLS_RECORDING="${HLS_RECORDING:-}"
 mkdir -p "${HLS_DIR}"
 
+# Live streams keep a sliding window of segments. Recordings list every
+# segment, and appending recordings continue the playlist peggy restores.
+HLS_FLAGS="delete_segments+append_list+temp_file+program_date_time"
+HLS_EXTRA_ARGS=()
+case "${HLS_RECORDING}" in
+  "") ;;
+  record|append)
+    HLS_LIST_SIZE=0
+    HLS_FLAGS="append_list+temp_file+program_date_time"
+    HLS_EXTRA_ARGS=(-hls_playlist_type event)
+    if [[ "${HLS_RECORDING}" == "append" ]]; then
+      HLS_FLAGS="${HLS_FLAGS}+discont_start"
+      for _ in {1..300}; do
+        [[ -e "${HLS_DIR}/.restored" ]] && break
+        sleep 0.1
+      done
+      [[ -e "${HLS_DIR}/.restored" ]] || echo "playlist was not restored, starting a new one" >&2
+    fi
+    ;;
+  *)
+    echo "HLS_RECORDING must be record or append, got '${HLS_RECORDING}'" >&2
+    exit 1
+    ;;
+esac
+
 PLAYLIST="${HLS_DIR}/index.m3u8"
 SEGMENT_PATTERN="${HLS_DIR}/segment_%05d.ts"
 
+# Only audio and video are packaged. AMF data streams such as onTextData
+# and onCaptionInfo are dropped: captions are not converted to WebVTT or
+# CEA-608. Ad cue points reach the playlist through peggy instead, which
+# reads them from NATS.
 ffmpeg_pid=""
 
 cleanup() {
@@ -30,12 +62,18 @@ ffmpeg \
   -thread_queue_size 1024 \
   -nostdin \
   -i "${RTMP_URL}" \
+  -map 0:v? \
+  -map 0:a? \
   -c:v copy \
   -c:a copy \
+  -copyts \
+  -muxdelay 0 \
+  -muxpreload 0 \
   -f hls \
-  -hls_time 8 \
-  -hls_list_size 450 \
-  -hls_flags delete_segments+append_list+temp_file \
+  -hls_time "${HLS_TIME}" \
+  -hls_list_size "${HLS_LIST_SIZE}" \
+  -hls_flags "${HLS_FLAGS}" \
+  "${HLS_EXTRA_ARGS[@]}" \
   -hls_segment_type mpegts \
   -hls_segment_filename "${SEGMENT_PATTERN}" \
   "${PLAYLIST}" &
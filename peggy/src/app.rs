@@ -21,18 +21,26 @@ use std::{
 use tokio::{select, sync::mpsc};
 use tokio_util::sync::CancellationToken;
 
-use crate::args::RunArgs;
+use crate::{args::RunArgs, cues::CueTracker};
+
+/// Name of the playlist ffmpeg writes.
+const PLAYLIST: &str = "index.m3u8";
+/// Written to the HLS directory once an appending recording's playlist is
+/// restored, which ffmpeg waits for before it starts.
+const RESTORED_MARKER: &str = ".restored";
 
 const FG1_COLOR: (u8, u8, u8) = (163, 83, 207);
 const FG2_COLOR: (u8, u8, u8) = (90, 70, 130);
-const FG1: Rgb = Rgb(FG1_COLOR.0, FG1_COLOR.1, FG1_COLOR.2);
-const FG2: Rgb = Rgb(FG2_COLOR.0, FG2_COLOR.1, FG2_COLOR.2);
+pub(crate) const FG1: Rgb = Rgb(FG1_COLOR.0, FG1_COLOR.1, FG1_COLOR.2);
+pub(crate) const FG2: Rgb = Rgb(FG2_COLOR.0, FG2_COLOR.1, FG2_COLOR.2);
 
 struct AppInner {
     client: S3Client,
     bucket: String,
     key_prefix: String,
     hls_dir: PathBuf,
+    /// Tags playlists with ad markers when cue points are subscribed to.
+    cues: Option<Arc<CueTracker>>,
 }
 
 #[derive(Clone)]
@@ -49,18 +57,36 @@ impl Deref for App {
 }
 
 impl App {
-    pub fn new(client: S3Client, bucket: String, key_prefix: String, hls_dir: PathBuf) -> Self {
+    pub fn new(
+        client: S3Client,
+        bucket: String,
+        key_prefix: String,
+        hls_dir: PathBuf,
+        cues: Option<Arc<CueTracker>>,
+    ) -> Self {
         Self {
             inner: Arc::new(AppInner {
                 client,
                 bucket,
                 key_prefix,
                 hls_dir,
+                cues,
             }),
         }
     }
 
     pub async fn handle_segment(&self, path: &PathBuf) -> Result<()> {
+        if let Some(ref cues) = self.cues {
+            let segment = tokio::fs::read(path)
+                .await
+                .with_context(|| format!("Failed to read segment {:?}", path))?;
+            if let (Some(name), Some(start_ms)) = (
+                path.file_name().and_then(|s| s.to_str()),
+                crate::cues::first_pts_ms(&segment),
+            ) {
+                cues.segment_completed(name, start_ms);
+            }
+        }
         self.upload_to_s3(path, true).await
     }
 
@@ -90,17 +116,30 @@ impl App {
         };
         let key = format!("{}{}", self.key_prefix, relative_path.to_string_lossy(),);
         let mut put = self.client.put_object();
+        let mut body = None;
         if path.extension().is_some_and(|s| s.to_str() == Some("m3u8")) {
             // Disable caching for playlists
             put = put.cache_control("no-cache");
+            if let Some(ref cues) = self.cues {
+                let playlist = tokio::fs::read_to_string(path)
+                    .await
+                    .with_context(|| format!("Failed to read playlist {:?}", path))?;
+                body = Some(ByteStream::from(
+                    cues.rewrite_playlist(&playlist).into_bytes(),
+                ));
+            }
         }
+        let body = match body {
+            Some(body) => body,
+            None => ByteStream::from_path(path.clone()).await?,
+        };
         let now = std::time::SystemTime::now();
         put.cache_control("no-cache")
             .bucket(&self.bucket)
             .set_acl(Some(ObjectCannedAcl::PublicRead))
             .set_content_type(Some(mimetype.into()))
             .key(key.clone())
-            .body(ByteStream::from_path(path.clone()).await?)
+            .body(body)
             .send()
             .await
             .with_context(|| format!("Failed to upload file {:?} to S3", path))?;
@@ -138,6 +177,51 @@ impl App {
         Ok(())
     }
 
+    /// Downloads the playlist of the previous recording under the key
+    /// prefix, so ffmpeg appends to it rather than starting a new one.
+    pub async fn restore_playlist(&self) -> Result<()> {
+        let key = format!("{}{}", self.key_prefix, PLAYLIST);
+        let result = self
+            .client
+            .get_object()
+            .bucket(&self.bucket)
+            .key(&key)
+            .send()
+            .await;
+        match result {
+            Ok(object) => {
+                let body = object
+                    .body
+                    .collect()
+                    .await
+                    .with_context(|| format!("Failed to download {}", key))?;
+                tokio::fs::write(self.hls_dir.join(PLAYLIST), body.into_bytes())
+                    .await
+                    .context("Failed to write restored playlist")?;
+                println!(
+                    "{}{}{}{}",
+                    "⏪ Restored playlist for appending • bucket=".color(FG1),
+                    self.bucket.color(FG2),
+                    " • key=".color(FG1),
+                    key.color(FG2),
+                );
+            }
+            Err(e) if e.as_service_error().is_some_and(|e| e.is_no_such_key()) => {
+                println!(
+                    "{}{}",
+                    "⏪ No playlist to append to, starting a new recording • key=".color(FG1),
+                    key.color(FG2),
+                );
+            }
+            Err(e) => {
+                return Err(e).with_context(|| format!("Failed to download {}", key));
+            }
+        }
+        tokio::fs::write(self.hls_dir.join(RESTORED_MARKER), b"")
+            .await
+            .context("Failed to write restored marker")
+    }
+
     pub async fn garbage_collect_old_segments_in_s3(&self, max_age: Duration) -> Result<()> {
         println!(
             "{}{}{}{}",
@@ -278,11 +362,33 @@ pub async fn run(args: RunArgs) -> Result<()> {
         .build();
     let client = S3Client::from_conf(config);
     let hls_dir = PathBuf::from(args.hls_dir);
+    let cues = match (args.nats_url.as_deref(), args.stable_id.clone()) {
+        (Some(url), Some(stable_id)) => {
+            let cues = Arc::new(CueTracker::default());
+            cues.subscribe(
+                url,
+                format!("{}.cue.point", args.nats_subject_prefix),
+                stable_id,
+                cancel.clone(),
+            )
+            .await?;
+            Some(cues)
+        }
+        (Some(_), None) => {
+            eprintln!(
+                "{}",
+                "⚠️  NATS_URL is set without STABLE_ID, ignoring cue points".yellow()
+            );
+            None
+        }
+        _ => None,
+    };
     let app = App::new(
         client,
         args.s3_bucket.clone(),
         args.s3_key_prefix.clone(),
         hls_dir.clone(),
+        cues,
     );
 
     if let Some(retention) = args.delete_old_segments_after.as_deref() {
@@ -309,6 +415,9 @@ pub async fn run(args: RunArgs) -> Result<()> {
             }
         });
     }
+    if args.recording.as_deref() == Some("append") {
+        app.restore_playlist().await?;
+    }
     println!("{}", "🚀 Starting peggy".green());
     let (tx, mut rx) = mpsc::channel::<PathBuf>(1024);
     let watch_dir = hls_dir.clone();
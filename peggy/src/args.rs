@@ -29,4 +29,22 @@ pub(crate) struct RunArgs {
 
     #[arg(long, env = "DELETE_OLD_SEGMENTS_AFTER")]
     pub delete_old_segments_after: Option<String>,
+
+    /// `record` or `append`. Appending recordings restore the playlist
+    /// left in S3 by the previous recording before ffmpeg starts.
+    #[arg(long, env = "HLS_RECORDING")]
+    pub recording: Option<String>,
+
+    /// NATS server strim publishes cue points to. Playlists are uploaded
+    /// without ad markers when unset.
+    #[arg(long, env = "NATS_URL")]
+    pub nats_url: Option<String>,
+
+    #[arg(long, env = "NATS_SUBJECT_PREFIX", default_value = "strim")]
+    pub nats_subject_prefix: String,
+
+    /// Stable id of the stream packaged by this pod. Only its cue points
+    /// are applied.
+    #[arg(long, env = "STABLE_ID")]
+    pub stable_id: Option<String>,
 }
@@ -0,0 +1,466 @@
+use anyhow::{Context, Result};
+use base64::{Engine as _, engine::general_purpose::STANDARD};
+use futures::StreamExt;
+use owo_colors::OwoColorize;
+use serde::Deserialize;
+use std::{
+    collections::{HashMap, HashSet},
+    sync::Mutex,
+};
+use strim_common::cues::{CueKind, CuePoint};
+use tokio_util::sync::CancellationToken;
+
+use crate::app::{FG1, FG2};
+
+const TS_PACKET_SIZE: usize = 188;
+const TS_SYNC_BYTE: u8 = 0x47;
+
+/// The fields of strim's `cue.point` event that peggy needs.
+#[derive(Deserialize)]
+struct CuePointEvent {
+    stable_id: String,
+    #[serde(flatten)]
+    cue: CuePoint,
+}
+
+#[derive(Default)]
+struct CueState {
+    /// Cues received whose segment has not been written yet.
+    pending: Vec<CuePoint>,
+    /// Cues to tag each segment with, keyed by segment file name.
+    placements: HashMap<String, Vec<CuePoint>>,
+    /// Segments listed in the last playlist that was rewritten.
+    listed: HashSet<String>,
+}
+
+/// Turns the ad markers strim receives from the encoder into HLS tags.
+///
+/// A cue is placed on the first segment starting at or after the cue's
+/// stream time, so ad breaks always begin and end on a segment boundary.
+#[derive(Default)]
+pub struct CueTracker {
+    state: Mutex<CueState>,
+}
+
+impl CueTracker {
+    /// Subscribes to the cue points strim publishes for `stable_id`.
+    pub async fn subscribe(
+        self: &std::sync::Arc<Self>,
+        url: &str,
+        subject: String,
+        stable_id: String,
+        cancel: CancellationToken,
+    ) -> Result<()> {
+        let client = async_nats::ConnectOptions::new()
+            .retry_on_initial_connect()
+            .connect(url)
+            .await
+            .with_context(|| format!("Failed to connect to NATS at {}", url))?;
+        let mut subscriber = client
+            .subscribe(subject.clone())
+            .await
+            .with_context(|| format!("Failed to subscribe to {}", subject))?;
+        println!(
+            "{}{}{}{}",
+            "📍 Listening for cue points • subject=".color(FG1),
+            subject.color(FG2),
+            " • stable_id=".color(FG1),
+            stable_id.color(FG2),
+        );
+        let tracker = self.clone();
+        tokio::spawn(async move {
+            loop {
+                let message = tokio::select! {
+                    _ = cancel.cancelled() => break,
+                    message = subscriber.next() => match message {
+                        Some(message) => message,
+                        None => break,
+                    },
+                };
+                let event: CuePointEvent = match serde_json::from_slice(&message.payload) {
+                    Ok(event) => event,
+                    Err(e) => {
+                        eprintln!(
+                            "{}{}",
+                            "❌ Failed to parse cue point • error=".red(),
+                            format!("{:?}", e).red().dimmed(),
+                        );
+                        continue;
+                    }
+                };
+                if event.stable_id != stable_id {
+                    continue;
+                }
+                if let Some(duration) = event
+                    .cue
+                    .duration
+                    .filter(|duration| !(duration.is_finite() && *duration >= 0.0))
+                {
+                    eprintln!(
+                        "{}{}",
+                        "⚠️ Ignoring cue point with invalid duration • duration=".yellow(),
+                        duration.to_string().yellow().dimmed(),
+                    );
+                    continue;
+                }
+                println!(
+                    "{}{}{}{}",
+                    "📍 Cue point received • kind=".color(FG1),
+                    format!("{:?}", event.cue.kind).color(FG2),
+                    " • time_ms=".color(FG1),
+                    event.cue.time_ms.to_string().color(FG2),
+                );
+                tracker.state.lock().unwrap().pending.push(event.cue);
+            }
+        });
+        Ok(())
+    }
+
+    /// Records a finished segment, placing every pending cue that falls at
+    /// or before its start on it.
+    pub fn segment_completed(&self, name: &str, start_ms: u64) {
+        let mut state = self.state.lock().unwrap();
+        let (due, pending): (Vec<_>, Vec<_>) = state
+            .pending
+            .drain(..)
+            .partition(|cue| u64::from(cue.time_ms) <= start_ms);
+        state.pending = pending;
+        if !due.is_empty() {
+            state
+                .placements
+                .entry(name.to_string())
+                .or_default()
+                .extend(due);
+        }
+    }
+
+    /// Inserts the cue tags of each segment ahead of its `#EXTINF`.
+    pub fn rewrite_playlist(&self, playlist: &str) -> String {
+        let mut state = self.state.lock().unwrap();
+        let mut rewritten = String::with_capacity(playlist.len());
+        let mut block: Vec<&str> = Vec::new();
+        let mut listed = HashSet::new();
+        for line in playlist.lines() {
+            if line.is_empty() || line.starts_with('#') {
+                block.push(line);
+                continue;
+            }
+            let name = line.rsplit('/').next().unwrap_or(line);
+            listed.insert(name.to_string());
+            let tags: Vec<String> = match state.placements.get(name) {
+                Some(cues) => {
+                    let start_date = block
+                        .iter()
+                        .find_map(|line| line.strip_prefix("#EXT-X-PROGRAM-DATE-TIME:"));
+                    cues.iter()
+                        .flat_map(|cue| cue_tags(cue, start_date))
+                        .collect()
+                }
+                None => Vec::new(),
+            };
+            let extinf = block
+                .iter()
+                .rposition(|line| line.starts_with("#EXTINF"))
+                .unwrap_or(block.len());
+            let (before, after) = block.split_at(extinf);
+            for line in before
+                .iter()
+                .copied()
+                .chain(tags.iter().map(String::as_str))
+                .chain(after.iter().copied())
+            {
+                rewritten.push_str(line);
+                rewritten.push('\n');
+            }
+            block.clear();
+            rewritten.push_str(line);
+            rewritten.push('\n');
+        }
+        for line in block {
+            rewritten.push_str(line);
+            rewritten.push('\n');
+        }
+
+        // Forget the cues of segments that slid out of the playlist
+        let previous = std::mem::take(&mut state.listed);
+        for name in previous.difference(&listed) {
+            state.placements.remove(name);
+        }
+        state.listed = listed;
+        rewritten
+    }
+}
+
+/// Tags marking `cue` on a segment. `#EXT-X-DATERANGE` is only written
+/// when the segment has a program date time, which the spec requires.
+fn cue_tags(cue: &CuePoint, start_date: Option<&str>) -> Vec<String> {
+    let (suffix, cue_tag) = match cue.kind {
+        CueKind::Out => (
+            "out",
+            match cue.duration {
+                Some(duration) => format!("#EXT-X-CUE-OUT:DURATION={}", duration),
+                None => "#EXT-X-CUE-OUT".to_string(),
+            },
+        ),
+        CueKind::In => ("in", "#EXT-X-CUE-IN".to_string()),
+    };
+    let mut tags = Vec::new();
+    if let Some(start_date) = start_date {
+        // The id comes from the publisher, so anything that could break
+        // out of the quoted attribute is replaced by the cue's time
+        let id = match cue.id {
+            Some(ref id) if is_safe_id(id) => id.clone(),
+            _ => cue.time_ms.to_string(),
+        };
+        let mut daterange = format!(
+            "#EXT-X-DATERANGE:ID=\"{}-{}\",START-DATE=\"{}\"",
+            id, suffix, start_date
+        );
+        if let (CueKind::Out, Some(duration)) = (cue.kind, cue.duration) {
+            daterange.push_str(&format!(",PLANNED-DURATION={}", duration));
+        }
+        if let Some(scte35) = cue.scte35.as_deref().and_then(scte35_hex) {
+            let attribute = match cue.kind {
+                CueKind::Out => "SCTE35-OUT",
+                CueKind::In => "SCTE35-IN",
+            };
+            daterange.push_str(&format!(",{}={}", attribute, scte35));
+        }
+        tags.push(daterange);
+    }
+    tags.push(cue_tag);
+    tags
+}
+
+fn is_safe_id(id: &str) -> bool {
+    !id.is_empty()
+        && id
+            .chars()
+            .all(|c| c.is_ascii_alphanumeric() || matches!(c, '.' | '_' | '-'))
+}
+
+/// Normalizes a `splice_info_section` to the `0x...` form HLS expects.
+fn scte35_hex(scte35: &str) -> Option<String> {
+    if let Some(hex) = scte35
+        .strip_prefix("0x")
+        .or_else(|| scte35.strip_prefix("0X"))
+    {
+        return hex
+            .chars()
+            .all(|c| c.is_ascii_hexdigit())
+            .then(|| format!("0x{}", hex.to_ascii_uppercase()));
+    }
+    let bytes = STANDARD.decode(scte35.trim()).ok()?;
+    Some(bytes.iter().fold(String::from("0x"), |mut hex, byte| {
+        hex.push_str(&format!("{:02X}", byte));
+        hex
+    }))
+}
+
+/// Returns the first presentation timestamp in an MPEG-TS segment, in
+/// milliseconds.
+pub fn first_pts_ms(segment: &[u8]) -> Option<u64> {
+    for packet in segment.chunks_exact(TS_PACKET_SIZE) {
+        if packet[0] != TS_SYNC_BYTE || packet[1] & 0x40 == 0 {
+            continue;
+        }
+        let payload = match (packet[3] >> 4) & 0b11 {
+            0b01 => &packet[4..],
+            0b11 => packet.get(5 + packet[4] as usize..)?,
+            _ => continue,
+        };
+        // PES packets of audio and video streams with a PTS
+        if payload.len() < 14
+            || payload[..3] != [0, 0, 1]
+            || !matches!(payload[3], 0xC0..=0xEF)
+            || payload[7] & 0x80 == 0
+        {
+            continue;
+        }
+        let pts = (u64::from(payload[9] & 0x0E) << 29)
+            | (u64::from(payload[10]) << 22)
+            | (u64::from(payload[11] & 0xFE) << 14)
+            | (u64::from(payload[12]) << 7)
+            | (u64::from(payload[13]) >> 1);
+        return Some(pts / 90);
+    }
+    None
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn cue(kind: CueKind, time_ms: u32) -> CuePoint {
+        CuePoint {
+            kind,
+            id: None,
+            time_ms,
+            duration: None,
+            scte35: None,
+        }
+    }
+
+    /// A transport stream packet of `pid` carrying `payload`, after an
+    /// adaptation field of `adaptation_length` bytes if one is given.
+    fn ts_packet(
+        pid: u16,
+        unit_start: bool,
+        adaptation_length: Option<u8>,
+        payload: &[u8],
+    ) -> Vec<u8> {
+        let mut packet = vec![
+            TS_SYNC_BYTE,
+            (u8::from(unit_start) << 6) | (pid >> 8) as u8,
+            pid as u8,
+        ];
+        match adaptation_length {
+            Some(length) => {
+                packet.push(0x30);
+                packet.push(length);
+                packet.extend(std::iter::repeat_n(0xFF, length as usize));
+            }
+            None => packet.push(0x10),
+        }
+        packet.extend_from_slice(payload);
+        packet.resize(TS_PACKET_SIZE, 0xFF);
+        packet
+    }
+
+    /// The start of a PES packet of `stream_id` with `pts` in 90 kHz units.
+    fn pes_header(stream_id: u8, pts: u64) -> Vec<u8> {
+        vec![
+            0,
+            0,
+            1,
+            stream_id,
+            0,
+            0,
+            0x80,
+            0x80, // PTS only
+            5,
+            0x21 | ((pts >> 29) & 0x0E) as u8,
+            (pts >> 22) as u8,
+            0x01 | ((pts >> 14) & 0xFE) as u8,
+            (pts >> 7) as u8,
+            0x01 | ((pts << 1) & 0xFE) as u8,
+        ]
+    }
+
+    #[test]
+    fn finds_first_pts_after_adaptation_field() {
+        // Sets the top bit of the 33 bit timestamp
+        let pts = (1 << 32) + 900_000;
+        let mut segment = ts_packet(0, true, None, &[0, 0, 0xB0]);
+        // Continuation of a PES packet from the previous segment
+        segment.extend(ts_packet(256, false, None, &pes_header(0xE0, 0)));
+        segment.extend(ts_packet(256, true, Some(7), &pes_header(0xE0, pts)));
+        segment.extend(ts_packet(257, true, None, &pes_header(0xC0, 0)));
+        assert_eq!(first_pts_ms(&segment), Some(pts / 90));
+    }
+
+    #[test]
+    fn skips_packets_without_pes_timestamps() {
+        // Private stream, and an adaptation field filling the packet
+        let mut segment = ts_packet(256, true, None, &pes_header(0xBD, 90_000));
+        segment.extend(ts_packet(256, true, Some(183), &[]));
+        assert_eq!(first_pts_ms(&segment), None);
+        assert_eq!(first_pts_ms(&segment[..100]), None);
+    }
+
+    #[test]
+    fn normalizes_scte35() {
+        assert_eq!(scte35_hex("0xfc302f"), Some("0xFC302F".to_string()));
+        assert_eq!(scte35_hex("0Xfc302f"), Some("0xFC302F".to_string()));
+        assert_eq!(scte35_hex("/DAv"), Some("0xFC302F".to_string()));
+        assert_eq!(scte35_hex("0xfc30\""), None);
+        assert_eq!(scte35_hex("not base64!"), None);
+    }
+
+    #[test]
+    fn replaces_unsafe_ids() {
+        let cue = CuePoint {
+            id: Some("a\",X=\"b".to_string()),
+            ..cue(CueKind::In, 1500)
+        };
+        assert_eq!(
+            cue_tags(&cue, Some("2024-01-01T00:00:00.000Z")),
+            [
+                "#EXT-X-DATERANGE:ID=\"1500-in\",START-DATE=\"2024-01-01T00:00:00.000Z\"",
+                "#EXT-X-CUE-IN",
+            ]
+        );
+        assert_eq!(cue_tags(&cue, None), ["#EXT-X-CUE-IN"]);
+    }
+
+    fn playlist(segments: &[u32]) -> String {
+        let mut playlist = format!(
+            "#EXTM3U\n#EXT-X-VERSION:3\n#EXT-X-TARGETDURATION:4\n#EXT-X-MEDIA-SEQUENCE:{}\n",
+            segments[0]
+        );
+        for segment in segments {
+            playlist.push_str(&format!(
+                "#EXT-X-PROGRAM-DATE-TIME:2024-01-01T00:00:{:02}.000Z\n#EXTINF:4.000000,\nhls/seg{}.ts\n",
+                segment * 4,
+                segment
+            ));
+        }
+        playlist
+    }
+
+    #[test]
+    fn tags_segments_across_boundaries() {
+        let tracker = CueTracker::default();
+        tracker.state.lock().unwrap().pending.extend([
+            CuePoint {
+                id: Some("break-1".to_string()),
+                duration: Some(8.0),
+                scte35: Some("/DAv".to_string()),
+                ..cue(CueKind::Out, 3000)
+            },
+            cue(CueKind::In, 9000),
+        ]);
+        for segment in 0u32..4 {
+            tracker.segment_completed(&format!("seg{}.ts", segment), u64::from(segment) * 4000);
+        }
+
+        // The break starts on the segment after its cue and ends on the
+        // first segment that starts once it is over
+        assert_eq!(
+            tracker.rewrite_playlist(&playlist(&[0, 1, 2, 3])),
+            "#EXTM3U\n\
+             #EXT-X-VERSION:3\n\
+             #EXT-X-TARGETDURATION:4\n\
+             #EXT-X-MEDIA-SEQUENCE:0\n\
+             #EXT-X-PROGRAM-DATE-TIME:2024-01-01T00:00:00.000Z\n\
+             #EXTINF:4.000000,\n\
+             hls/seg0.ts\n\
+             #EXT-X-PROGRAM-DATE-TIME:2024-01-01T00:00:04.000Z\n\
+             #EXT-X-DATERANGE:ID=\"break-1-out\",START-DATE=\"2024-01-01T00:00:04.000Z\",PLANNED-DURATION=8,SCTE35-OUT=0xFC302F\n\
+             #EXT-X-CUE-OUT:DURATION=8\n\
+             #EXTINF:4.000000,\n\
+             hls/seg1.ts\n\
+             #EXT-X-PROGRAM-DATE-TIME:2024-01-01T00:00:08.000Z\n\
+             #EXTINF:4.000000,\n\
+             hls/seg2.ts\n\
+             #EXT-X-PROGRAM-DATE-TIME:2024-01-01T00:00:12.000Z\n\
+             #EXT-X-DATERANGE:ID=\"9000-in\",START-DATE=\"2024-01-01T00:00:12.000Z\"\n\
+             #EXT-X-CUE-IN\n\
+             #EXTINF:4.000000,\n\
+             hls/seg3.ts\n"
+        );
+
+        // Tags stay with their segment as the playlist slides
+        let rewritten = tracker.rewrite_playlist(&playlist(&[2, 3]));
+        assert!(!rewritten.contains("#EXT-X-CUE-OUT"));
+        assert_eq!(rewritten.matches("#EXT-X-CUE-IN").count(), 1);
+        assert!(
+            !tracker
+                .state
+                .lock()
+                .unwrap()
+                .placements
+                .contains_key("seg1.ts")
+        );
+    }
+}
@@ -3,6 +3,7 @@ use clap::Parser;
 
 mod app;
 mod args;
+mod cues;
 
 #[tokio::main]
 async fn main() -> Result<()> {
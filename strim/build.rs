@@ -0,0 +1,8 @@
+fn main() -> Result<(), Box<dyn std::error::Error>> {
+    // SAFETY: build scripts are single threaded
+    unsafe {
+        std::env::set_var("PROTOC", protoc_bin_vendored::protoc_bin_path()?);
+    }
+    tonic_build::configure().compile_protos(&["proto/strim/v1/control.proto"], &["proto"])?;
+    Ok(())
+}
@@ -0,0 +1,151 @@
+//! Feeds the server a mix of raw bytes and well-formed RTMP messages with
+//! fuzzed contents, over several connections that have finished their
+//! handshake.
+
+#![no_main]
+
+use arbitrary::Arbitrary;
+use bytes::Bytes;
+use libfuzzer_sys::fuzz_target;
+use rml_rtmp::sessions::{
+    ClientSession, ClientSessionConfig, ClientSessionResult, PublishRequestType, StreamMetadata,
+};
+use rml_rtmp::time::RtmpTimestamp;
+use std::collections::HashMap;
+use strim::server::CloseReason;
+use strim_fuzz::{CONNECTIONS, Harness};
+
+/// Round trips between a client and the server after each action, enough
+/// for a request to be answered.
+const MAX_ROUND_TRIPS: usize = 8;
+
+#[derive(Arbitrary, Debug)]
+enum Action {
+    /// Bytes that need not be valid RTMP. Desynchronizes the client
+    /// session of the connection.
+    Raw(Vec<u8>),
+    Connect(String),
+    Publish(String, bool),
+    Play(String),
+    Metadata {
+        video_width: Option<u32>,
+        video_height: Option<u32>,
+        video_codec_id: Option<u32>,
+        video_frame_rate: Option<f32>,
+        audio_codec_id: Option<u32>,
+        audio_sample_rate: Option<u32>,
+        audio_channels: Option<u32>,
+    },
+    Video(Vec<u8>, u32),
+    Audio(Vec<u8>, u32),
+    StopPublishing,
+    Close,
+}
+
+fuzz_target!(|steps: Vec<(u8, Action)>| {
+    let mut harness = Harness::new();
+    let mut clients: HashMap<usize, ClientSession> = HashMap::new();
+    for (connection, action) in steps {
+        let connection = connection as usize % CONNECTIONS;
+        if !harness.is_open(connection) {
+            clients.remove(&connection);
+        }
+        if let Action::Raw(bytes) = action {
+            harness.receive(connection, &bytes);
+            continue;
+        }
+        if let Action::Close = action {
+            harness.close(connection, CloseReason::Disconnected);
+            clients.remove(&connection);
+            continue;
+        }
+
+        let mut pending = Vec::new();
+        let client = match clients.entry(connection) {
+            std::collections::hash_map::Entry::Occupied(entry) => entry.into_mut(),
+            std::collections::hash_map::Entry::Vacant(entry) => {
+                let Ok((session, results)) = ClientSession::new(ClientSessionConfig::new()) else {
+                    return;
+                };
+                pending.extend(results);
+                entry.insert(session)
+            }
+        };
+        let results = match action {
+            Action::Connect(app) => client.request_connection(app).map(|result| vec![result]),
+            Action::Publish(stream_key, record) => client
+                .request_publishing(
+                    stream_key,
+                    if record {
+                        PublishRequestType::Record
+                    } else {
+                        PublishRequestType::Live
+                    },
+                )
+                .map(|result| vec![result]),
+            Action::Play(stream_key) => client
+                .request_playback(stream_key)
+                .map(|result| vec![result]),
+            Action::Metadata {
+                video_width,
+                video_height,
+                video_codec_id,
+                video_frame_rate,
+                audio_codec_id,
+                audio_sample_rate,
+                audio_channels,
+            } => {
+                let mut metadata = StreamMetadata::new();
+                metadata.video_width = video_width;
+                metadata.video_height = video_height;
+                metadata.video_codec_id = video_codec_id;
+                metadata.video_frame_rate = video_frame_rate;
+                metadata.audio_codec_id = audio_codec_id;
+                metadata.audio_sample_rate = audio_sample_rate;
+                metadata.audio_channels = audio_channels;
+                client
+                    .publish_metadata(&metadata)
+                    .map(|result| vec![result])
+            }
+            Action::Video(data, timestamp) => client
+                .publish_video_data(Bytes::from(data), RtmpTimestamp::new(timestamp), false)
+                .map(|result| vec![result]),
+            Action::Audio(data, timestamp) => client
+                .publish_audio_data(Bytes::from(data), RtmpTimestamp::new(timestamp), false)
+                .map(|result| vec![result]),
+            Action::StopPublishing => client.stop_publishing(),
+            Action::Raw(_) | Action::Close => unreachable!(),
+        };
+        // The client refusing an action out of order is not under test
+        if let Ok(results) = results {
+            pending.extend(results);
+        }
+
+        let mut closed = false;
+        for _ in 0..MAX_ROUND_TRIPS {
+            let bytes: Vec<u8> = pending
+                .drain(..)
+                .filter_map(|result| match result {
+                    ClientSessionResult::OutboundResponse(packet) => Some(packet.bytes),
+                    _ => None,
+                })
+                .flatten()
+                .collect();
+            if !bytes.is_empty() && !harness.receive(connection, &bytes) {
+                closed = true;
+                break;
+            }
+            let output = harness.take_output(connection);
+            if output.is_empty() {
+                break;
+            }
+            match client.handle_input(&output) {
+                Ok(results) => pending = results,
+                Err(_) => break,
+            }
+        }
+        if closed {
+            clients.remove(&connection);
+        }
+    }
+});
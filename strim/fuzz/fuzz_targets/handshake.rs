@@ -0,0 +1,35 @@
+//! Feeds the handshake of an inbound connection arbitrary reads, handing
+//! whatever follows a completed handshake to the server like the event
+//! loop does.
+
+#![no_main]
+
+use libfuzzer_sys::fuzz_target;
+use strim::connection::{ConnectionHandshake, ReadResult};
+use strim_fuzz::Harness;
+
+fuzz_target!(|reads: Vec<Vec<u8>>| {
+    let mut harness = Harness::new();
+    let mut handshake = ConnectionHandshake::new(true);
+    if handshake.start().is_err() {
+        return;
+    }
+    for bytes in reads {
+        if handshake.is_completed() {
+            if !harness.receive(0, &bytes) {
+                return;
+            }
+            continue;
+        }
+        match handshake.process_bytes(&bytes) {
+            Ok((_, ReadResult::HandshakeCompleted { remaining_bytes })) => {
+                if !harness.receive(0, &remaining_bytes) {
+                    return;
+                }
+            }
+            Ok(_) => {}
+            // The connection is closed
+            Err(_) => return,
+        }
+    }
+});
@@ -0,0 +1,129 @@
+//! Drives a [`Server`] the way the RTMP event loop does, without sockets.
+//! Targets are run from `strim/` with `cargo +nightly fuzz run <target>`.
+
+use std::collections::{HashMap, HashSet};
+use std::net::SocketAddr;
+use std::sync::LazyLock;
+use strim::{
+    admin::LocalStreams,
+    events::EventBus,
+    listeners::ListenerPolicy,
+    routes::{PublishPolicy, RoutingTable},
+    server::{CloseReason, Server, ServerResult},
+    tuning::SessionTuning,
+};
+use tokio::runtime::{EnterGuard, Runtime};
+
+/// Connections the fuzzer can address.
+pub const CONNECTIONS: usize = 4;
+
+/// Tasks the server spawns are never polled, so nothing leaves the process.
+static RUNTIME: LazyLock<Runtime> = LazyLock::new(|| {
+    tokio::runtime::Builder::new_current_thread()
+        .enable_all()
+        .build()
+        .expect("Failed to build runtime")
+});
+
+pub struct Harness {
+    server: Server,
+    open: HashSet<usize>,
+    /// Bytes the server sent to each connection that were not taken yet.
+    outbox: HashMap<usize, Vec<u8>>,
+    _runtime: EnterGuard<'static>,
+}
+
+impl Harness {
+    pub fn new() -> Self {
+        let runtime = RUNTIME.enter();
+        let server = Server::new(
+            None,
+            "127.0.0.1".to_string(),
+            "strim-fuzz".to_string(),
+            String::new(),
+            String::new(),
+            1935,
+            &None,
+            None,
+            EventBus::new("strim-fuzz".to_string()),
+            None,
+            None,
+            LocalStreams::default(),
+            false,
+            RoutingTable::live_only(PublishPolicy::Open),
+            None,
+            None,
+            SessionTuning::default(),
+        );
+        Harness {
+            server,
+            open: HashSet::new(),
+            outbox: HashMap::new(),
+            _runtime: runtime,
+        }
+    }
+
+    /// Hands bytes read from `connection` to the server, accepting the
+    /// connection first if needed. Returns whether it is still open.
+    pub fn receive(&mut self, connection: usize, bytes: &[u8]) -> bool {
+        if self.open.insert(connection) {
+            let peer_addr = SocketAddr::from(([10, 0, 0, connection as u8], 50000));
+            self.server
+                .connection_accepted(connection, peer_addr, ListenerPolicy::ALL);
+        }
+        // Unlike the event loop, panics are not contained, so that the
+        // fuzzer reports them
+        match self.server.bytes_received(connection, bytes) {
+            Ok(results) => self.apply(results),
+            Err(_) => self.close(connection, CloseReason::ProtocolError),
+        }
+        let results = self.server.handle_notifications();
+        self.apply(results);
+        self.open.contains(&connection)
+    }
+
+    /// Bytes the server sent to `connection` since they were last taken.
+    pub fn take_output(&mut self, connection: usize) -> Vec<u8> {
+        self.outbox.remove(&connection).unwrap_or_default()
+    }
+
+    pub fn close(&mut self, connection: usize, reason: CloseReason) {
+        self.outbox.remove(&connection);
+        if self.open.remove(&connection) {
+            self.server.notify_connection_closed(connection, reason);
+        }
+    }
+
+    pub fn is_open(&self, connection: usize) -> bool {
+        self.open.contains(&connection)
+    }
+
+    fn apply(&mut self, results: Vec<ServerResult>) {
+        for result in results {
+            match result {
+                ServerResult::OutboundPacket {
+                    target_connection_id,
+                    packet,
+                } => {
+                    if self.open.contains(&target_connection_id) {
+                        self.outbox
+                            .entry(target_connection_id)
+                            .or_default()
+                            .extend_from_slice(&packet.bytes);
+                    }
+                }
+                ServerResult::DisconnectConnection { connection_id } => {
+                    self.close(connection_id, CloseReason::ServerRequested)
+                }
+                // Outbound connections are not fuzzed
+                ServerResult::PushConnected { .. } | ServerResult::RelayConnected { .. } => {}
+            }
+        }
+    }
+}
+
+impl Default for Harness {
+    fn default() -> Self {
+        Self::new()
+    }
+}
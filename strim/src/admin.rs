@@ -0,0 +1,91 @@
+use axum::{
+    Json, Router,
+    extract::State,
+    middleware,
+    response::{IntoResponse, Response},
+    routing::get,
+};
+use owo_colors::OwoColorize;
+use serde::Serialize;
+use std::{
+    collections::BTreeMap,
+    sync::{Arc, Mutex},
+};
+use strim_common::{access_log, response, shutdown::shutdown_signal};
+use tokio::net::TcpListener;
+
+use crate::{
+    codecs::MediaInfo,
+    colors::{FG1, FG2},
+    registry::StreamRegistry,
+};
+
+/// A stream published to this instance.
+#[derive(Serialize, Clone, Debug)]
+pub struct LocalStream {
+    pub stable_id: String,
+    pub app_name: String,
+    #[serde(flatten)]
+    pub media: MediaInfo,
+}
+
+/// The streams published to this instance, keyed by stable id.
+pub type LocalStreams = Arc<Mutex<BTreeMap<String, LocalStream>>>;
+
+#[derive(Clone)]
+struct AdminState {
+    registry: Option<StreamRegistry>,
+    local_streams: LocalStreams,
+}
+
+/// Spawns the admin HTTP API on `port`.
+pub async fn spawn_admin_server(
+    port: u16,
+    registry: Option<StreamRegistry>,
+    local_streams: LocalStreams,
+) -> anyhow::Result<()> {
+    let app = Router::new()
+        .route("/streams", get(list_streams))
+        .route("/streams/local", get(list_local_streams))
+        .layer(middleware::from_fn(access_log::internal_errors_only))
+        .with_state(AdminState {
+            registry,
+            local_streams,
+        });
+    let listener = TcpListener::bind(format!("0.0.0.0:{}", port)).await?;
+    println!(
+        "{}{}",
+        "🛠️ Starting admin API • port=".color(FG1),
+        port.to_string().color(FG2),
+    );
+    tokio::spawn(async move {
+        axum::serve(listener, app)
+            .with_graceful_shutdown(shutdown_signal())
+            .await
+            .expect("Failed to serve admin API");
+    });
+    Ok(())
+}
+
+/// Lists every live stream in the cluster.
+async fn list_streams(State(state): State<AdminState>) -> Response {
+    let Some(registry) = state.registry else {
+        return response::service_unavailable(anyhow::anyhow!("Stream registry is disabled"));
+    };
+    match registry.list().await {
+        Ok(streams) => Json(streams).into_response(),
+        Err(e) => response::internal_server_error(e),
+    }
+}
+
+/// Lists the streams published to this instance with their codecs.
+async fn list_local_streams(State(state): State<AdminState>) -> Response {
+    let streams: Vec<LocalStream> = state
+        .local_streams
+        .lock()
+        .unwrap()
+        .values()
+        .cloned()
+        .collect();
+    Json(streams).into_response()
+}
@@ -1,4 +1,8 @@
 use clap::{Parser, Subcommand};
+use std::{net::IpAddr, path::PathBuf, time::Duration};
+use strim_common::args::RedisArgs;
+
+use crate::listeners::ListenAddr;
 
 #[derive(Parser, Debug, Clone)]
 #[command(author, version, about, long_about = None)]
@@ -7,34 +11,346 @@ pub struct Cli {
     pub command: Commands,
 }
 
+#[allow(clippy::large_enum_variant)]
 #[derive(Debug, Clone, Subcommand)]
 pub enum Commands {
     /// Run the search service HTTP server
     Server(ServerArgs),
+
+    /// Stream a local FLV file to an RTMP server, looping it
+    Publish(PublishArgs),
+
+    /// Watch a stream, writing it to an FLV file or printing its stats
+    Play(PlayArgs),
+
+    /// Load a server with synthetic publishers and watchers
+    Bench(BenchArgs),
+}
+
+#[derive(Debug, Clone, clap::Args)]
+pub struct BenchArgs {
+    /// Url the publishers publish to, with `{n}` replaced by the index of
+    /// their stream, e.g. `rtmp://localhost:1935/live/bench-{n}/key-{n}`.
+    #[arg(long)]
+    pub publish_url: String,
+
+    /// Url the watchers play from, with `{n}` replaced like in the publish
+    /// url. Defaults to the publish url without its stream key.
+    #[arg(long)]
+    pub play_url: Option<String>,
+
+    /// Streams published, one publisher each.
+    #[arg(long, default_value_t = 1)]
+    pub publishers: usize,
+
+    /// Watchers of each stream.
+    #[arg(long, default_value_t = 1)]
+    pub watchers: usize,
+
+    /// Video bitrate of each stream.
+    #[arg(long, default_value_t = 2500)]
+    pub bitrate_kbps: u32,
+
+    #[arg(long, default_value_t = 30, value_parser = clap::value_parser!(u32).range(1..=240))]
+    pub fps: u32,
+
+    #[arg(long, default_value = "2s", value_parser = humantime::parse_duration)]
+    pub keyframe_interval: Duration,
+
+    /// How long the streams are published for.
+    #[arg(long, default_value = "60s", value_parser = humantime::parse_duration)]
+    pub duration: Duration,
+
+    /// How often progress is printed.
+    #[arg(long, default_value = "5s", value_parser = parse_nonzero_duration)]
+    pub report_interval: Duration,
+}
+
+#[derive(Debug, Clone, clap::Args)]
+pub struct PublishArgs {
+    /// FLV file to stream. Tags are sent at the pace of their timestamps.
+    pub file: PathBuf,
+
+    /// `rtmp://host[:port]/app/stream` to publish to.
+    pub url: String,
+
+    /// Times the file is streamed. Zero loops it until interrupted.
+    #[arg(long, default_value_t = 0)]
+    pub loops: u32,
+}
+
+#[derive(Debug, Clone, clap::Args)]
+pub struct PlayArgs {
+    /// `rtmp://host[:port]/app/stream` to watch.
+    pub url: String,
+
+    /// FLV file the received tags are written to. Stats are printed every
+    /// second instead when unset.
+    #[arg(long, short)]
+    pub output: Option<PathBuf>,
+
+    /// Stop watching after this long. Watches until the stream ends or
+    /// the command is interrupted when unset.
+    #[arg(long, value_parser = humantime::parse_duration)]
+    pub duration: Option<Duration>,
 }
 
 #[derive(Debug, Clone, clap::Args)]
 pub struct ServerArgs {
-    #[arg(long, env = "NODE_ID", required = true)]
-    pub node_id: String,
+    /// Run without Kubernetes, e.g. on a laptop or a bare-metal edge box.
+    /// Publishes are packaged into HLS by processes spawned on this host
+    /// instead of a `Strim` resource, and the pod arguments are optional.
+    #[arg(long, env = "STANDALONE")]
+    pub standalone: bool,
 
-    #[arg(long, env = "POD_IP", required = true)]
-    pub pod_ip: String,
+    #[arg(long, env = "NODE_ID", required_unless_present = "standalone")]
+    pub node_id: Option<String>,
 
-    #[arg(long, env = "POD_NAME", required = true)]
-    pub pod_name: String,
+    /// Address other replicas and HLS pipelines reach this server at.
+    /// Defaults to `127.0.0.1` in standalone mode.
+    #[arg(long, env = "POD_IP", required_unless_present = "standalone")]
+    pub pod_ip: Option<String>,
 
-    #[arg(long, env = "POD_UID", required = true)]
-    pub pod_uid: String,
+    #[arg(long, env = "POD_NAME", required_unless_present = "standalone")]
+    pub pod_name: Option<String>,
 
-    #[arg(long, env = "NAMESPACE", required = true)]
-    pub namespace: String,
+    #[arg(long, env = "POD_UID", required_unless_present = "standalone")]
+    pub pod_uid: Option<String>,
 
+    #[arg(long, env = "NAMESPACE", required_unless_present = "standalone")]
+    pub namespace: Option<String>,
+
+    /// Port other replicas and HLS pipelines pull streams from, which is
+    /// advertised in `internal_url` and the stream registry.
     #[arg(long, env = "PORT", required = true)]
     pub port: u16,
 
+    /// Addresses RTMP connections are accepted on, as `address[=policy]`
+    /// where the policy is `publish`, `play` or `all`, the default. For
+    /// example `[::]:1935=publish,[::]:7080=play` accepts publishes on
+    /// 1935 only and serves pulls on 7080. IPv6 addresses also accept
+    /// IPv4 connections. Defaults to `0.0.0.0:PORT`.
+    #[arg(long = "listen", env = "LISTEN", value_delimiter = ',')]
+    pub listen: Vec<ListenAddr>,
+
+    /// Close connections that have not completed the RTMP handshake
+    /// within this duration. Zero disables the timeout.
+    #[arg(long, env = "HANDSHAKE_TIMEOUT", default_value = "10s", value_parser = humantime::parse_duration)]
+    pub handshake_timeout: Duration,
+
+    /// Close connections that have neither sent nor received any bytes
+    /// within this duration. Zero disables the timeout.
+    #[arg(long, env = "IDLE_TIMEOUT", default_value = "60s", value_parser = humantime::parse_duration)]
+    pub idle_timeout: Duration,
+
+    /// Close publishers that have not sent any audio or video within
+    /// this duration. Zero disables the timeout.
+    #[arg(long, env = "STALL_TIMEOUT", default_value = "15s", value_parser = humantime::parse_duration)]
+    pub stall_timeout: Duration,
+
+    /// How often live stats are written to the status of each published
+    /// `Strim`. Zero disables the stats.
+    #[arg(long, env = "STATS_INTERVAL", default_value = "15s", value_parser = humantime::parse_duration)]
+    pub stats_interval: Duration,
+
+    /// How often `Strim`s created by this pod whose publisher is gone are
+    /// deleted. They are also swept on startup. Zero disables the sweep.
+    #[arg(long, env = "ORPHAN_SWEEP_INTERVAL", default_value = "5m", value_parser = humantime::parse_duration)]
+    pub orphan_sweep_interval: Duration,
+
+    /// How long to keep serving existing connections after a shutdown
+    /// signal before exiting. Should be shorter than the pod's
+    /// termination grace period.
+    #[arg(long, env = "DRAIN_TIMEOUT", default_value = "20s", value_parser = humantime::parse_duration)]
+    pub drain_timeout: Duration,
+
+    #[clap(flatten)]
+    pub rtmp: RtmpArgs,
+
     #[clap(flatten)]
     pub target: Option<TargetArgs>,
+
+    #[clap(flatten)]
+    pub standalone_hls: StandaloneHlsArgs,
+
+    #[clap(flatten)]
+    pub nats: NatsEventArgs,
+
+    #[clap(flatten)]
+    pub webhooks: WebhookArgs,
+
+    #[clap(flatten)]
+    pub registry: RegistryArgs,
+
+    /// YAML file defining the apps this server accepts, such as a mounted
+    /// ConfigMap. Only the `live` app is served when unset.
+    #[arg(long, env = "ROUTES_FILE")]
+    pub routes_file: Option<String>,
+
+    /// Serve watchers of streams published to other replicas by relaying
+    /// the stream from its origin pod, found through the stream registry
+    /// or the `Strim` resources in the namespace.
+    #[arg(long, env = "EDGE_RELAY")]
+    pub edge_relay: bool,
+
+    /// Port of the admin HTTP API. The API is disabled when unset.
+    #[arg(long, env = "ADMIN_PORT")]
+    pub admin_port: Option<u16>,
+
+    /// Port of the gRPC control plane. The control plane is disabled when
+    /// unset.
+    #[arg(long, env = "GRPC_PORT")]
+    pub grpc_port: Option<u16>,
+
+    /// Address the gRPC control plane listens on. Use a loopback or
+    /// internal address to keep it away from other hosts.
+    #[arg(long, env = "GRPC_BIND_ADDRESS", default_value = "0.0.0.0")]
+    pub grpc_bind_address: IpAddr,
+
+    /// Bearer token control plane callers must send in their
+    /// `authorization` header. Required unless the control plane listens
+    /// on a loopback address.
+    #[arg(long, env = "GRPC_TOKEN", hide_env_values = true)]
+    pub grpc_token: Option<String>,
+}
+
+/// Settings of the RTMP sessions and the sockets they run on. Apps can
+/// override the settings of their pushes in the routing table.
+#[derive(Debug, Clone, clap::Args)]
+pub struct RtmpArgs {
+    /// Size of the chunks sent to peers. Larger chunks lower the framing
+    /// overhead of high-bitrate streams.
+    #[arg(long, env = "RTMP_CHUNK_SIZE", default_value_t = 4096, value_parser = clap::value_parser!(u32).range(128..=MAX_CHUNK_SIZE as i64))]
+    pub rtmp_chunk_size: u32,
+
+    /// Bytes a peer may send before it must wait for an acknowledgement.
+    /// Defaults to 1 GiB for clients and 2.5 MB for pushes.
+    #[arg(long, env = "RTMP_WINDOW_ACK_SIZE")]
+    pub rtmp_window_ack_size: Option<u32>,
+
+    /// Bandwidth limit announced to clients, in bytes per second.
+    #[arg(long, env = "RTMP_PEER_BANDWIDTH", default_value_t = 2_500_000)]
+    pub rtmp_peer_bandwidth: u32,
+
+    /// Size of the kernel receive buffer of each socket.
+    #[arg(long, env = "SOCKET_RECEIVE_BUFFER_SIZE", default_value_t = 4 * 1024 * 1024)]
+    pub socket_receive_buffer_size: usize,
+
+    /// Size of the kernel send buffer of each socket.
+    #[arg(long, env = "SOCKET_SEND_BUFFER_SIZE", default_value_t = 4 * 1024 * 1024)]
+    pub socket_send_buffer_size: usize,
+
+    /// Bytes read from a socket at once.
+    #[arg(long, env = "READ_BUFFER_SIZE", default_value_t = 4096, value_parser = clap::builder::RangedU64ValueParser::<usize>::new().range(1..))]
+    pub read_buffer_size: usize,
+
+    /// Flash version pushes announce in their `connect` request.
+    #[arg(long, env = "PUSH_FLASH_VERSION", default_value = "WIN 23,0,0,207")]
+    pub push_flash_version: String,
+
+    /// `tcUrl` pushes send in their `connect` request, which some
+    /// destinations require. `{host}` and `{app}` are replaced with those
+    /// of the destination, e.g. `rtmp://{host}/{app}`. Not sent when unset.
+    #[arg(long, env = "PUSH_TC_URL")]
+    pub push_tc_url: Option<String>,
+}
+
+/// Largest chunk size RTMP allows.
+pub const MAX_CHUNK_SIZE: u32 = 0xFF_FFFF;
+
+fn parse_nonzero_duration(value: &str) -> Result<Duration, String> {
+    match humantime::parse_duration(value) {
+        Ok(duration) if duration.is_zero() => Err("must be greater than zero".to_string()),
+        Ok(duration) => Ok(duration),
+        Err(e) => Err(e.to_string()),
+    }
+}
+
+/// HLS pipelines spawned for each publish in standalone mode, mirroring
+/// the ffmpeg and peggy containers of a `Strim` pod.
+#[derive(Debug, Clone, clap::Args)]
+pub struct StandaloneHlsArgs {
+    /// Segments the stream read from `RTMP_URL` into `HLS_DIR`.
+    #[arg(long, env = "HLS_COMMAND", default_value = "run-ffmpeg-hls.sh")]
+    pub hls_command: String,
+
+    /// Uploads the segments in `HLS_DIR` to S3. AWS credentials are
+    /// passed on from this process's environment.
+    #[arg(long, env = "HLS_UPLOADER_COMMAND", default_value = "strim-peggy")]
+    pub hls_uploader_command: String,
+
+    /// Directory segments are written to before they are uploaded.
+    /// Defaults to a directory under the system temp directory.
+    #[arg(long, env = "HLS_WORK_DIR")]
+    pub hls_work_dir: Option<PathBuf>,
+
+    /// Directory streams are written to when no S3 target is configured.
+    /// Each stream gets a subdirectory named like its key prefix.
+    #[arg(long, env = "HLS_OUTPUT_DIR")]
+    pub hls_output_dir: Option<PathBuf>,
+}
+
+#[derive(Debug, Clone, clap::Args)]
+pub struct RegistryArgs {
+    /// Track live streams in Redis so that every replica knows which pod
+    /// hosts which stream and duplicate publishes are rejected cluster-wide.
+    #[arg(long, env = "STREAM_REGISTRY")]
+    pub stream_registry: bool,
+
+    /// How long a stream stays registered without a heartbeat from the pod
+    /// publishing it.
+    #[arg(long, env = "STREAM_REGISTRY_TTL", default_value = "30s", value_parser = humantime::parse_duration)]
+    pub stream_registry_ttl: Duration,
+
+    #[clap(flatten)]
+    pub redis: RedisArgs,
+}
+
+#[derive(Debug, Clone, clap::Args)]
+pub struct WebhookArgs {
+    /// Endpoints that every lifecycle event is POSTed to as JSON.
+    #[arg(long = "webhook-url", env = "WEBHOOK_URLS", value_delimiter = ',')]
+    pub webhook_urls: Vec<String>,
+
+    /// Shared secret used to sign webhook bodies. The signature is sent in
+    /// the `X-Strim-Signature` header as `sha256=<hex hmac>`.
+    #[arg(long, env = "WEBHOOK_SECRET")]
+    pub webhook_secret: Option<String>,
+
+    /// Delivery attempts per event before it is dropped.
+    #[arg(long, env = "WEBHOOK_MAX_ATTEMPTS", default_value_t = 5)]
+    pub webhook_max_attempts: u32,
+
+    /// Timeout of a single webhook request.
+    #[arg(long, env = "WEBHOOK_TIMEOUT", default_value = "5s", value_parser = humantime::parse_duration)]
+    pub webhook_timeout: Duration,
+
+    /// Events buffered per endpoint before new events are dropped.
+    #[arg(long, env = "WEBHOOK_QUEUE_CAPACITY", default_value_t = 1024)]
+    pub webhook_queue_capacity: usize,
+
+    /// Endpoint consulted before a publish is accepted. A non-2xx response
+    /// or `{"allow": false}` rejects the publish.
+    #[arg(long, env = "ON_PUBLISH_URL")]
+    pub on_publish_url: Option<String>,
+
+    /// Accept publishes when the `on_publish` endpoint is unreachable or
+    /// returns a server error, instead of rejecting them.
+    #[arg(long, env = "ON_PUBLISH_FAIL_OPEN")]
+    pub on_publish_fail_open: bool,
+}
+
+#[derive(Debug, Clone, clap::Args)]
+pub struct NatsEventArgs {
+    /// NATS server that lifecycle events are published to. Event
+    /// publishing is disabled when unset.
+    #[arg(long, env = "NATS_URL")]
+    pub nats_url: Option<String>,
+
+    /// Prefix of the subjects events are published on, e.g. events are
+    /// sent to `strim.publish.started` with the default prefix.
+    #[arg(long, env = "NATS_SUBJECT_PREFIX", default_value = "strim")]
+    pub nats_subject_prefix: String,
 }
 
 #[derive(Debug, Clone, clap::Args)]
@@ -45,7 +361,6 @@ pub struct TargetArgs {
         requires_all = [
             "endpoint",
             "region",
-            "secret",
             "key_prefix"
         ]
     )]
@@ -57,6 +372,8 @@ pub struct TargetArgs {
     #[arg(long, env = "TARGET_REGION")]
     pub region: Option<String>,
 
+    /// Kubernetes secret holding the S3 credentials. Required unless
+    /// running standalone.
     #[arg(long, env = "TARGET_SECRET")]
     pub secret: Option<String>,
 
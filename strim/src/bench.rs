@@ -0,0 +1,445 @@
+use anyhow::{Result, bail};
+use bytes::{BufMut, Bytes, BytesMut};
+use owo_colors::OwoColorize;
+use rml_rtmp::sessions::{ClientSessionConfig, ClientSessionEvent};
+use std::sync::atomic::{AtomicU64, AtomicUsize, Ordering};
+use std::sync::{Arc, Mutex};
+use tokio::sync::watch;
+use tokio::task::JoinSet;
+use tokio::time::{Duration, Instant, sleep, sleep_until};
+
+use crate::args::BenchArgs;
+use crate::client::RtmpClient;
+use crate::codecs;
+use crate::colors::{FG1, FG2};
+use crate::routes::{self, PushDestination};
+
+/// Pause before a publisher or watcher whose connection failed reconnects.
+const RECONNECT_DELAY: Duration = Duration::from_secs(1);
+
+/// SPS and PPS of a 1280x720 H.264 High profile stream, so that the
+/// server sees a plausible sequence header. The frames that follow are
+/// not decodable.
+const SPS: &[u8] = &[
+    0x67, 0x64, 0x00, 0x1f, 0xac, 0xd9, 0x40, 0x50, 0x05, 0xbb, 0x01, 0x10, 0x00, 0x00, 0x03, 0x00,
+    0x10, 0x00, 0x00, 0x03, 0x03, 0x20, 0xf1, 0x83, 0x19, 0x60,
+];
+const PPS: &[u8] = &[0x68, 0xeb, 0xe3, 0xcb, 0x22, 0xc0];
+
+/// FLV video tag header bytes before the NAL unit of a frame: frame type
+/// and codec, AVC packet type and composition time, then the NAL length.
+const FRAME_HEADER_SIZE: usize = 9;
+
+/// Bytes of a frame's NAL unit taken by its header, publish and index.
+const FRAME_LABEL_SIZE: usize = 13;
+
+/// Counters shared by the publishers and watchers.
+#[derive(Default)]
+struct Stats {
+    publishing: AtomicUsize,
+    watching: AtomicUsize,
+    frames_sent: AtomicU64,
+    bytes_sent: AtomicU64,
+    frames_received: AtomicU64,
+    bytes_received: AtomicU64,
+    /// Frames a watcher missed between two it received.
+    frames_dropped: AtomicU64,
+    reconnects: AtomicU64,
+    handshakes: Mutex<Vec<Duration>>,
+    first_frames: Mutex<Vec<Duration>>,
+}
+
+/// Publishes synthetic streams to a server and watches them, reporting
+/// how well the server keeps up.
+pub async fn run(args: BenchArgs) -> Result<()> {
+    if args.publishers == 0 {
+        bail!("At least one publisher is needed");
+    }
+    let play_url = match args.play_url {
+        Some(ref url) => url.clone(),
+        None => match args.publish_url.rsplit_once('/') {
+            Some((url, _)) => url.to_string(),
+            None => bail!("Publish url '{}' has no stream key", args.publish_url),
+        },
+    };
+    // Check the urls before connecting anything
+    routes::parse_stream_url(&args.publish_url.replace("{n}", "0"))?;
+    routes::parse_stream_url(&play_url.replace("{n}", "0"))?;
+
+    println!(
+        "{}{}{}{}{}{}{}{}",
+        "🏋️ Starting bench • publishers=".color(FG1),
+        args.publishers.color(FG2),
+        " • watchers_per_stream=".color(FG1),
+        args.watchers.color(FG2),
+        " • bitrate_kbps=".color(FG1),
+        args.bitrate_kbps.color(FG2),
+        " • duration=".color(FG1),
+        humantime::format_duration(args.duration).color(FG2),
+    );
+    let stats = Arc::new(Stats::default());
+    let started_at = Instant::now();
+    let deadline = started_at + args.duration;
+    let mut tasks = JoinSet::new();
+    for n in 0..args.publishers {
+        let publish_url =
+            routes::parse_stream_url(&args.publish_url.replace("{n}", &n.to_string()))?;
+        let play_url = routes::parse_stream_url(&play_url.replace("{n}", &n.to_string()))?;
+        // Watchers connect once their stream is first published
+        let (published_tx, published_rx) = watch::channel(false);
+        tasks.spawn(publish_stream(
+            publish_url,
+            StreamShape::new(&args),
+            deadline,
+            published_tx,
+            stats.clone(),
+        ));
+        for _ in 0..args.watchers {
+            tasks.spawn(watch_stream(
+                play_url.clone(),
+                deadline,
+                published_rx.clone(),
+                stats.clone(),
+            ));
+        }
+    }
+
+    let mut report =
+        tokio::time::interval_at(started_at + args.report_interval, args.report_interval);
+    let mut last_report = (started_at, 0, 0);
+    loop {
+        tokio::select! {
+            _ = report.tick() => {
+                let now = Instant::now();
+                let sent = stats.bytes_sent.load(Ordering::Relaxed);
+                let received = stats.bytes_received.load(Ordering::Relaxed);
+                let elapsed = now.duration_since(last_report.0);
+                println!(
+                    "{}{}{}{}{}{}{}{}{}{}{}{}",
+                    "📊 Bench progress • publishing=".color(FG1),
+                    stats.publishing.load(Ordering::Relaxed).color(FG2),
+                    " • watching=".color(FG1),
+                    stats.watching.load(Ordering::Relaxed).color(FG2),
+                    " • sent_kbps=".color(FG1),
+                    kbps(sent - last_report.1, elapsed).color(FG2),
+                    " • received_kbps=".color(FG1),
+                    kbps(received - last_report.2, elapsed).color(FG2),
+                    " • dropped=".color(FG1),
+                    stats.frames_dropped.load(Ordering::Relaxed).color(FG2),
+                    " • reconnects=".color(FG1),
+                    stats.reconnects.load(Ordering::Relaxed).color(FG2),
+                );
+                last_report = (now, sent, received);
+            }
+            joined = tasks.join_next() => {
+                if joined.is_none() {
+                    break;
+                }
+            }
+        }
+    }
+
+    let elapsed = started_at.elapsed();
+    let handshakes = stats.handshakes.lock().unwrap().clone();
+    let first_frames = stats.first_frames.lock().unwrap().clone();
+    println!(
+        "{}{}",
+        "🏁 Bench finished • duration=".color(FG1),
+        humantime::format_duration(Duration::from_secs(elapsed.as_secs())).color(FG2),
+    );
+    print_latencies("⏱️ Handshake latency", handshakes);
+    print_latencies("🎬 Time to first frame", first_frames);
+    println!(
+        "{}{}{}{}",
+        "📶 Throughput • sent_kbps=".color(FG1),
+        kbps(stats.bytes_sent.load(Ordering::Relaxed), elapsed).color(FG2),
+        " • received_kbps=".color(FG1),
+        kbps(stats.bytes_received.load(Ordering::Relaxed), elapsed).color(FG2),
+    );
+    println!(
+        "{}{}{}{}{}{}{}{}",
+        "🧮 Frames • sent=".color(FG1),
+        stats.frames_sent.load(Ordering::Relaxed).color(FG2),
+        " • received=".color(FG1),
+        stats.frames_received.load(Ordering::Relaxed).color(FG2),
+        " • dropped=".color(FG1),
+        stats.frames_dropped.load(Ordering::Relaxed).color(FG2),
+        " • reconnects=".color(FG1),
+        stats.reconnects.load(Ordering::Relaxed).color(FG2),
+    );
+    Ok(())
+}
+
+/// Frame sizes and timing of a synthetic stream.
+#[derive(Clone, Copy)]
+struct StreamShape {
+    frame_interval: Duration,
+    frame_size: usize,
+    /// Every this many frames is a keyframe.
+    keyframe_every: u64,
+}
+
+impl StreamShape {
+    fn new(args: &BenchArgs) -> Self {
+        let bytes_per_second = u64::from(args.bitrate_kbps) * 1000 / 8;
+        let frame_interval = Duration::from_secs(1) / args.fps;
+        StreamShape {
+            frame_interval,
+            frame_size: (bytes_per_second / u64::from(args.fps)).max(16) as usize,
+            keyframe_every: (args.keyframe_interval.as_millis() / frame_interval.as_millis().max(1))
+                .max(1) as u64,
+        }
+    }
+
+    /// A frame carrying the publish it was sent in and its index, which
+    /// lets watchers count the frames they missed.
+    fn frame(&self, publish: u32, index: u64) -> Bytes {
+        let keyframe = index.is_multiple_of(self.keyframe_every);
+        let nal_size = self
+            .frame_size
+            .saturating_sub(FRAME_HEADER_SIZE)
+            .max(FRAME_LABEL_SIZE);
+        let mut frame = BytesMut::with_capacity(FRAME_HEADER_SIZE + nal_size);
+        frame.put_slice(&[if keyframe { 0x17 } else { 0x27 }, 1, 0, 0, 0]);
+        frame.put_u32(nal_size as u32);
+        frame.put_u8(if keyframe { 0x65 } else { 0x41 });
+        frame.put_u32(publish);
+        frame.put_u64(index);
+        frame.resize(FRAME_HEADER_SIZE + nal_size, 0);
+        frame.freeze()
+    }
+}
+
+fn sequence_header() -> Bytes {
+    let mut header = BytesMut::new();
+    header.put_slice(&[0x17, 0, 0, 0, 0]);
+    header.put_slice(&[1, SPS[1], SPS[2], SPS[3], 0xff, 0xe1]);
+    header.put_u16(SPS.len() as u16);
+    header.put_slice(SPS);
+    header.put_u8(1);
+    header.put_u16(PPS.len() as u16);
+    header.put_slice(PPS);
+    header.freeze()
+}
+
+/// Publish and index of a frame made by [`StreamShape::frame`].
+fn frame_label(data: &[u8]) -> Option<(u32, u64)> {
+    let label = data.get(FRAME_HEADER_SIZE + 1..FRAME_HEADER_SIZE + FRAME_LABEL_SIZE)?;
+    let publish = u32::from_be_bytes(label[..4].try_into().ok()?);
+    let index = u64::from_be_bytes(label[4..].try_into().ok()?);
+    Some((publish, index))
+}
+
+/// Publishes until the deadline, reconnecting whenever the connection is
+/// lost.
+async fn publish_stream(
+    destination: PushDestination,
+    shape: StreamShape,
+    deadline: Instant,
+    published: watch::Sender<bool>,
+    stats: Arc<Stats>,
+) {
+    let mut next_index = 0;
+    let mut attempts = 0;
+    while Instant::now() < deadline {
+        if attempts > 0 {
+            stats.reconnects.fetch_add(1, Ordering::Relaxed);
+        }
+        attempts += 1;
+        let result = publish_once(
+            &destination,
+            shape,
+            deadline,
+            &published,
+            &stats,
+            attempts,
+            &mut next_index,
+        )
+        .await;
+        match result {
+            Ok(()) => return,
+            Err(e) => {
+                eprintln!(
+                    "{}{}{}{}",
+                    "⚠️ Publisher failed • app=".yellow(),
+                    destination.app.yellow().dimmed(),
+                    " • error=".yellow(),
+                    format!("{:#}", e).yellow().dimmed(),
+                );
+                sleep(RECONNECT_DELAY).await;
+            }
+        }
+    }
+}
+
+async fn publish_once(
+    destination: &PushDestination,
+    shape: StreamShape,
+    deadline: Instant,
+    published: &watch::Sender<bool>,
+    stats: &Stats,
+    publish: u32,
+    next_index: &mut u64,
+) -> Result<()> {
+    let mut client = RtmpClient::connect(destination, ClientSessionConfig::new()).await?;
+    stats
+        .handshakes
+        .lock()
+        .unwrap()
+        .push(client.handshake_duration());
+    client.publish(&destination.stream).await?;
+    stats.publishing.fetch_add(1, Ordering::Relaxed);
+    published.send_replace(true);
+
+    let result = async {
+        client.send_video(sequence_header(), 0).await?;
+        let started_at = Instant::now();
+        // Start on a keyframe so watchers that reconnect can decode
+        *next_index = next_index.next_multiple_of(shape.keyframe_every);
+        let mut frame_at = started_at;
+        while frame_at < deadline {
+            loop {
+                tokio::select! {
+                    _ = sleep_until(frame_at) => break,
+                    readable = client.readable() => {
+                        readable?;
+                        client.read().await?;
+                    }
+                }
+            }
+            let frame = shape.frame(publish, *next_index);
+            let timestamp = frame_at.duration_since(started_at).as_millis() as u32;
+            stats
+                .bytes_sent
+                .fetch_add(frame.len() as u64, Ordering::Relaxed);
+            client.send_video(frame, timestamp).await?;
+            stats.frames_sent.fetch_add(1, Ordering::Relaxed);
+            *next_index += 1;
+            frame_at += shape.frame_interval;
+        }
+        Ok(())
+    }
+    .await;
+    stats.publishing.fetch_sub(1, Ordering::Relaxed);
+    result
+}
+
+/// Watches until the deadline, reconnecting whenever the connection is
+/// lost.
+async fn watch_stream(
+    source: PushDestination,
+    deadline: Instant,
+    mut published: watch::Receiver<bool>,
+    stats: Arc<Stats>,
+) {
+    if tokio::time::timeout_at(deadline, published.wait_for(|published| *published))
+        .await
+        .is_err()
+    {
+        return;
+    }
+    let mut attempts = 0;
+    while Instant::now() < deadline {
+        if attempts > 0 {
+            stats.reconnects.fetch_add(1, Ordering::Relaxed);
+        }
+        attempts += 1;
+        match watch_once(&source, deadline, &stats).await {
+            Ok(()) => return,
+            Err(e) => {
+                eprintln!(
+                    "{}{}{}{}",
+                    "⚠️ Watcher failed • stream=".yellow(),
+                    source.stream.yellow().dimmed(),
+                    " • error=".yellow(),
+                    format!("{:#}", e).yellow().dimmed(),
+                );
+                sleep(RECONNECT_DELAY).await;
+            }
+        }
+    }
+}
+
+async fn watch_once(source: &PushDestination, deadline: Instant, stats: &Stats) -> Result<()> {
+    let connecting_at = Instant::now();
+    let mut client = RtmpClient::connect(source, ClientSessionConfig::new()).await?;
+    stats
+        .handshakes
+        .lock()
+        .unwrap()
+        .push(client.handshake_duration());
+    client.play(&source.stream).await?;
+    stats.watching.fetch_add(1, Ordering::Relaxed);
+
+    let result = async {
+        let mut last_label: Option<(u32, u64)> = None;
+        loop {
+            tokio::select! {
+                _ = sleep_until(deadline) => return Ok(()),
+                readable = client.readable() => {
+                    readable?;
+                    client.read().await?;
+                }
+            }
+            for event in client.take_events() {
+                let ClientSessionEvent::VideoDataReceived { data, .. } = event else {
+                    continue;
+                };
+                if codecs::is_video_sequence_header(&data) {
+                    continue;
+                }
+                let Some((publish, index)) = frame_label(&data) else {
+                    continue;
+                };
+                stats.frames_received.fetch_add(1, Ordering::Relaxed);
+                stats
+                    .bytes_received
+                    .fetch_add(data.len() as u64, Ordering::Relaxed);
+                // A publisher that reconnects skips ahead to the next
+                // keyframe, so gaps only count within one publish
+                match last_label {
+                    None => stats
+                        .first_frames
+                        .lock()
+                        .unwrap()
+                        .push(connecting_at.elapsed()),
+                    Some((last_publish, last)) if last_publish == publish && index > last + 1 => {
+                        stats
+                            .frames_dropped
+                            .fetch_add(index - last - 1, Ordering::Relaxed);
+                    }
+                    Some(_) => {}
+                }
+                last_label = Some((publish, index));
+            }
+        }
+    }
+    .await;
+    stats.watching.fetch_sub(1, Ordering::Relaxed);
+    result
+}
+
+fn kbps(bytes: u64, elapsed: Duration) -> u64 {
+    bytes * 8 / elapsed.as_millis().max(1) as u64
+}
+
+fn print_latencies(label: &str, mut samples: Vec<Duration>) {
+    if samples.is_empty() {
+        println!("{}{}", label.color(FG1), " • samples=0".color(FG1));
+        return;
+    }
+    samples.sort();
+    let percentile = |p: usize| samples[(samples.len() - 1) * p / 100];
+    println!(
+        "{}{}{}{}{}{}{}{}{}",
+        label.color(FG1),
+        " • samples=".color(FG1),
+        samples.len().color(FG2),
+        " • p50=".color(FG1),
+        format!("{:?}", percentile(50)).color(FG2),
+        " • p95=".color(FG1),
+        format!("{:?}", percentile(95)).color(FG2),
+        " • max=".color(FG1),
+        format!("{:?}", percentile(100)).color(FG2),
+    );
+}
@@ -0,0 +1,275 @@
+use anyhow::{Context, Result, bail};
+use bytes::Bytes;
+use rml_rtmp::handshake::{Handshake, HandshakeProcessResult, PeerType};
+use rml_rtmp::rml_amf0::Amf0Value;
+use rml_rtmp::sessions::{
+    ClientSession, ClientSessionConfig, ClientSessionEvent, ClientSessionResult,
+    PublishRequestType, StreamMetadata,
+};
+use rml_rtmp::time::RtmpTimestamp;
+use std::collections::VecDeque;
+use std::time::{Duration, Instant};
+use tokio::io::{AsyncReadExt, AsyncWriteExt};
+use tokio::net::TcpStream;
+
+use crate::routes::PushDestination;
+
+/// How long connecting and each request may take.
+const REQUEST_TIMEOUT: Duration = Duration::from_secs(10);
+
+const READ_BUFFER_SIZE: usize = 64 * 1024;
+
+/// An RTMP client session over a TCP connection, for the command line
+/// tools. Input from the server is only read when asked for, so
+/// publishers should [`RtmpClient::read`] it whenever it is
+/// [`RtmpClient::readable`] to keep up with acknowledgements and pings.
+pub struct RtmpClient {
+    stream: TcpStream,
+    session: ClientSession,
+    events: VecDeque<ClientSessionEvent>,
+    buffer: Vec<u8>,
+    /// Time from opening the connection to completing the handshake.
+    handshake_duration: Duration,
+}
+
+impl RtmpClient {
+    /// Connects to the server of `destination`, completing the handshake
+    /// and the `connect` request for its app.
+    pub async fn connect(
+        destination: &PushDestination,
+        config: ClientSessionConfig,
+    ) -> Result<Self> {
+        tokio::time::timeout(REQUEST_TIMEOUT, Self::connect_inner(destination, config))
+            .await
+            .with_context(|| format!("Timed out connecting to {}", destination.host))?
+    }
+
+    async fn connect_inner(
+        destination: &PushDestination,
+        config: ClientSessionConfig,
+    ) -> Result<Self> {
+        let connecting_at = Instant::now();
+        let mut stream = TcpStream::connect(&destination.host)
+            .await
+            .with_context(|| format!("Failed to connect to {}", destination.host))?;
+        stream.set_nodelay(true)?;
+
+        let mut handshake = Handshake::new(PeerType::Client);
+        let p0_and_p1 = handshake
+            .generate_outbound_p0_and_p1()
+            .map_err(|e| anyhow::anyhow!("Failed to start handshake: {:?}", e))?;
+        stream.write_all(&p0_and_p1).await?;
+        let mut buffer = vec![0; READ_BUFFER_SIZE];
+        let remaining_bytes = loop {
+            let count = stream.read(&mut buffer).await?;
+            if count == 0 {
+                bail!("Server closed the connection during the handshake");
+            }
+            match handshake
+                .process_bytes(&buffer[..count])
+                .map_err(|e| anyhow::anyhow!("Handshake failed: {:?}", e))?
+            {
+                HandshakeProcessResult::InProgress { response_bytes } => {
+                    stream.write_all(&response_bytes).await?;
+                }
+                HandshakeProcessResult::Completed {
+                    response_bytes,
+                    remaining_bytes,
+                } => {
+                    stream.write_all(&response_bytes).await?;
+                    break remaining_bytes;
+                }
+            }
+        };
+
+        let handshake_duration = connecting_at.elapsed();
+
+        let (session, results) = ClientSession::new(config)
+            .map_err(|e| anyhow::anyhow!("Failed to create session: {:?}", e))?;
+        let mut client = RtmpClient {
+            stream,
+            session,
+            events: VecDeque::new(),
+            buffer,
+            handshake_duration,
+        };
+        client.handle_results(results).await?;
+        if !remaining_bytes.is_empty() {
+            client.handle_input(&remaining_bytes).await?;
+        }
+        let result = client
+            .session
+            .request_connection(destination.app.clone())
+            .map_err(|e| anyhow::anyhow!("Failed to request connection: {:?}", e))?;
+        client.handle_results(vec![result]).await?;
+        client
+            .wait_for("connection", |event| {
+                matches!(event, ClientSessionEvent::ConnectionRequestAccepted)
+            })
+            .await?;
+        Ok(client)
+    }
+
+    pub fn handshake_duration(&self) -> Duration {
+        self.handshake_duration
+    }
+
+    pub async fn publish(&mut self, stream_key: &str) -> Result<()> {
+        let result = self
+            .session
+            .request_publishing(stream_key.to_string(), PublishRequestType::Live)
+            .map_err(|e| anyhow::anyhow!("Failed to request publishing: {:?}", e))?;
+        self.handle_results(vec![result]).await?;
+        self.wait_for("publish", |event| {
+            matches!(event, ClientSessionEvent::PublishRequestAccepted)
+        })
+        .await
+    }
+
+    pub async fn play(&mut self, stream_key: &str) -> Result<()> {
+        let result = self
+            .session
+            .request_playback(stream_key.to_string())
+            .map_err(|e| anyhow::anyhow!("Failed to request playback: {:?}", e))?;
+        self.handle_results(vec![result]).await?;
+        self.wait_for("playback", |event| {
+            matches!(event, ClientSessionEvent::PlaybackRequestAccepted)
+        })
+        .await
+    }
+
+    pub async fn send_metadata(&mut self, metadata: &StreamMetadata) -> Result<()> {
+        let result = self
+            .session
+            .publish_metadata(metadata)
+            .map_err(|e| anyhow::anyhow!("Failed to publish metadata: {:?}", e))?;
+        self.handle_results(vec![result]).await
+    }
+
+    pub async fn send_video(&mut self, data: Bytes, timestamp: u32) -> Result<()> {
+        let result = self
+            .session
+            .publish_video_data(data, RtmpTimestamp::new(timestamp), false)
+            .map_err(|e| anyhow::anyhow!("Failed to publish video: {:?}", e))?;
+        self.handle_results(vec![result]).await
+    }
+
+    pub async fn send_audio(&mut self, data: Bytes, timestamp: u32) -> Result<()> {
+        let result = self
+            .session
+            .publish_audio_data(data, RtmpTimestamp::new(timestamp), false)
+            .map_err(|e| anyhow::anyhow!("Failed to publish audio: {:?}", e))?;
+        self.handle_results(vec![result]).await
+    }
+
+    /// Waits until the server sent something. Unlike [`RtmpClient::read`]
+    /// this is cancel safe, so it can be raced against a timer.
+    pub async fn readable(&self) -> Result<()> {
+        self.stream.readable().await?;
+        Ok(())
+    }
+
+    /// Reads bytes from the server and hands them to the session.
+    pub async fn read(&mut self) -> Result<()> {
+        let count = self.stream.read(&mut self.buffer).await?;
+        if count == 0 {
+            bail!("Server closed the connection");
+        }
+        let bytes = self.buffer[..count].to_vec();
+        self.handle_input(&bytes).await
+    }
+
+    /// Events raised by the session since they were last taken.
+    pub fn take_events(&mut self) -> impl Iterator<Item = ClientSessionEvent> + '_ {
+        self.events.drain(..)
+    }
+
+    /// Waits for the next event raised by the session.
+    pub async fn next_event(&mut self) -> Result<ClientSessionEvent> {
+        loop {
+            if let Some(event) = self.events.pop_front() {
+                return Ok(event);
+            }
+            self.read().await?;
+        }
+    }
+
+    async fn handle_input(&mut self, bytes: &[u8]) -> Result<()> {
+        let results = self
+            .session
+            .handle_input(bytes)
+            .map_err(|e| anyhow::anyhow!("Failed to handle input: {:?}", e))?;
+        self.handle_results(results).await
+    }
+
+    async fn handle_results(&mut self, results: Vec<ClientSessionResult>) -> Result<()> {
+        for result in results {
+            match result {
+                ClientSessionResult::OutboundResponse(packet) => {
+                    self.stream.write_all(&packet.bytes).await?;
+                }
+                ClientSessionResult::RaisedEvent(event) => self.events.push_back(event),
+                ClientSessionResult::UnhandleableMessageReceived(_) => {}
+            }
+        }
+        Ok(())
+    }
+
+    /// Waits for the event accepting a request, failing if the server
+    /// rejects it.
+    async fn wait_for(
+        &mut self,
+        request: &str,
+        accepted: impl Fn(&ClientSessionEvent) -> bool,
+    ) -> Result<()> {
+        let wait = async {
+            loop {
+                let event = self.next_event().await?;
+                if accepted(&event) {
+                    return Ok(());
+                }
+                match event {
+                    ClientSessionEvent::ConnectionRequestRejected { description } => {
+                        bail!("Connection rejected: {}", description)
+                    }
+                    ClientSessionEvent::UnhandleableOnStatusCode { code }
+                        if is_rejection(&code) =>
+                    {
+                        bail!("Request rejected: {}", code)
+                    }
+                    // Servers may answer a request with an `_error` the
+                    // session does not tie to it
+                    ClientSessionEvent::UnknownTransactionResultReceived {
+                        additional_values,
+                        ..
+                    } => {
+                        for value in additional_values {
+                            let Amf0Value::Object(mut properties) = value else {
+                                continue;
+                            };
+                            if let Some(Amf0Value::Utf8String(code)) = properties.remove("code")
+                                && is_rejection(&code)
+                            {
+                                match properties.remove("description") {
+                                    Some(Amf0Value::Utf8String(description)) => {
+                                        bail!("Request rejected: {} ({})", code, description)
+                                    }
+                                    _ => bail!("Request rejected: {}", code),
+                                }
+                            }
+                        }
+                    }
+                    _ => {}
+                }
+            }
+        };
+        tokio::time::timeout(REQUEST_TIMEOUT, wait)
+            .await
+            .with_context(|| format!("Timed out waiting for the {} request", request))?
+            .with_context(|| format!("The {} request failed", request))
+    }
+}
+
+fn is_rejection(code: &str) -> bool {
+    code.contains("Rejected") || code.contains("Failed") || code.contains("BadName")
+}
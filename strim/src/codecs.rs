@@ -0,0 +1,759 @@
+use serde::Serialize;
+use std::fmt;
+
+/// FLV codec ids of the video codecs that carry a decoder configuration.
+const FLV_CODEC_AVC: u8 = 7;
+const FLV_CODEC_HEVC: u8 = 12;
+/// FLV sound format of AAC.
+const FLV_SOUND_AAC: u8 = 10;
+/// Enhanced RTMP packet type of a sequence start.
+const EX_PACKET_SEQUENCE_START: u8 = 0;
+const EX_PACKET_CODED_FRAMES: u8 = 1;
+const EX_PACKET_CODED_FRAMES_X: u8 = 3;
+/// FLV frame type of a keyframe.
+const FRAME_TYPE_KEY: u8 = 1;
+/// HEVC NAL unit type of a sequence parameter set.
+const HEVC_NAL_SPS: u8 = 33;
+
+const AAC_SAMPLE_RATES: [u32; 13] = [
+    96000, 88200, 64000, 48000, 44100, 32000, 24000, 22050, 16000, 12000, 11025, 8000, 7350,
+];
+
+/// Details of a video track, parsed from its decoder configuration.
+#[derive(Serialize, Clone, Debug, PartialEq)]
+pub struct VideoInfo {
+    pub codec: &'static str,
+    pub profile: String,
+    pub level: String,
+    pub width: u32,
+    pub height: u32,
+    pub frame_rate: Option<f64>,
+}
+
+impl fmt::Display for VideoInfo {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(
+            f,
+            "{} {}@{} {}x{}",
+            self.codec, self.profile, self.level, self.width, self.height
+        )?;
+        if let Some(frame_rate) = self.frame_rate {
+            write!(f, " {}fps", (frame_rate * 100.0).round() / 100.0)?;
+        }
+        Ok(())
+    }
+}
+
+/// Details of an audio track, parsed from its AudioSpecificConfig.
+#[derive(Serialize, Clone, Debug, PartialEq)]
+pub struct AudioInfo {
+    pub codec: &'static str,
+    pub profile: String,
+    pub sample_rate: u32,
+    pub channels: u8,
+}
+
+impl fmt::Display for AudioInfo {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(
+            f,
+            "{} {} {}kHz",
+            self.codec,
+            self.profile,
+            self.sample_rate as f64 / 1000.0
+        )?;
+        match self.channels {
+            0 => Ok(()),
+            1 => write!(f, " mono"),
+            2 => write!(f, " stereo"),
+            channels => write!(f, " {}ch", channels),
+        }
+    }
+}
+
+/// What a stream's sequence headers say about its tracks.
+#[derive(Serialize, Clone, Debug, Default, PartialEq)]
+pub struct MediaInfo {
+    pub video: Option<VideoInfo>,
+    pub audio: Option<AudioInfo>,
+}
+
+/// Name of the codec a video tag is encoded with.
+pub fn video_codec(data: &[u8]) -> Option<&'static str> {
+    let header = *data.first()?;
+    if header & 0x80 != 0 {
+        return match data.get(1..5)? {
+            b"avc1" => Some("h264"),
+            b"hvc1" => Some("hevc"),
+            b"av01" => Some("av1"),
+            b"vp09" => Some("vp9"),
+            _ => None,
+        };
+    }
+    flv_video_codec(u32::from(header & 0x0F))
+}
+
+/// Name of an FLV video codec id, as found in tags and `onMetaData`.
+pub fn flv_video_codec(codec_id: u32) -> Option<&'static str> {
+    match codec_id {
+        2 => Some("h263"),
+        3 | 6 => Some("screen"),
+        4 | 5 => Some("vp6"),
+        7 => Some("h264"),
+        12 => Some("hevc"),
+        _ => None,
+    }
+}
+
+/// Name of the codec an audio tag is encoded with.
+pub fn audio_codec(data: &[u8]) -> Option<&'static str> {
+    flv_audio_codec(u32::from(*data.first()? >> 4))
+}
+
+/// Name of an FLV sound format, as found in tags and `onMetaData`.
+pub fn flv_audio_codec(sound_format: u32) -> Option<&'static str> {
+    match sound_format {
+        0 | 3 => Some("pcm"),
+        1 => Some("adpcm"),
+        2 | 14 => Some("mp3"),
+        4..=6 => Some("nellymoser"),
+        7 => Some("g711a"),
+        8 => Some("g711u"),
+        10 => Some("aac"),
+        11 => Some("speex"),
+        _ => None,
+    }
+}
+
+pub fn is_video_sequence_header(data: &[u8]) -> bool {
+    match data {
+        [header, ..] if header & 0x80 != 0 => header & 0x0F == EX_PACKET_SEQUENCE_START,
+        [header, 0x00, ..] => {
+            header >> 4 == FRAME_TYPE_KEY && matches!(header & 0x0F, FLV_CODEC_AVC | FLV_CODEC_HEVC)
+        }
+        _ => false,
+    }
+}
+
+pub fn is_video_keyframe(data: &[u8]) -> bool {
+    match data {
+        [header, ..] if header & 0x80 != 0 => {
+            (header >> 4) & 0x07 == FRAME_TYPE_KEY
+                && matches!(
+                    header & 0x0F,
+                    EX_PACKET_CODED_FRAMES | EX_PACKET_CODED_FRAMES_X
+                )
+        }
+        // 0x00 is the sequence header, don't count that
+        [header, packet_type, ..] => {
+            header >> 4 == FRAME_TYPE_KEY
+                && matches!(header & 0x0F, FLV_CODEC_AVC | FLV_CODEC_HEVC)
+                && *packet_type != 0x00
+        }
+        _ => false,
+    }
+}
+
+pub fn is_audio_sequence_header(data: &[u8]) -> bool {
+    data.len() >= 2 && data[0] >> 4 == FLV_SOUND_AAC && data[1] == 0x00
+}
+
+/// Parses the decoder configuration record of an H.264 or HEVC sequence
+/// header, in either the legacy or the Enhanced RTMP tag format.
+pub fn parse_video_sequence_header(data: &[u8]) -> Option<VideoInfo> {
+    if !is_video_sequence_header(data) {
+        return None;
+    }
+    let hevc = if data[0] & 0x80 != 0 {
+        match data.get(1..5)? {
+            b"hvc1" => true,
+            b"avc1" => false,
+            _ => return None,
+        }
+    } else {
+        data[0] & 0x0F == FLV_CODEC_HEVC
+    };
+    // Both formats put the record after five bytes of tag header
+    let record = data.get(5..)?;
+    if hevc {
+        parse_hevc_record(record)
+    } else {
+        parse_avc_record(record)
+    }
+}
+
+/// Parses the AudioSpecificConfig of an AAC sequence header.
+pub fn parse_audio_sequence_header(data: &[u8]) -> Option<AudioInfo> {
+    if !is_audio_sequence_header(data) {
+        return None;
+    }
+    let mut reader = BitReader::new(data.get(2..)?);
+    let object_type = audio_object_type(&mut reader)?;
+    let mut sample_rate = aac_sample_rate(&mut reader)?;
+    let channel_config = reader.bits(4)? as u8;
+    let mut channels = match channel_config {
+        7 => 8,
+        channels => channels,
+    };
+    if matches!(object_type, 5 | 29) {
+        // Explicit SBR signals the output sample rate next
+        sample_rate = aac_sample_rate(&mut reader)?;
+        if object_type == 29 {
+            // Parametric stereo decodes a mono channel to stereo
+            channels = 2;
+        }
+    }
+    let profile = match object_type {
+        1 => "Main".to_string(),
+        2 => "LC".to_string(),
+        3 => "SSR".to_string(),
+        4 => "LTP".to_string(),
+        5 => "HE-AAC".to_string(),
+        23 => "LD".to_string(),
+        29 => "HE-AACv2".to_string(),
+        39 => "ELD".to_string(),
+        object_type => format!("AOT {}", object_type),
+    };
+    Some(AudioInfo {
+        codec: "aac",
+        profile,
+        sample_rate,
+        channels,
+    })
+}
+
+fn audio_object_type(reader: &mut BitReader) -> Option<u32> {
+    match reader.bits(5)? {
+        31 => Some(32 + reader.bits(6)?),
+        object_type => Some(object_type),
+    }
+}
+
+fn aac_sample_rate(reader: &mut BitReader) -> Option<u32> {
+    match reader.bits(4)? {
+        15 => reader.bits(24),
+        index => AAC_SAMPLE_RATES.get(index as usize).copied(),
+    }
+}
+
+/// Parses an AVCDecoderConfigurationRecord and its first SPS.
+fn parse_avc_record(record: &[u8]) -> Option<VideoInfo> {
+    let sps_count = record.get(5)? & 0x1F;
+    if sps_count == 0 {
+        return None;
+    }
+    let length = u16::from_be_bytes([*record.get(6)?, *record.get(7)?]) as usize;
+    let sps = record.get(8..8 + length)?;
+    // Skip the NAL unit header
+    parse_avc_sps(&unescape(sps.get(1..)?))
+}
+
+fn parse_avc_sps(sps: &[u8]) -> Option<VideoInfo> {
+    let mut reader = BitReader::new(sps);
+    let profile_idc = reader.bits(8)?;
+    let constraints = reader.bits(8)?;
+    let level_idc = reader.bits(8)?;
+    reader.ue()?; // seq_parameter_set_id
+
+    let mut chroma_format_idc = 1;
+    let mut separate_colour_plane = false;
+    if matches!(
+        profile_idc,
+        100 | 110 | 122 | 244 | 44 | 83 | 86 | 118 | 128 | 138 | 139 | 134 | 135
+    ) {
+        chroma_format_idc = reader.ue()?;
+        if chroma_format_idc == 3 {
+            separate_colour_plane = reader.bit()?;
+        }
+        reader.ue()?; // bit_depth_luma_minus8
+        reader.ue()?; // bit_depth_chroma_minus8
+        reader.bit()?; // qpprime_y_zero_transform_bypass_flag
+        if reader.bit()? {
+            let lists = if chroma_format_idc == 3 { 12 } else { 8 };
+            for index in 0..lists {
+                if reader.bit()? {
+                    skip_scaling_list(&mut reader, if index < 6 { 16 } else { 64 })?;
+                }
+            }
+        }
+    }
+
+    reader.ue()?; // log2_max_frame_num_minus4
+    match reader.ue()? {
+        0 => {
+            reader.ue()?; // log2_max_pic_order_cnt_lsb_minus4
+        }
+        1 => {
+            reader.bit()?; // delta_pic_order_always_zero_flag
+            reader.se()?; // offset_for_non_ref_pic
+            reader.se()?; // offset_for_top_to_bottom_field
+            for _ in 0..reader.ue()? {
+                reader.se()?;
+            }
+        }
+        _ => {}
+    }
+    reader.ue()?; // max_num_ref_frames
+    reader.bit()?; // gaps_in_frame_num_value_allowed_flag
+    let width_in_mbs = reader.ue()?.checked_add(1)?;
+    let height_in_map_units = reader.ue()?.checked_add(1)?;
+    let frame_mbs_only = reader.bit()?;
+    if !frame_mbs_only {
+        reader.bit()?; // mb_adaptive_frame_field_flag
+    }
+    reader.bit()?; // direct_8x8_inference_flag
+
+    let field_factor = if frame_mbs_only { 1 } else { 2 };
+    let (crop_unit_x, crop_unit_y) = match (separate_colour_plane, chroma_format_idc) {
+        (true, _) | (false, 0) => (1, field_factor),
+        (false, 1) => (2, 2 * field_factor),
+        (false, 2) => (2, field_factor),
+        _ => (1, field_factor),
+    };
+    // The sizes come from the publisher, so a crafted SPS must not
+    // overflow them
+    let mut width = width_in_mbs.checked_mul(16)?;
+    let mut height = height_in_map_units.checked_mul(16 * field_factor)?;
+    if reader.bit()? {
+        let (left, right, top, bottom) = (reader.ue()?, reader.ue()?, reader.ue()?, reader.ue()?);
+        width = width.saturating_sub(left.checked_add(right)?.checked_mul(crop_unit_x)?);
+        height = height.saturating_sub(top.checked_add(bottom)?.checked_mul(crop_unit_y)?);
+    }
+
+    let frame_rate = if reader.bit().unwrap_or(false) {
+        avc_vui_frame_rate(&mut reader)
+    } else {
+        None
+    };
+
+    let profile = match profile_idc {
+        66 if constraints & 0x40 != 0 => "Constrained Baseline".to_string(),
+        66 => "Baseline".to_string(),
+        77 => "Main".to_string(),
+        88 => "Extended".to_string(),
+        100 => "High".to_string(),
+        110 => "High 10".to_string(),
+        122 => "High 4:2:2".to_string(),
+        244 => "High 4:4:4".to_string(),
+        44 => "CAVLC 4:4:4".to_string(),
+        profile_idc => format!("Profile {}", profile_idc),
+    };
+    Some(VideoInfo {
+        codec: "h264",
+        profile,
+        level: format!("{}.{}", level_idc / 10, level_idc % 10),
+        width,
+        height,
+        frame_rate,
+    })
+}
+
+/// Reads the VUI up to its timing info, which is all that is needed for
+/// the frame rate.
+fn avc_vui_frame_rate(reader: &mut BitReader) -> Option<f64> {
+    if reader.bit()? {
+        // aspect_ratio_info_present_flag
+        if reader.bits(8)? == 255 {
+            reader.skip(32)?; // sar_width, sar_height
+        }
+    }
+    if reader.bit()? {
+        reader.skip(1)?; // overscan_appropriate_flag
+    }
+    if reader.bit()? {
+        // video_signal_type_present_flag
+        reader.skip(4)?;
+        if reader.bit()? {
+            reader.skip(24)?; // colour description
+        }
+    }
+    if reader.bit()? {
+        reader.ue()?; // chroma_sample_loc_type_top_field
+        reader.ue()?; // chroma_sample_loc_type_bottom_field
+    }
+    if !reader.bit()? {
+        return None;
+    }
+    let num_units_in_tick = reader.bits(32)?;
+    let time_scale = reader.bits(32)?;
+    if num_units_in_tick == 0 {
+        return None;
+    }
+    Some(time_scale as f64 / (2.0 * num_units_in_tick as f64))
+}
+
+fn skip_scaling_list(reader: &mut BitReader, size: usize) -> Option<()> {
+    let mut last_scale: i32 = 8;
+    let mut next_scale = 8;
+    for _ in 0..size {
+        if next_scale != 0 {
+            let delta = reader.se()?;
+            next_scale = last_scale.checked_add(delta)?.rem_euclid(256);
+        }
+        if next_scale != 0 {
+            last_scale = next_scale;
+        }
+    }
+    Some(())
+}
+
+/// Parses an HEVCDecoderConfigurationRecord and its first SPS.
+fn parse_hevc_record(record: &[u8]) -> Option<VideoInfo> {
+    let profile_idc = record.get(1)? & 0x1F;
+    let high_tier = record.get(1)? & 0x20 != 0;
+    let level_idc = *record.get(12)? as u32;
+    let average_frame_rate = u16::from_be_bytes([*record.get(19)?, *record.get(20)?]);
+    let array_count = *record.get(22)?;
+
+    let mut offset = 23;
+    let mut sps = None;
+    for _ in 0..array_count {
+        let nal_type = record.get(offset)? & 0x3F;
+        let nal_count = u16::from_be_bytes([*record.get(offset + 1)?, *record.get(offset + 2)?]);
+        offset += 3;
+        for _ in 0..nal_count {
+            let length =
+                u16::from_be_bytes([*record.get(offset)?, *record.get(offset + 1)?]) as usize;
+            let nal = record.get(offset + 2..offset + 2 + length)?;
+            if nal_type == HEVC_NAL_SPS && sps.is_none() {
+                sps = Some(nal);
+            }
+            offset += 2 + length;
+        }
+    }
+    // Skip the two byte NAL unit header
+    let (width, height) = parse_hevc_sps_dimensions(&unescape(sps?.get(2..)?))?;
+
+    let profile = match profile_idc {
+        1 => "Main".to_string(),
+        2 => "Main 10".to_string(),
+        3 => "Main Still Picture".to_string(),
+        4 => "Range Extensions".to_string(),
+        profile_idc => format!("Profile {}", profile_idc),
+    };
+    Some(VideoInfo {
+        codec: "hevc",
+        profile: if high_tier {
+            format!("{} High tier", profile)
+        } else {
+            profile
+        },
+        level: format!("{}.{}", level_idc / 30, level_idc % 30 / 3),
+        width,
+        height,
+        frame_rate: (average_frame_rate != 0).then(|| average_frame_rate as f64 / 256.0),
+    })
+}
+
+fn parse_hevc_sps_dimensions(sps: &[u8]) -> Option<(u32, u32)> {
+    let mut reader = BitReader::new(sps);
+    reader.skip(4)?; // sps_video_parameter_set_id
+    let max_sub_layers_minus1 = reader.bits(3)? as usize;
+    reader.skip(1)?; // sps_temporal_id_nesting_flag
+
+    // profile_tier_level: the general profile and level, then the
+    // optional ones of each sub-layer
+    reader.skip(96)?;
+    let mut sub_layers = Vec::with_capacity(max_sub_layers_minus1);
+    for _ in 0..max_sub_layers_minus1 {
+        sub_layers.push((reader.bit()?, reader.bit()?));
+    }
+    if max_sub_layers_minus1 > 0 {
+        reader.skip(2 * (8 - max_sub_layers_minus1))?;
+    }
+    for (profile_present, level_present) in sub_layers {
+        if profile_present {
+            reader.skip(88)?;
+        }
+        if level_present {
+            reader.skip(8)?;
+        }
+    }
+
+    reader.ue()?; // sps_seq_parameter_set_id
+    let chroma_format_idc = reader.ue()?;
+    let separate_colour_plane = chroma_format_idc == 3 && reader.bit()?;
+    let mut width = reader.ue()?;
+    let mut height = reader.ue()?;
+    if reader.bit()? {
+        let (sub_width, sub_height) = match (separate_colour_plane, chroma_format_idc) {
+            (false, 1) => (2, 2),
+            (false, 2) => (2, 1),
+            _ => (1, 1),
+        };
+        let (left, right, top, bottom) = (reader.ue()?, reader.ue()?, reader.ue()?, reader.ue()?);
+        width = width.saturating_sub(left.checked_add(right)?.checked_mul(sub_width)?);
+        height = height.saturating_sub(top.checked_add(bottom)?.checked_mul(sub_height)?);
+    }
+    Some((width, height))
+}
+
+/// Removes the emulation prevention bytes of a NAL unit.
+fn unescape(nal: &[u8]) -> Vec<u8> {
+    let mut rbsp = Vec::with_capacity(nal.len());
+    let mut zeros = 0;
+    for &byte in nal {
+        if zeros >= 2 && byte == 0x03 {
+            zeros = 0;
+            continue;
+        }
+        zeros = if byte == 0 { zeros + 1 } else { 0 };
+        rbsp.push(byte);
+    }
+    rbsp
+}
+
+/// Reads big-endian bit fields and Exp-Golomb codes.
+struct BitReader<'a> {
+    data: &'a [u8],
+    position: usize,
+}
+
+impl<'a> BitReader<'a> {
+    fn new(data: &'a [u8]) -> Self {
+        Self { data, position: 0 }
+    }
+
+    fn bit(&mut self) -> Option<bool> {
+        let byte = self.data.get(self.position / 8)?;
+        let bit = (byte >> (7 - self.position % 8)) & 1;
+        self.position += 1;
+        Some(bit == 1)
+    }
+
+    fn bits(&mut self, count: u32) -> Option<u32> {
+        let mut value = 0u32;
+        for _ in 0..count {
+            value = (value << 1) | self.bit()? as u32;
+        }
+        Some(value)
+    }
+
+    fn skip(&mut self, count: usize) -> Option<()> {
+        if self.position + count > self.data.len() * 8 {
+            return None;
+        }
+        self.position += count;
+        Some(())
+    }
+
+    fn ue(&mut self) -> Option<u32> {
+        let mut leading_zeros = 0;
+        while !self.bit()? {
+            leading_zeros += 1;
+            if leading_zeros > 31 {
+                return None;
+            }
+        }
+        Some((1u32 << leading_zeros) - 1 + self.bits(leading_zeros)?)
+    }
+
+    fn se(&mut self) -> Option<i32> {
+        let value = self.ue()?;
+        Some(if value % 2 == 1 {
+            value.div_ceil(2) as i32
+        } else {
+            -((value / 2) as i32)
+        })
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    /// SPS of a 1280x720 H.264 High profile stream at level 3.1 and
+    /// 25 fps, as the bench sends it.
+    const SPS_720P: &[u8] = &[
+        0x67, 0x64, 0x00, 0x1f, 0xac, 0xd9, 0x40, 0x50, 0x05, 0xbb, 0x01, 0x10, 0x00, 0x00, 0x03,
+        0x00, 0x10, 0x00, 0x00, 0x03, 0x03, 0x20, 0xf1, 0x83, 0x19, 0x60,
+    ];
+    const PPS_720P: &[u8] = &[0x68, 0xeb, 0xe3, 0xcb, 0x22, 0xc0];
+
+    /// Writes bit fields and Exp-Golomb codes, to craft parameter sets.
+    #[derive(Default)]
+    struct BitWriter {
+        bytes: Vec<u8>,
+        position: usize,
+    }
+
+    impl BitWriter {
+        fn bit(&mut self, bit: bool) -> &mut Self {
+            if self.position.is_multiple_of(8) {
+                self.bytes.push(0);
+            }
+            if bit {
+                *self.bytes.last_mut().unwrap() |= 0x80 >> (self.position % 8);
+            }
+            self.position += 1;
+            self
+        }
+
+        fn bits(&mut self, count: u32, value: u64) -> &mut Self {
+            for shift in (0..count).rev() {
+                self.bit((value >> shift) & 1 == 1);
+            }
+            self
+        }
+
+        fn ue(&mut self, value: u32) -> &mut Self {
+            let value = u64::from(value) + 1;
+            let length = 64 - value.leading_zeros();
+            self.bits(length - 1, 0).bits(length, value)
+        }
+
+        /// Pads with the RBSP stop bit and zeros up to a byte boundary.
+        fn finish(&mut self) -> Vec<u8> {
+            self.bit(true);
+            self.bytes.clone()
+        }
+    }
+
+    fn avc_sequence_header(sps: &[u8], pps: &[u8]) -> Vec<u8> {
+        let mut header = vec![0x17, 0, 0, 0, 0, 1, sps[1], sps[2], sps[3], 0xff, 0xe1];
+        header.extend_from_slice(&(sps.len() as u16).to_be_bytes());
+        header.extend_from_slice(sps);
+        header.push(1);
+        header.extend_from_slice(&(pps.len() as u16).to_be_bytes());
+        header.extend_from_slice(pps);
+        header
+    }
+
+    /// A Baseline SPS with the given size fields and no VUI.
+    fn baseline_sps(width_in_mbs_minus1: u32, crop: Option<[u32; 4]>) -> Vec<u8> {
+        let mut writer = BitWriter::default();
+        writer
+            .bits(8, 66) // profile_idc
+            .bits(8, 0) // constraint flags
+            .bits(8, 30) // level_idc
+            .ue(0) // seq_parameter_set_id
+            .ue(0) // log2_max_frame_num_minus4
+            .ue(0) // pic_order_cnt_type
+            .ue(0) // log2_max_pic_order_cnt_lsb_minus4
+            .ue(1) // max_num_ref_frames
+            .bit(false) // gaps_in_frame_num_value_allowed_flag
+            .ue(width_in_mbs_minus1)
+            .ue(44) // pic_height_in_map_units_minus1
+            .bit(true) // frame_mbs_only_flag
+            .bit(true); // direct_8x8_inference_flag
+        match crop {
+            Some(offsets) => {
+                writer.bit(true);
+                for offset in offsets {
+                    writer.ue(offset);
+                }
+            }
+            None => {
+                writer.bit(false);
+            }
+        }
+        writer.bit(false); // vui_parameters_present_flag
+        let mut sps = vec![0x67];
+        sps.extend(writer.finish());
+        sps
+    }
+
+    /// The SPS of an HEVC stream with the given size fields, starting
+    /// after its NAL unit header.
+    fn hevc_sps(width: u32, height: u32, crop: [u32; 4]) -> Vec<u8> {
+        let mut writer = BitWriter::default();
+        writer
+            .bits(4, 0) // sps_video_parameter_set_id
+            .bits(3, 0) // sps_max_sub_layers_minus1
+            .bit(true) // sps_temporal_id_nesting_flag
+            .bits(48, 0)
+            .bits(48, 0) // profile_tier_level
+            .ue(0) // sps_seq_parameter_set_id
+            .ue(1) // chroma_format_idc
+            .ue(width)
+            .ue(height)
+            .bit(true); // conformance_window_flag
+        for offset in crop {
+            writer.ue(offset);
+        }
+        writer.finish()
+    }
+
+    #[test]
+    fn parses_avc_sequence_header() {
+        let info = parse_video_sequence_header(&avc_sequence_header(SPS_720P, PPS_720P)).unwrap();
+        assert_eq!(info.codec, "h264");
+        assert_eq!(info.profile, "High");
+        assert_eq!(info.level, "3.1");
+        assert_eq!((info.width, info.height), (1280, 720));
+        assert_eq!(info.frame_rate, Some(25.0));
+    }
+
+    #[test]
+    fn applies_avc_cropping() {
+        // 1920x1088 coded, cropped by 8 lines at the bottom
+        let sps = baseline_sps(119, Some([0, 0, 0, 4]));
+        let info = parse_video_sequence_header(&avc_sequence_header(&sps, PPS_720P)).unwrap();
+        assert_eq!(info.profile, "Baseline");
+        assert_eq!((info.width, info.height), (1920, 712));
+    }
+
+    #[test]
+    fn rejects_overflowing_avc_sizes() {
+        let sps = baseline_sps(u32::MAX / 8, None);
+        assert_eq!(
+            parse_video_sequence_header(&avc_sequence_header(&sps, PPS_720P)),
+            None
+        );
+        let sps = baseline_sps(119, Some([u32::MAX - 1, u32::MAX - 1, 0, 0]));
+        assert_eq!(
+            parse_video_sequence_header(&avc_sequence_header(&sps, PPS_720P)),
+            None
+        );
+    }
+
+    #[test]
+    fn rejects_overflowing_scaling_list() {
+        let mut writer = BitWriter::default();
+        writer
+            .bits(8, 100) // profile_idc
+            .bits(8, 0) // constraint flags
+            .bits(8, 40) // level_idc
+            .ue(0) // seq_parameter_set_id
+            .ue(1) // chroma_format_idc
+            .ue(0) // bit_depth_luma_minus8
+            .ue(0) // bit_depth_chroma_minus8
+            .bit(false) // qpprime_y_zero_transform_bypass_flag
+            .bit(true) // seq_scaling_matrix_present_flag
+            .bit(true) // seq_scaling_list_present_flag
+            .ue(u32::MAX - 2); // delta_scale of i32::MAX
+        let mut sps = vec![0x67];
+        sps.extend(writer.finish());
+        assert_eq!(
+            parse_video_sequence_header(&avc_sequence_header(&sps, PPS_720P)),
+            None
+        );
+    }
+
+    #[test]
+    fn parses_hevc_sps_dimensions() {
+        let sps = hevc_sps(1920, 1088, [0, 0, 0, 4]);
+        assert_eq!(parse_hevc_sps_dimensions(&sps), Some((1920, 1080)));
+    }
+
+    #[test]
+    fn rejects_overflowing_hevc_cropping() {
+        let sps = hevc_sps(1920, 1088, [u32::MAX - 1, u32::MAX - 1, 0, 0]);
+        assert_eq!(parse_hevc_sps_dimensions(&sps), None);
+    }
+
+    #[test]
+    fn parses_aac_sequence_header() {
+        // AAC LC, 44.1 kHz, stereo
+        let info = parse_audio_sequence_header(&[0xAF, 0x00, 0x12, 0x10]).unwrap();
+        assert_eq!(info.to_string(), "aac LC 44.1kHz stereo");
+    }
+
+    #[test]
+    fn removes_emulation_prevention_bytes() {
+        assert_eq!(
+            unescape(&[0x00, 0x00, 0x03, 0x01, 0x00, 0x00, 0x03]),
+            [0x00, 0x00, 0x01, 0x00, 0x00]
+        );
+    }
+}
@@ -8,25 +8,39 @@ use std::fs;
 use std::fs::File;
 use std::io;
 use std::io::{Read, Write};
-use std::time::{SystemTime, UNIX_EPOCH};
+use std::time::{Duration, Instant, SystemTime, UNIX_EPOCH};
 
 use crate::colors::{FG1, FG2};
 
-const BUFFER_SIZE: usize = 4096;
-const SOCKET_RECEIVE_BUFFER_SIZE: usize = 4 * 1024 * 1024;
-const SOCKET_SEND_BUFFER_SIZE: usize = 4 * 1024 * 1024;
+/// Buffer sizes of a connection's socket.
+#[derive(Debug, Clone, Copy)]
+pub struct SocketOptions {
+    pub receive_buffer_size: usize,
+    pub send_buffer_size: usize,
+    /// Bytes read from the socket at once.
+    pub read_buffer_size: usize,
+}
+
+impl Default for SocketOptions {
+    fn default() -> Self {
+        Self {
+            receive_buffer_size: 4 * 1024 * 1024,
+            send_buffer_size: 4 * 1024 * 1024,
+            read_buffer_size: 4096,
+        }
+    }
+}
 
 pub enum ReadResult {
     HandshakingInProgress,
     NoBytesReceived,
     BytesReceived {
-        buffer: [u8; BUFFER_SIZE],
-        byte_count: usize,
+        bytes: Vec<u8>,
     },
 
+    /// The handshake finished. Holds the bytes received after it.
     HandshakeCompleted {
-        buffer: [u8; BUFFER_SIZE],
-        byte_count: usize,
+        remaining_bytes: Vec<u8>,
     },
 }
 
@@ -53,17 +67,103 @@ struct DebugLogFiles {
     rtmp_output_file: File,
 }
 
+impl DebugLogFiles {
+    fn create(count: usize) -> io::Result<Self> {
+        fs::create_dir_all("logs")?;
+
+        let seconds = SystemTime::now()
+            .duration_since(UNIX_EPOCH)
+            .unwrap_or_default()
+            .as_secs();
+        let rtmp_input_name = format!("logs/{}-{}.rtmp.input.log", seconds, count);
+        let rtmp_output_name = format!("logs/{}-{}.rtmp.output.log", seconds, count);
+
+        Ok(DebugLogFiles {
+            rtmp_input_file: File::create(rtmp_input_name)?,
+            rtmp_output_file: File::create(rtmp_output_name)?,
+        })
+    }
+}
+
+/// The RTMP handshake of a connection, kept apart from its socket so
+/// that the fuzz targets can drive it without one.
+pub struct ConnectionHandshake {
+    handshake: Handshake,
+    completed: bool,
+}
+
+impl ConnectionHandshake {
+    pub fn new(is_inbound_connection: bool) -> Self {
+        let handshake = match is_inbound_connection {
+            true => Handshake::new(PeerType::Server),
+            false => Handshake::new(PeerType::Client),
+        };
+        ConnectionHandshake {
+            handshake,
+            completed: false,
+        }
+    }
+
+    /// The bytes that open the handshake, sent as soon as the connection
+    /// is made.
+    pub fn start(&mut self) -> io::Result<Vec<u8>> {
+        self.handshake
+            .generate_outbound_p0_and_p1()
+            .map_err(|error| io::Error::other(format!("{:?}", error)))
+    }
+
+    pub fn is_completed(&self) -> bool {
+        self.completed
+    }
+
+    /// Processes bytes read before the handshake completed. Returns the
+    /// bytes to answer with along with the outcome of the read.
+    pub fn process_bytes(
+        &mut self,
+        bytes: &[u8],
+    ) -> Result<(Vec<u8>, ReadResult), ConnectionError> {
+        let result = match self.handshake.process_bytes(bytes) {
+            Ok(result) => result,
+            Err(error) => {
+                println!(
+                    "{}{}",
+                    "💥 Handshake error: ".red(),
+                    format!("{:?}", error).red()
+                );
+                return Err(ConnectionError::SocketClosed);
+            }
+        };
+        match result {
+            HandshakeProcessResult::InProgress { response_bytes } => {
+                Ok((response_bytes, ReadResult::HandshakingInProgress))
+            }
+            HandshakeProcessResult::Completed {
+                response_bytes,
+                remaining_bytes,
+            } => {
+                self.completed = true;
+                Ok((
+                    response_bytes,
+                    ReadResult::HandshakeCompleted { remaining_bytes },
+                ))
+            }
+        }
+    }
+}
+
 pub struct Connection {
     socket: TcpStream,
     pub token: Option<Token>,
     interest: Ready,
     send_queue: VecDeque<SendablePacket>,
     has_been_registered: bool,
-    handshake: Handshake,
-    handshake_completed: bool,
+    handshake: ConnectionHandshake,
     debug_log_files: Option<DebugLogFiles>,
+    read_buffer: Vec<u8>,
     dropped_packet_count: u32,
-    last_drop_notification_at: SystemTime,
+    last_drop_notification_at: Instant,
+    created_at: Instant,
+    last_activity_at: Instant,
 }
 
 impl Connection {
@@ -72,39 +172,27 @@ impl Connection {
         count: usize,
         log_debug_logic: bool,
         is_inbound_connection: bool,
-    ) -> Connection {
-        socket
-            .set_recv_buffer_size(SOCKET_RECEIVE_BUFFER_SIZE)
-            .expect("Could not set recv buffer size");
-        socket
-            .set_send_buffer_size(SOCKET_SEND_BUFFER_SIZE)
-            .expect("Could not set send buffer size");
+        options: SocketOptions,
+    ) -> io::Result<Connection> {
+        socket.set_recv_buffer_size(options.receive_buffer_size)?;
+        socket.set_send_buffer_size(options.send_buffer_size)?;
 
         let debug_log_files = match log_debug_logic {
-            true => {
-                fs::create_dir_all("logs").unwrap();
-
-                let duration = SystemTime::now().duration_since(UNIX_EPOCH).unwrap();
-                let seconds = duration.as_secs();
-                let rtmp_input_name = format!("logs/{}-{}.rtmp.input.log", seconds, count);
-                let rtmp_output_name = format!("logs/{}-{}.rtmp.output.log", seconds, count);
-
-                let log_files = DebugLogFiles {
-                    rtmp_input_file: File::create(rtmp_input_name).unwrap(),
-                    rtmp_output_file: File::create(rtmp_output_name).unwrap(),
-                };
-
-                Some(log_files)
-            }
+            true => match DebugLogFiles::create(count) {
+                Ok(log_files) => Some(log_files),
+                Err(e) => {
+                    eprintln!(
+                        "{}{}",
+                        "⚠️ Failed to create debug log files • error=".yellow(),
+                        e.to_string().yellow().dimmed(),
+                    );
+                    None
+                }
+            },
 
             false => None,
         };
 
-        let handshake = match is_inbound_connection {
-            true => Handshake::new(PeerType::Server),
-            false => Handshake::new(PeerType::Client),
-        };
-
         println!(
             "{}{}{}{}",
             "✔️ Created new connection • inbound=".color(FG1),
@@ -116,22 +204,24 @@ impl Connection {
         let mut connection = Connection {
             socket,
             debug_log_files,
+            read_buffer: vec![0; options.read_buffer_size],
             token: None,
             interest: Ready::readable() | Ready::writable(),
             send_queue: VecDeque::new(),
             has_been_registered: false,
-            handshake_completed: false,
             dropped_packet_count: 0,
-            last_drop_notification_at: SystemTime::now(),
-            handshake,
+            last_drop_notification_at: Instant::now(),
+            created_at: Instant::now(),
+            last_activity_at: Instant::now(),
+            handshake: ConnectionHandshake::new(is_inbound_connection),
         };
 
-        let handshake_bytes = connection.handshake.generate_outbound_p0_and_p1().unwrap();
+        let handshake_bytes = connection.handshake.start()?;
         connection
             .send_queue
             .push_back(SendablePacket::RawBytes(handshake_bytes));
         connection.interest.insert(Ready::writable());
-        connection
+        Ok(connection)
     }
 
     pub fn enqueue_response(&mut self, poll: &mut Poll, bytes: Vec<u8>) -> io::Result<()> {
@@ -141,7 +231,7 @@ impl Connection {
     }
 
     pub fn enqueue_packet(&mut self, poll: &mut Poll, packet: Packet) -> io::Result<()> {
-        let elapsed = self.last_drop_notification_at.elapsed().unwrap();
+        let elapsed = self.last_drop_notification_at.elapsed();
         if elapsed.as_secs() > 10 {
             if self.dropped_packet_count > 0 {
                 println!(
@@ -151,7 +241,7 @@ impl Connection {
                 );
             }
 
-            self.last_drop_notification_at = SystemTime::now();
+            self.last_drop_notification_at = Instant::now();
             self.dropped_packet_count = 0;
         }
 
@@ -166,30 +256,20 @@ impl Connection {
     }
 
     pub fn readable(&mut self, poll: &mut Poll) -> Result<ReadResult, ConnectionError> {
-        let mut buffer = [0_u8; 4096];
-        match self.socket.read(&mut buffer) {
+        match self.socket.read(&mut self.read_buffer) {
             Ok(0) => Err(ConnectionError::SocketClosed),
 
             Ok(bytes_read_count) => {
-                let read_result = match self.handshake_completed {
-                    false => self.handle_handshake_bytes(poll, &buffer[..bytes_read_count])?,
-                    true => ReadResult::BytesReceived {
-                        buffer,
-                        byte_count: bytes_read_count,
-                    },
+                let bytes = self.read_buffer[..bytes_read_count].to_vec();
+                self.last_activity_at = Instant::now();
+                let read_result = match self.handshake.is_completed() {
+                    false => self.handle_handshake_bytes(poll, &bytes)?,
+                    true => {
+                        self.log_io(&bytes, false);
+                        ReadResult::BytesReceived { bytes }
+                    }
                 };
 
-                if let ReadResult::BytesReceived {
-                    buffer: read_buffer,
-                    byte_count,
-                } = read_result
-                    && let Some(ref mut logs) = self.debug_log_files
-                {
-                    logs.rtmp_input_file
-                        .write_all(&read_buffer[..byte_count])
-                        .unwrap();
-                }
-
                 self.register(poll)?;
                 Ok(read_result)
             }
@@ -229,13 +309,9 @@ impl Connection {
 
         match self.socket.write_all(&bytes) {
             Ok(()) => {
-                if self.handshake_completed {
-                    match self.debug_log_files {
-                        None => (),
-                        Some(ref mut logs) => {
-                            logs.rtmp_output_file.write_all(&bytes).unwrap();
-                        }
-                    }
+                self.last_activity_at = Instant::now();
+                if self.handshake.is_completed() {
+                    self.log_io(&bytes, true);
                 }
             }
 
@@ -262,17 +338,40 @@ impl Connection {
         Ok(())
     }
 
+    /// Whether the RTMP handshake has finished for this connection.
+    pub fn is_handshake_completed(&self) -> bool {
+        self.handshake.is_completed()
+    }
+
+    /// Time elapsed since the connection was accepted or opened.
+    pub fn age(&self) -> Duration {
+        self.created_at.elapsed()
+    }
+
+    /// Time elapsed since bytes were last read from or written to the socket.
+    pub fn idle_for(&self) -> Duration {
+        self.last_activity_at.elapsed()
+    }
+
+    /// Whether packets are queued that were not written to the socket yet.
+    pub fn has_pending_output(&self) -> bool {
+        !self.send_queue.is_empty()
+    }
+
     pub fn register(&mut self, poll: &mut Poll) -> io::Result<()> {
+        let token = self
+            .token
+            .ok_or_else(|| io::Error::other("Connection has no token"))?;
         match self.has_been_registered {
             true => poll.reregister(
                 &self.socket,
-                self.token.unwrap(),
+                token,
                 self.interest,
                 PollOpt::edge() | PollOpt::oneshot(),
             )?,
             false => poll.register(
                 &self.socket,
-                self.token.unwrap(),
+                token,
                 self.interest,
                 PollOpt::edge() | PollOpt::oneshot(),
             )?,
@@ -282,53 +381,44 @@ impl Connection {
         Ok(())
     }
 
+    /// Appends `bytes` to the debug log of the given direction. Logging
+    /// stops for the connection if a write fails.
+    fn log_io(&mut self, bytes: &[u8], outbound: bool) {
+        let Some(ref mut logs) = self.debug_log_files else {
+            return;
+        };
+        let file = match outbound {
+            true => &mut logs.rtmp_output_file,
+            false => &mut logs.rtmp_input_file,
+        };
+        if let Err(e) = file.write_all(bytes) {
+            eprintln!(
+                "{}{}{}{}",
+                "⚠️ Failed to write debug log, disabling it • token=".yellow(),
+                format!("{:?}", self.token).yellow().dimmed(),
+                " • error=".yellow(),
+                e.to_string().yellow().dimmed(),
+            );
+            self.debug_log_files = None;
+        }
+    }
+
     fn handle_handshake_bytes(
         &mut self,
         poll: &mut Poll,
         bytes: &[u8],
     ) -> Result<ReadResult, ConnectionError> {
-        let result = match self.handshake.process_bytes(bytes) {
-            Ok(result) => result,
-            Err(error) => {
-                println!(
-                    "{}{}",
-                    "💥 Handshake error: ".red(),
-                    format!("{:?}", error).red()
-                );
-                return Err(ConnectionError::SocketClosed);
-            }
-        };
-
-        match result {
-            HandshakeProcessResult::InProgress { response_bytes } => {
-                if !response_bytes.is_empty() {
-                    self.enqueue_response(poll, response_bytes)?;
+        let (response_bytes, read_result) =
+            match strim_common::contain_panic(|| self.handshake.process_bytes(bytes)) {
+                Ok(result) => result?,
+                Err(_) => {
+                    println!("{}", "💥 Handshake input caused a panic".red());
+                    return Err(ConnectionError::SocketClosed);
                 }
-
-                Ok(ReadResult::HandshakingInProgress)
-            }
-
-            HandshakeProcessResult::Completed {
-                response_bytes,
-                remaining_bytes,
-            } => {
-                if !response_bytes.is_empty() {
-                    self.enqueue_response(poll, response_bytes)?;
-                }
-
-                let mut buffer = [0; BUFFER_SIZE];
-                let buffer_size = remaining_bytes.len();
-                for (index, value) in remaining_bytes.into_iter().enumerate() {
-                    buffer[index] = value;
-                }
-
-                self.handshake_completed = true;
-
-                Ok(ReadResult::HandshakeCompleted {
-                    buffer,
-                    byte_count: buffer_size,
-                })
-            }
+            };
+        if !response_bytes.is_empty() {
+            self.enqueue_response(poll, response_bytes)?;
         }
+        Ok(read_result)
     }
 }
@@ -0,0 +1,250 @@
+use futures::{Stream, StreamExt};
+use owo_colors::OwoColorize;
+use std::{collections::HashSet, net::SocketAddr, pin::Pin, sync::Arc};
+use strim_common::shutdown::shutdown_signal;
+use tokio::{
+    net::TcpListener,
+    sync::{broadcast, mpsc, oneshot},
+};
+use tokio_stream::wrappers::{BroadcastStream, TcpListenerStream};
+use tonic::{Request, Response, Status};
+
+use crate::{
+    colors::{FG1, FG2},
+    events::Event,
+    server::ServerNotification,
+};
+
+pub mod proto {
+    tonic::include_proto!("strim.v1");
+}
+
+use proto::control_plane_server::{ControlPlane, ControlPlaneServer};
+
+/// Events buffered for each `WatchEvents` call before the slowest
+/// watchers start missing some.
+const WATCH_QUEUE_CAPACITY: usize = 1024;
+
+/// A control plane call, answered on the RTMP event loop.
+#[derive(Debug)]
+pub enum ControlRequest {
+    ListStreams {
+        reply: oneshot::Sender<Vec<proto::Stream>>,
+    },
+    DisconnectClient {
+        connection_id: usize,
+        reason: String,
+        reply: oneshot::Sender<Result<(), Status>>,
+    },
+    StartPush {
+        stable_id: String,
+        url: String,
+        reply: oneshot::Sender<Result<usize, Status>>,
+    },
+    StopPush {
+        push_id: usize,
+        reply: oneshot::Sender<Result<(), Status>>,
+    },
+}
+
+struct ControlPlaneService {
+    notifications_tx: mpsc::UnboundedSender<ServerNotification>,
+    events: broadcast::Sender<Arc<Event>>,
+}
+
+impl ControlPlaneService {
+    /// Hands a request to the event loop and waits for its reply.
+    async fn call<T>(
+        &self,
+        request: impl FnOnce(oneshot::Sender<T>) -> ControlRequest,
+    ) -> Result<T, Status> {
+        let (reply, rx) = oneshot::channel();
+        self.notifications_tx
+            .send(ServerNotification::Control(request(reply)))
+            .map_err(|_| Status::unavailable("Server is shutting down"))?;
+        rx.await
+            .map_err(|_| Status::unavailable("Server is shutting down"))
+    }
+}
+
+#[tonic::async_trait]
+impl ControlPlane for ControlPlaneService {
+    async fn list_streams(
+        &self,
+        _request: Request<proto::ListStreamsRequest>,
+    ) -> Result<Response<proto::ListStreamsResponse>, Status> {
+        let streams = self
+            .call(|reply| ControlRequest::ListStreams { reply })
+            .await?;
+        Ok(Response::new(proto::ListStreamsResponse { streams }))
+    }
+
+    async fn get_stream(
+        &self,
+        request: Request<proto::GetStreamRequest>,
+    ) -> Result<Response<proto::Stream>, Status> {
+        let stable_id = request.into_inner().stable_id;
+        self.call(|reply| ControlRequest::ListStreams { reply })
+            .await?
+            .into_iter()
+            .find(|stream| stream.stable_id == stable_id)
+            .map(Response::new)
+            .ok_or_else(|| Status::not_found(format!("Stream {} is not published", stable_id)))
+    }
+
+    async fn disconnect_client(
+        &self,
+        request: Request<proto::DisconnectClientRequest>,
+    ) -> Result<Response<proto::DisconnectClientResponse>, Status> {
+        let request = request.into_inner();
+        self.call(|reply| ControlRequest::DisconnectClient {
+            connection_id: request.connection_id as usize,
+            reason: request.reason,
+            reply,
+        })
+        .await??;
+        Ok(Response::new(proto::DisconnectClientResponse {}))
+    }
+
+    async fn start_push(
+        &self,
+        request: Request<proto::StartPushRequest>,
+    ) -> Result<Response<proto::StartPushResponse>, Status> {
+        let request = request.into_inner();
+        let push_id = self
+            .call(|reply| ControlRequest::StartPush {
+                stable_id: request.stable_id,
+                url: request.url,
+                reply,
+            })
+            .await??;
+        Ok(Response::new(proto::StartPushResponse {
+            push_id: push_id as u64,
+        }))
+    }
+
+    async fn stop_push(
+        &self,
+        request: Request<proto::StopPushRequest>,
+    ) -> Result<Response<proto::StopPushResponse>, Status> {
+        let push_id = request.into_inner().push_id as usize;
+        self.call(|reply| ControlRequest::StopPush { push_id, reply })
+            .await??;
+        Ok(Response::new(proto::StopPushResponse {}))
+    }
+
+    type WatchEventsStream = Pin<Box<dyn Stream<Item = Result<proto::Event, Status>> + Send>>;
+
+    async fn watch_events(
+        &self,
+        request: Request<proto::WatchEventsRequest>,
+    ) -> Result<Response<Self::WatchEventsStream>, Status> {
+        let subjects: HashSet<String> = request.into_inner().subjects.into_iter().collect();
+        let stream = BroadcastStream::new(self.events.subscribe())
+            .filter_map(move |event| {
+                // Events missed by a lagging watcher are skipped
+                let event = event
+                    .ok()
+                    .filter(|event| subjects.is_empty() || subjects.contains(event.kind.subject()))
+                    .map(|event| to_proto(&event));
+                async move { event }
+            })
+            .map(Ok);
+        Ok(Response::new(Box::pin(stream)))
+    }
+}
+
+fn to_proto(event: &Event) -> proto::Event {
+    proto::Event {
+        id: event.id.to_string(),
+        timestamp: event.timestamp.clone(),
+        pod_name: event.pod_name.clone(),
+        subject: event.kind.subject().to_string(),
+        json: serde_json::to_string(event).unwrap_or_default(),
+    }
+}
+
+/// Checks the bearer token of each call against `token`. Every call is
+/// let through when no token is configured.
+#[allow(clippy::result_large_err)]
+fn authenticate(
+    token: Option<Arc<str>>,
+) -> impl FnMut(Request<()>) -> Result<Request<()>, Status> + Clone {
+    move |request: Request<()>| {
+        let Some(ref token) = token else {
+            return Ok(request);
+        };
+        let presented = request
+            .metadata()
+            .get("authorization")
+            .and_then(|value| value.to_str().ok())
+            .and_then(|value| value.strip_prefix("Bearer "));
+        match presented {
+            Some(presented) if constant_time_eq(presented.as_bytes(), token.as_bytes()) => {
+                Ok(request)
+            }
+            _ => Err(Status::unauthenticated("Invalid or missing bearer token")),
+        }
+    }
+}
+
+/// Compares without returning early, so the time taken does not reveal
+/// how much of the token was guessed right.
+fn constant_time_eq(a: &[u8], b: &[u8]) -> bool {
+    a.len() == b.len() && a.iter().zip(b).fold(0, |diff, (a, b)| diff | (a ^ b)) == 0
+}
+
+/// Spawns the gRPC control plane on `address`. Calls must carry `token`
+/// as a bearer token, which may only be omitted on a loopback address.
+/// Calls are answered by the server through `notifications_tx`, and
+/// `events` are streamed to `WatchEvents` callers.
+pub async fn spawn_grpc_server(
+    address: SocketAddr,
+    token: Option<String>,
+    notifications_tx: mpsc::UnboundedSender<ServerNotification>,
+    mut events: mpsc::Receiver<Arc<Event>>,
+) -> anyhow::Result<()> {
+    let token = token.filter(|token| !token.is_empty());
+    if token.is_none() && !address.ip().is_loopback() {
+        anyhow::bail!(
+            "GRPC_TOKEN is required unless the control plane listens on a loopback address"
+        );
+    }
+    let (events_tx, _) = broadcast::channel(WATCH_QUEUE_CAPACITY);
+    tokio::spawn({
+        let events_tx = events_tx.clone();
+        async move {
+            while let Some(event) = events.recv().await {
+                // Nobody is watching
+                let _ = events_tx.send(event);
+            }
+        }
+    });
+    let service = ControlPlaneService {
+        notifications_tx,
+        events: events_tx,
+    };
+    let listener = TcpListener::bind(address).await?;
+    println!(
+        "{}{}{}{}",
+        "🎛️ Starting gRPC control plane • address=".color(FG1),
+        address.to_string().color(FG2),
+        " • authenticated=".color(FG1),
+        token.is_some().color(FG2),
+    );
+    let interceptor = authenticate(token.map(Arc::from));
+    tokio::spawn(async move {
+        let result = tonic::transport::Server::builder()
+            .add_service(ControlPlaneServer::with_interceptor(service, interceptor))
+            .serve_with_incoming_shutdown(TcpListenerStream::new(listener), shutdown_signal())
+            .await;
+        if let Err(e) = result {
+            eprintln!(
+                "{}{}",
+                "❌ gRPC control plane failed • error=".red(),
+                format!("{:?}", e).red().dimmed(),
+            );
+        }
+    });
+    Ok(())
+}
@@ -0,0 +1,485 @@
+use rml_rtmp::chunk_io::{ChunkDeserializer, Packet};
+use rml_rtmp::messages::{MessagePayload, RtmpMessage};
+use rml_rtmp::rml_amf0::Amf0Value;
+use rml_rtmp::time::RtmpTimestamp;
+use std::collections::HashMap;
+use strim_common::cues::{CueKind, CuePoint};
+
+/// Message type ids of the RTMP messages the tap needs to look at.
+const SET_CHUNK_SIZE: u8 = 1;
+const AUDIO_DATA: u8 = 8;
+const VIDEO_DATA: u8 = 9;
+const AMF0_DATA: u8 = 18;
+
+/// Chunk stream used for forwarded data messages and status messages the
+/// sessions cannot send themselves. The sessions only use
+/// chunk streams 2 to 6, so the header compression state they keep with
+/// the peer is never disturbed by these messages.
+const DATA_CHUNK_STREAM_ID: u8 = 7;
+
+/// Largest timestamp that fits in a chunk header before the extended
+/// timestamp field is needed.
+const MAX_HEADER_TIMESTAMP: u32 = 0xFFFFFF;
+
+/// An AMF0 data message other than `onMetaData`, such as `onTextData`,
+/// `onCuePoint` or `onCaptionInfo`. All of them are forwarded over RTMP,
+/// but only cue points make it into HLS; captions are not packaged.
+#[derive(Debug, Clone)]
+pub struct DataMessage {
+    /// The handler name followed by its arguments, without the
+    /// `@setDataFrame` prefix some encoders add.
+    pub values: Vec<Amf0Value>,
+    pub timestamp: RtmpTimestamp,
+}
+
+impl DataMessage {
+    pub fn name(&self) -> Option<&str> {
+        match self.values.first() {
+            Some(Amf0Value::Utf8String(name)) => Some(name),
+            _ => None,
+        }
+    }
+
+    /// Interprets an `onCuePoint` message as an ad marker. The kind is
+    /// taken from the `type` or `cue` parameter, or from the cue point's
+    /// own name or type (`cue-out`, `SpliceOut`, `AdStart`, ...).
+    pub fn cue_point(&self) -> Option<CuePoint> {
+        if self.name() != Some("onCuePoint") {
+            return None;
+        }
+        let Some(Amf0Value::Object(cue)) = self.values.get(1) else {
+            return None;
+        };
+        let empty = HashMap::new();
+        let parameters = match cue.get("parameters") {
+            Some(Amf0Value::Object(parameters)) => parameters,
+            _ => &empty,
+        };
+        let kind = [
+            parameters.get("type"),
+            parameters.get("cue"),
+            cue.get("name"),
+            cue.get("type"),
+        ]
+        .into_iter()
+        .flatten()
+        .find_map(cue_kind)?;
+        Some(CuePoint {
+            kind,
+            id: parameters.get("id").and_then(string),
+            time_ms: self.timestamp.value,
+            duration: parameters
+                .get("duration")
+                .or_else(|| cue.get("duration"))
+                .and_then(number),
+            scte35: parameters.get("scte35").and_then(string),
+        })
+    }
+
+    /// Serializes the message for `stream_id` as uncompressed chunks of at
+    /// most `chunk_size` bytes.
+    pub fn to_packet(&self, stream_id: u32, chunk_size: u32) -> Result<Packet, String> {
+        let message = RtmpMessage::Amf0Data {
+            values: self.values.clone(),
+        };
+        let payload = message
+            .into_message_payload(self.timestamp, stream_id)
+            .map_err(|e| e.to_string())?;
+        Ok(Packet {
+            bytes: chunk(&payload, chunk_size.max(1) as usize),
+            can_be_dropped: false,
+        })
+    }
+}
+
+fn cue_kind(value: &Amf0Value) -> Option<CueKind> {
+    let Amf0Value::Utf8String(value) = value else {
+        return None;
+    };
+    let normalized: String = value
+        .chars()
+        .filter(|c| c.is_ascii_alphanumeric())
+        .collect::<String>()
+        .to_ascii_lowercase();
+    match normalized.as_str() {
+        "out" | "cueout" | "spliceout" | "adstart" => Some(CueKind::Out),
+        "in" | "cuein" | "splicein" | "adend" => Some(CueKind::In),
+        _ => None,
+    }
+}
+
+fn string(value: &Amf0Value) -> Option<String> {
+    match value {
+        Amf0Value::Utf8String(value) => Some(value.clone()),
+        Amf0Value::Number(value) => Some(value.to_string()),
+        _ => None,
+    }
+}
+
+fn number(value: &Amf0Value) -> Option<f64> {
+    match value {
+        Amf0Value::Number(value) => Some(*value),
+        Amf0Value::Utf8String(value) => value.trim().parse().ok(),
+        _ => None,
+    }
+}
+
+/// Builds an `onStatus` for a stream the peer is publishing or playing,
+/// for events the sessions have no way to report, such as a publish
+/// stopped by the server. `level` is `status` or `error`.
+pub fn status_packet(
+    stream_id: u32,
+    chunk_size: u32,
+    level: &str,
+    code: &str,
+    description: &str,
+) -> Result<Packet, String> {
+    let info = HashMap::from([
+        (
+            "level".to_string(),
+            Amf0Value::Utf8String(level.to_string()),
+        ),
+        ("code".to_string(), Amf0Value::Utf8String(code.to_string())),
+        (
+            "description".to_string(),
+            Amf0Value::Utf8String(description.to_string()),
+        ),
+    ]);
+    let message = RtmpMessage::Amf0Command {
+        command_name: "onStatus".to_string(),
+        transaction_id: 0.0,
+        command_object: Amf0Value::Null,
+        additional_arguments: vec![Amf0Value::Object(info)],
+    };
+    let payload = message
+        .into_message_payload(RtmpTimestamp::new(0), stream_id)
+        .map_err(|e| e.to_string())?;
+    Ok(Packet {
+        bytes: chunk(&payload, chunk_size.max(1) as usize),
+        can_be_dropped: false,
+    })
+}
+
+/// Splits a message payload into a type 0 chunk followed by type 3
+/// continuation chunks.
+fn chunk(payload: &MessagePayload, chunk_size: usize) -> Vec<u8> {
+    let timestamp = payload.timestamp.value;
+    let extended = timestamp >= MAX_HEADER_TIMESTAMP;
+    let length = payload.data.len() as u32;
+    let mut bytes = Vec::with_capacity(payload.data.len() + 16);
+
+    bytes.push(DATA_CHUNK_STREAM_ID);
+    bytes.extend_from_slice(&timestamp.min(MAX_HEADER_TIMESTAMP).to_be_bytes()[1..]);
+    bytes.extend_from_slice(&length.to_be_bytes()[1..]);
+    bytes.push(payload.type_id);
+    bytes.extend_from_slice(&payload.message_stream_id.to_le_bytes());
+    for (index, slice) in payload.data.chunks(chunk_size).enumerate() {
+        if index > 0 {
+            bytes.push(0b1100_0000 | DATA_CHUNK_STREAM_ID);
+        }
+        if extended {
+            bytes.extend_from_slice(&timestamp.to_be_bytes());
+        }
+        bytes.extend_from_slice(slice);
+    }
+    bytes
+}
+
+/// Returns the message stream id of an audio, video or data message if
+/// the packet starts with a type 0 chunk.
+///
+/// The client sessions do not expose the stream id they publish on, but
+/// the first media they send always carries a full header.
+pub fn media_stream_id(packet: &Packet) -> Option<u32> {
+    let bytes = &packet.bytes;
+    let first = *bytes.first()?;
+    if first >> 6 != 0 {
+        return None;
+    }
+    let header = match first & 0b0011_1111 {
+        0 => bytes.get(2..13)?,
+        1 => bytes.get(3..14)?,
+        _ => bytes.get(1..12)?,
+    };
+    if !matches!(header[6], AUDIO_DATA | VIDEO_DATA | AMF0_DATA) {
+        return None;
+    }
+    Some(u32::from_le_bytes([
+        header[7], header[8], header[9], header[10],
+    ]))
+}
+
+/// Picks the AMF0 data messages out of a peer's inbound bytes.
+///
+/// The RTMP sessions only surface `onMetaData`, so the same bytes are fed
+/// to a second deserializer. Each message is returned with the number of
+/// audio and video messages that preceded it in the same batch, which lets
+/// the caller forward it in order with the media the session raised.
+pub struct DataTap {
+    deserializer: ChunkDeserializer,
+    stream_id: Option<u32>,
+}
+
+impl Default for DataTap {
+    fn default() -> Self {
+        Self {
+            deserializer: ChunkDeserializer::new(),
+            stream_id: None,
+        }
+    }
+}
+
+impl DataTap {
+    /// Message stream id of the last audio or video the peer sent, which
+    /// is the stream it publishes on.
+    pub fn stream_id(&self) -> Option<u32> {
+        self.stream_id
+    }
+
+    pub fn feed(&mut self, bytes: &[u8]) -> Result<Vec<(usize, DataMessage)>, String> {
+        let mut messages = Vec::new();
+        let mut media_count = 0;
+        let mut bytes = bytes;
+        while let Some(payload) = self
+            .deserializer
+            .get_next_message(bytes)
+            .map_err(|e| e.to_string())?
+        {
+            bytes = &[];
+            match payload.type_id {
+                AUDIO_DATA | VIDEO_DATA => {
+                    media_count += 1;
+                    self.stream_id = Some(payload.message_stream_id);
+                }
+                SET_CHUNK_SIZE | AMF0_DATA => match payload.to_rtmp_message() {
+                    Ok(RtmpMessage::SetChunkSize { size }) => self
+                        .deserializer
+                        .set_max_chunk_size(size as usize)
+                        .map_err(|e| e.to_string())?,
+                    Ok(RtmpMessage::Amf0Data { values }) => {
+                        if let Some(message) = data_message(values, payload.timestamp) {
+                            messages.push((media_count, message));
+                        }
+                    }
+                    _ => {}
+                },
+                _ => {}
+            }
+        }
+        Ok(messages)
+    }
+}
+
+/// Strips `@setDataFrame` and drops `onMetaData`, which the sessions
+/// already handle.
+fn data_message(mut values: Vec<Amf0Value>, timestamp: RtmpTimestamp) -> Option<DataMessage> {
+    if matches!(values.first(), Some(Amf0Value::Utf8String(name)) if name == "@setDataFrame") {
+        values.remove(0);
+    }
+    let message = DataMessage { values, timestamp };
+    match message.name() {
+        Some("onMetaData") | None => None,
+        Some(_) => Some(message),
+    }
+}
+
+/// A session result or a tapped data message, in the order they were
+/// received.
+pub enum Received<R> {
+    Session(R),
+    Data(DataMessage),
+}
+
+/// Merges tapped data messages back in between the session results, each
+/// one ahead of the first audio or video result that followed it.
+pub fn interleave<R>(
+    results: Vec<R>,
+    messages: Vec<(usize, DataMessage)>,
+    is_media: impl Fn(&R) -> bool,
+) -> Vec<Received<R>> {
+    let mut merged = Vec::with_capacity(results.len() + messages.len());
+    let mut messages = messages.into_iter().peekable();
+    let mut media_count = 0;
+    for result in results {
+        if is_media(&result) {
+            while let Some((_, message)) = messages.next_if(|(count, _)| *count <= media_count) {
+                merged.push(Received::Data(message));
+            }
+            media_count += 1;
+        }
+        merged.push(Received::Session(result));
+    }
+    merged.extend(messages.map(|(_, message)| Received::Data(message)));
+    merged
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn text_message(text: &str, timestamp: u32) -> DataMessage {
+        DataMessage {
+            values: vec![
+                Amf0Value::Utf8String("onTextData".to_string()),
+                Amf0Value::Object(HashMap::from([(
+                    "text".to_string(),
+                    Amf0Value::Utf8String(text.to_string()),
+                )])),
+            ],
+            timestamp: RtmpTimestamp::new(timestamp),
+        }
+    }
+
+    fn cue_message(parameters: Vec<(&str, Amf0Value)>) -> DataMessage {
+        let parameters = parameters
+            .into_iter()
+            .map(|(name, value)| (name.to_string(), value))
+            .collect();
+        DataMessage {
+            values: vec![
+                Amf0Value::Utf8String("onCuePoint".to_string()),
+                Amf0Value::Object(HashMap::from([
+                    ("name".to_string(), Amf0Value::Utf8String("ad".to_string())),
+                    ("parameters".to_string(), Amf0Value::Object(parameters)),
+                ])),
+            ],
+            timestamp: RtmpTimestamp::new(4000),
+        }
+    }
+
+    /// Deserializes every message in `bytes` with chunks of at most
+    /// `chunk_size` bytes.
+    fn deserialize(bytes: &[u8], chunk_size: u32) -> Vec<MessagePayload> {
+        let mut deserializer = ChunkDeserializer::new();
+        deserializer
+            .set_max_chunk_size(chunk_size as usize)
+            .unwrap();
+        let mut payloads = Vec::new();
+        let mut bytes = bytes;
+        while let Some(payload) = deserializer.get_next_message(bytes).unwrap() {
+            bytes = &[];
+            payloads.push(payload);
+        }
+        payloads
+    }
+
+    fn assert_round_trips(message: &DataMessage, chunk_size: u32) {
+        let packet = message.to_packet(5, chunk_size).unwrap();
+        let payloads = deserialize(&packet.bytes, chunk_size);
+        assert_eq!(payloads.len(), 1);
+        let payload = &payloads[0];
+        assert_eq!(payload.type_id, AMF0_DATA);
+        assert_eq!(payload.message_stream_id, 5);
+        assert_eq!(payload.timestamp, message.timestamp);
+        match payload.to_rtmp_message().unwrap() {
+            RtmpMessage::Amf0Data { values } => assert_eq!(values, message.values),
+            other => panic!("Unexpected message {:?}", other),
+        }
+    }
+
+    /// Length of the AMF0 body of `message`.
+    fn payload_length(message: &DataMessage) -> u32 {
+        let message = RtmpMessage::Amf0Data {
+            values: message.values.clone(),
+        };
+        let payload = message
+            .into_message_payload(RtmpTimestamp::new(0), 0)
+            .unwrap();
+        payload.data.len() as u32
+    }
+
+    #[test]
+    fn chunks_round_trip() {
+        let message = text_message(&"a".repeat(1000), 1234);
+        let length = payload_length(&message);
+        assert_eq!(length % 2, 0);
+        // Many chunks, an exact multiple of the chunk size, a single full
+        // chunk and one with room to spare
+        for chunk_size in [1, 128, length / 2, length, length + 1, 4096] {
+            assert_round_trips(&message, chunk_size);
+        }
+    }
+
+    #[test]
+    fn chunks_extended_timestamps_round_trip() {
+        let message = text_message(&"b".repeat(500), 0x0123_4567);
+        assert_round_trips(&message, 128);
+        assert_round_trips(&message, 4096);
+        let message = text_message("at the limit", MAX_HEADER_TIMESTAMP);
+        assert_round_trips(&message, 128);
+    }
+
+    #[test]
+    fn starts_continuation_chunks_with_type_3_headers() {
+        let packet = text_message(&"c".repeat(300), 0).to_packet(1, 128).unwrap();
+        assert_eq!(packet.bytes[0], DATA_CHUNK_STREAM_ID);
+        assert_eq!(packet.bytes[12 + 128], 0xC0 | DATA_CHUNK_STREAM_ID);
+        assert_eq!(media_stream_id(&packet), Some(1));
+    }
+
+    fn media_chunks(type_id: u8, data: &'static [u8]) -> Vec<u8> {
+        let payload = MessagePayload {
+            timestamp: RtmpTimestamp::new(40),
+            type_id,
+            message_stream_id: 3,
+            data: data.into(),
+        };
+        chunk(&payload, 128)
+    }
+
+    #[test]
+    fn taps_data_messages_between_media() {
+        let mut message = text_message("hello", 40);
+        message
+            .values
+            .insert(0, Amf0Value::Utf8String("@setDataFrame".to_string()));
+        let mut bytes = media_chunks(VIDEO_DATA, &[0x17, 0, 0, 0, 0]);
+        bytes.extend(message.to_packet(3, 128).unwrap().bytes);
+        bytes.extend(media_chunks(AUDIO_DATA, &[0xAF, 1]));
+        let mut tap = DataTap::default();
+        let messages = tap.feed(&bytes).unwrap();
+        assert_eq!(messages.len(), 1);
+        let (media_count, message) = &messages[0];
+        assert_eq!(*media_count, 1);
+        assert_eq!(message.name(), Some("onTextData"));
+        assert_eq!(message.timestamp, RtmpTimestamp::new(40));
+        assert_eq!(tap.stream_id(), Some(3));
+    }
+
+    #[test]
+    fn drops_metadata() {
+        let values = vec![
+            Amf0Value::Utf8String("@setDataFrame".to_string()),
+            Amf0Value::Utf8String("onMetaData".to_string()),
+        ];
+        assert!(data_message(values, RtmpTimestamp::new(0)).is_none());
+    }
+
+    #[test]
+    fn parses_cue_points() {
+        let cue = cue_message(vec![
+            ("type", Amf0Value::Utf8String("cue-out".to_string())),
+            ("id", Amf0Value::Number(7.0)),
+            ("duration", Amf0Value::Utf8String(" 30.5 ".to_string())),
+            ("scte35", Amf0Value::Utf8String("0xFC30".to_string())),
+        ])
+        .cue_point()
+        .unwrap();
+        assert_eq!(
+            cue,
+            CuePoint {
+                kind: CueKind::Out,
+                id: Some("7".to_string()),
+                time_ms: 4000,
+                duration: Some(30.5),
+                scte35: Some("0xFC30".to_string()),
+            }
+        );
+        let cue = cue_message(vec![("cue", Amf0Value::Utf8String("AdEnd".to_string()))])
+            .cue_point()
+            .unwrap();
+        assert_eq!(cue.kind, CueKind::In);
+        assert!(cue_message(Vec::new()).cue_point().is_none());
+        assert!(text_message("cue-out", 0).cue_point().is_none());
+    }
+}
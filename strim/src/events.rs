@@ -0,0 +1,189 @@
+use metrics::counter;
+use rml_rtmp::sessions::StreamMetadata;
+use serde::Serialize;
+use std::sync::Arc;
+use strim_common::cues::CuePoint;
+use tokio::sync::mpsc;
+use tokio::sync::mpsc::error::TrySendError;
+use uuid::Uuid;
+
+use crate::routes::LimitAction;
+use crate::server::{CloseReason, PushState};
+
+/// Codec details reported by a publisher's `onMetaData`.
+#[derive(Serialize, Clone, Debug, Default)]
+pub struct CodecInfo {
+    pub video_codec_id: Option<u32>,
+    pub video_width: Option<u32>,
+    pub video_height: Option<u32>,
+    pub video_frame_rate: Option<f32>,
+    pub video_bitrate_kbps: Option<u32>,
+    pub audio_codec_id: Option<u32>,
+    pub audio_bitrate_kbps: Option<u32>,
+    pub audio_sample_rate: Option<u32>,
+    pub audio_channels: Option<u32>,
+    pub encoder: Option<String>,
+}
+
+impl From<&StreamMetadata> for CodecInfo {
+    fn from(metadata: &StreamMetadata) -> Self {
+        CodecInfo {
+            video_codec_id: metadata.video_codec_id,
+            video_width: metadata.video_width,
+            video_height: metadata.video_height,
+            video_frame_rate: metadata.video_frame_rate,
+            video_bitrate_kbps: metadata.video_bitrate_kbps,
+            audio_codec_id: metadata.audio_codec_id,
+            audio_bitrate_kbps: metadata.audio_bitrate_kbps,
+            audio_sample_rate: metadata.audio_sample_rate,
+            audio_channels: metadata.audio_channels,
+            encoder: metadata.encoder.clone(),
+        }
+    }
+}
+
+/// A lifecycle event raised by the RTMP server.
+#[derive(Serialize, Clone, Debug)]
+#[serde(tag = "type", rename_all = "snake_case")]
+pub enum EventKind {
+    PublishStarted {
+        connection_id: usize,
+        app_name: String,
+        stable_id: String,
+    },
+    PublishStopped {
+        connection_id: usize,
+        app_name: String,
+        stable_id: String,
+    },
+    /// `stream_key` is the key the watcher asked for, usually the public
+    /// `stable_id`, and never the secret key of the publisher.
+    PlayStarted {
+        connection_id: usize,
+        app_name: String,
+        stream_key: String,
+    },
+    PlayStopped {
+        connection_id: usize,
+        stream_key: String,
+    },
+    ConnectionClosed {
+        connection_id: usize,
+        reason: CloseReason,
+    },
+    StrimCreated {
+        namespace: String,
+        name: String,
+        stable_id: String,
+    },
+    StrimDeleted {
+        namespace: String,
+        name: String,
+    },
+    PushStateChanged {
+        app_name: String,
+        host: String,
+        target_stream: String,
+        state: PushState,
+        failed_attempts: u32,
+    },
+    CodecInfo {
+        app_name: String,
+        stable_id: String,
+        #[serde(flatten)]
+        codec: CodecInfo,
+    },
+    /// An ad marker, consumed by peggy to tag the HLS playlist.
+    CuePoint {
+        app_name: String,
+        stable_id: String,
+        #[serde(flatten)]
+        cue: CuePoint,
+    },
+    /// A publisher exceeded one of its app's ingest limits.
+    IngestLimitExceeded {
+        app_name: String,
+        stable_id: String,
+        limit: String,
+        detail: String,
+        action: LimitAction,
+    },
+}
+
+impl EventKind {
+    /// The subject suffix the event is published under, appended to the
+    /// configured prefix (e.g. `strim.publish.started`).
+    pub fn subject(&self) -> &'static str {
+        match self {
+            EventKind::PublishStarted { .. } => "publish.started",
+            EventKind::PublishStopped { .. } => "publish.stopped",
+            EventKind::PlayStarted { .. } => "play.started",
+            EventKind::PlayStopped { .. } => "play.stopped",
+            EventKind::ConnectionClosed { .. } => "connection.closed",
+            EventKind::StrimCreated { .. } => "strim.created",
+            EventKind::StrimDeleted { .. } => "strim.deleted",
+            EventKind::PushStateChanged { .. } => "push.state",
+            EventKind::CodecInfo { .. } => "codec.info",
+            EventKind::CuePoint { .. } => "cue.point",
+            EventKind::IngestLimitExceeded { .. } => "ingest.limit_exceeded",
+        }
+    }
+}
+
+#[derive(Serialize, Clone, Debug)]
+pub struct Event {
+    pub id: Uuid,
+    pub timestamp: String,
+    pub pod_name: String,
+    #[serde(flatten)]
+    pub kind: EventKind,
+}
+
+/// Fans lifecycle events out to every subscriber (NATS, webhooks, ...).
+///
+/// Emitting never blocks: each subscriber has a bounded queue, and events
+/// are dropped and counted when a subscriber falls behind, so the RTMP
+/// event loop is never stalled by a slow consumer.
+#[derive(Clone)]
+pub struct EventBus {
+    pod_name: String,
+    subscribers: Vec<mpsc::Sender<Arc<Event>>>,
+}
+
+impl EventBus {
+    pub fn new(pod_name: String) -> Self {
+        Self {
+            pod_name,
+            subscribers: Vec::new(),
+        }
+    }
+
+    /// Registers a new subscriber with a queue of the given capacity.
+    pub fn subscribe(&mut self, capacity: usize) -> mpsc::Receiver<Arc<Event>> {
+        let (tx, rx) = mpsc::channel(capacity);
+        self.subscribers.push(tx);
+        rx
+    }
+
+    pub fn emit(&self, kind: EventKind) {
+        if self.subscribers.is_empty() {
+            return;
+        }
+        let event = Arc::new(Event {
+            id: Uuid::new_v4(),
+            timestamp: chrono::Utc::now().to_rfc3339(),
+            pod_name: self.pod_name.clone(),
+            kind,
+        });
+        for subscriber in &self.subscribers {
+            match subscriber.try_send(event.clone()) {
+                Ok(()) => {}
+                Err(TrySendError::Full(_)) => {
+                    counter!("strim_events_dropped_total", "type" => event.kind.subject())
+                        .increment(1);
+                }
+                Err(TrySendError::Closed(_)) => {}
+            }
+        }
+    }
+}
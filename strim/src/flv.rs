@@ -0,0 +1,131 @@
+use anyhow::{Result, bail};
+use bytes::Bytes;
+use rml_rtmp::rml_amf0::{self, Amf0Value};
+use rml_rtmp::sessions::StreamMetadata;
+use std::collections::HashMap;
+use std::io::{self, Write};
+
+pub const AUDIO_TAG: u8 = 8;
+pub const VIDEO_TAG: u8 = 9;
+pub const SCRIPT_TAG: u8 = 18;
+
+const TAG_HEADER_SIZE: usize = 11;
+
+/// Header flags announcing both audio and video, which players accept
+/// even when one of them never shows up.
+const HAS_AUDIO_AND_VIDEO: u8 = 0b101;
+
+#[derive(Debug, Clone)]
+pub struct FlvTag {
+    pub tag_type: u8,
+    /// Milliseconds since the start of the stream.
+    pub timestamp: u32,
+    pub data: Bytes,
+}
+
+/// Splits the contents of an FLV file into its tags.
+pub fn parse(bytes: &[u8]) -> Result<Vec<FlvTag>> {
+    if bytes.len() < 9 || &bytes[..3] != b"FLV" {
+        bail!("Not an FLV file");
+    }
+    let header_size = u32::from_be_bytes([bytes[5], bytes[6], bytes[7], bytes[8]]) as usize;
+    // Each tag is preceded by the size of the previous one
+    let mut offset = header_size + 4;
+    let mut tags = Vec::new();
+    while let Some(header) = bytes.get(offset..offset + TAG_HEADER_SIZE) {
+        let size = u32::from_be_bytes([0, header[1], header[2], header[3]]) as usize;
+        let timestamp = u32::from_be_bytes([header[7], header[4], header[5], header[6]]);
+        let start = offset + TAG_HEADER_SIZE;
+        let Some(data) = bytes.get(start..start + size) else {
+            // A truncated last tag, as left by an interrupted recording
+            break;
+        };
+        tags.push(FlvTag {
+            tag_type: header[0] & 0x1F,
+            timestamp,
+            data: Bytes::copy_from_slice(data),
+        });
+        offset = start + size + 4;
+    }
+    Ok(tags)
+}
+
+/// Writes tags to an FLV file.
+pub struct FlvWriter<W: Write> {
+    inner: W,
+}
+
+impl<W: Write> FlvWriter<W> {
+    pub fn new(mut inner: W) -> io::Result<Self> {
+        inner.write_all(&[b'F', b'L', b'V', 1, HAS_AUDIO_AND_VIDEO, 0, 0, 0, 9])?;
+        inner.write_all(&0u32.to_be_bytes())?;
+        Ok(FlvWriter { inner })
+    }
+
+    pub fn write_tag(&mut self, tag_type: u8, timestamp: u32, data: &[u8]) -> io::Result<()> {
+        let size = data.len() as u32;
+        let timestamp = timestamp.to_be_bytes();
+        let mut header = [0; TAG_HEADER_SIZE];
+        header[0] = tag_type;
+        header[1..4].copy_from_slice(&size.to_be_bytes()[1..]);
+        header[4..7].copy_from_slice(&timestamp[1..]);
+        header[7] = timestamp[0];
+        self.inner.write_all(&header)?;
+        self.inner.write_all(data)?;
+        self.inner
+            .write_all(&(TAG_HEADER_SIZE as u32 + size).to_be_bytes())
+    }
+
+    pub fn flush(&mut self) -> io::Result<()> {
+        self.inner.flush()
+    }
+}
+
+/// Reads the metadata out of an `onMetaData` script tag.
+pub fn parse_metadata(data: &[u8]) -> Option<StreamMetadata> {
+    let values = rml_amf0::deserialize(&mut io::Cursor::new(data)).ok()?;
+    let mut values = values.into_iter();
+    match values.next() {
+        Some(Amf0Value::Utf8String(name)) if name == "onMetaData" => {}
+        _ => return None,
+    }
+    let Some(Amf0Value::Object(properties)) = values.next() else {
+        return None;
+    };
+    let mut metadata = StreamMetadata::new();
+    metadata.apply_metadata_values(properties);
+    Some(metadata)
+}
+
+/// Encodes metadata as the body of an `onMetaData` script tag.
+pub fn metadata_tag(metadata: &StreamMetadata) -> Vec<u8> {
+    let mut properties = HashMap::new();
+    let mut number = |key: &str, value: Option<f64>| {
+        if let Some(value) = value {
+            properties.insert(key.to_string(), Amf0Value::Number(value));
+        }
+    };
+    number("width", metadata.video_width.map(f64::from));
+    number("height", metadata.video_height.map(f64::from));
+    number("videocodecid", metadata.video_codec_id.map(f64::from));
+    number("videodatarate", metadata.video_bitrate_kbps.map(f64::from));
+    number("framerate", metadata.video_frame_rate.map(f64::from));
+    number("audiocodecid", metadata.audio_codec_id.map(f64::from));
+    number("audiodatarate", metadata.audio_bitrate_kbps.map(f64::from));
+    number("audiosamplerate", metadata.audio_sample_rate.map(f64::from));
+    number("audiochannels", metadata.audio_channels.map(f64::from));
+    if let Some(stereo) = metadata.audio_is_stereo {
+        properties.insert("stereo".to_string(), Amf0Value::Boolean(stereo));
+    }
+    if let Some(ref encoder) = metadata.encoder {
+        properties.insert(
+            "encoder".to_string(),
+            Amf0Value::Utf8String(encoder.clone()),
+        );
+    }
+    let values = vec![
+        Amf0Value::Utf8String("onMetaData".to_string()),
+        Amf0Value::Object(properties),
+    ];
+    rml_amf0::serialize(&values).unwrap_or_default()
+}
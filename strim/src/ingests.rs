@@ -0,0 +1,69 @@
+use futures::StreamExt;
+use kube::{
+    Api, Client, ResourceExt,
+    runtime::{WatchStreamExt, reflector, watcher},
+};
+use owo_colors::OwoColorize;
+use std::sync::Arc;
+use strim_types::StrimIngest;
+
+use crate::colors::{FG1, FG2};
+
+/// Locally cached view of the `StrimIngest` resources in the namespace,
+/// kept up to date by a background watch. Reads never block, so it can be
+/// queried from the RTMP event loop.
+#[derive(Clone)]
+pub struct IngestCatalog {
+    store: reflector::Store<StrimIngest>,
+}
+
+impl IngestCatalog {
+    /// Starts watching `StrimIngest` resources in `namespace`.
+    pub fn spawn(client: Client, namespace: &str) -> Self {
+        println!(
+            "{}{}",
+            "👀 Watching StrimIngest resources • namespace=".color(FG1),
+            namespace.color(FG2),
+        );
+        let api: Api<StrimIngest> = Api::namespaced(client, namespace);
+        let (store, writer) = reflector::store();
+        let stream = watcher(api, watcher::Config::default())
+            .default_backoff()
+            .reflect(writer)
+            .applied_objects();
+        tokio::spawn(async move {
+            futures::pin_mut!(stream);
+            while let Some(result) = stream.next().await {
+                if let Err(e) = result {
+                    eprintln!(
+                        "{}{}",
+                        "❌ StrimIngest watch failed • error=".red(),
+                        format!("{:?}", e).red().dimmed(),
+                    );
+                }
+            }
+        });
+        Self { store }
+    }
+
+    /// Returns the ingest that most specifically matches a stream. Ties
+    /// are broken by priority, then by name so the choice is stable.
+    pub fn find(&self, app_name: &str, stable_id: &str) -> Option<Arc<StrimIngest>> {
+        self.store
+            .state()
+            .into_iter()
+            .filter_map(|ingest| {
+                let specificity = ingest.spec.specificity(app_name, stable_id)?;
+                Some((specificity, ingest.spec.priority, ingest))
+            })
+            .max_by(
+                |(a_specificity, a_priority, a), (b_specificity, b_priority, b)| {
+                    a_specificity
+                        .cmp(b_specificity)
+                        .then(a_priority.cmp(b_priority))
+                        .then_with(|| b.name_any().cmp(&a.name_any()))
+                },
+            )
+            .map(|(_, _, ingest)| ingest)
+    }
+}
@@ -0,0 +1,35 @@
+pub mod admin;
+pub mod args;
+pub mod bench;
+pub mod client;
+pub mod codecs;
+pub mod colors;
+pub mod connection;
+pub mod control;
+pub mod data;
+pub mod events;
+pub mod flv;
+pub mod ingests;
+pub mod limits;
+pub mod listeners;
+pub mod nats;
+pub mod orphans;
+pub mod pipelines;
+pub mod play;
+pub mod publish;
+pub mod registry;
+pub mod relay;
+pub mod revocations;
+pub mod routes;
+pub mod server;
+pub mod tuning;
+pub mod webhooks;
+
+/// A stream pushed to another RTMP server for as long as it is published.
+#[derive(Debug)]
+pub struct PushOptions {
+    pub host: String,
+    pub app: String,
+    pub source_stream: String,
+    pub target_stream: String,
+}
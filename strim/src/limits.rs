@@ -0,0 +1,172 @@
+use rml_rtmp::sessions::StreamMetadata;
+use std::collections::HashSet;
+use std::time::{Duration, Instant};
+
+use crate::codecs::{self, MediaInfo};
+use crate::routes::IngestLimits;
+
+/// Length of the windows the ingest bitrate is averaged over. Long enough
+/// to smooth out the burst of a keyframe.
+const BITRATE_WINDOW: Duration = Duration::from_secs(5);
+
+/// A limit a publisher exceeded.
+#[derive(Debug)]
+pub struct Violation {
+    /// `bitrate`, `width`, `height`, `frame_rate`, `video_codec` or
+    /// `audio_codec`.
+    pub limit: &'static str,
+    pub detail: String,
+}
+
+/// What is known about a published stream, from its metadata, sequence
+/// headers and tags, to be checked against its app's [`IngestLimits`].
+#[derive(Default)]
+pub struct IngestMeter {
+    declared_bitrate_kbps: Option<u32>,
+    measured_bitrate_kbps: Option<u32>,
+    width: Option<u32>,
+    height: Option<u32>,
+    frame_rate: Option<f64>,
+    video_codec: Option<&'static str>,
+    audio_codec: Option<&'static str>,
+    window_started_at: Option<Instant>,
+    window_bytes: u64,
+    /// Limits already reported, so each is only raised once per publish.
+    reported: HashSet<&'static str>,
+}
+
+impl IngestMeter {
+    pub fn metadata_received(&mut self, metadata: &StreamMetadata) {
+        let bitrate =
+            metadata.video_bitrate_kbps.unwrap_or(0) + metadata.audio_bitrate_kbps.unwrap_or(0);
+        self.declared_bitrate_kbps = (bitrate > 0).then_some(bitrate);
+        self.width = metadata.video_width.or(self.width);
+        self.height = metadata.video_height.or(self.height);
+        self.frame_rate = metadata.video_frame_rate.map(f64::from).or(self.frame_rate);
+        self.video_codec = metadata
+            .video_codec_id
+            .and_then(codecs::flv_video_codec)
+            .or(self.video_codec);
+        self.audio_codec = metadata
+            .audio_codec_id
+            .and_then(codecs::flv_audio_codec)
+            .or(self.audio_codec);
+    }
+
+    /// The sequence headers are authoritative over what the metadata
+    /// declared.
+    pub fn media_info_changed(&mut self, media: &MediaInfo) {
+        if let Some(video) = &media.video {
+            self.width = Some(video.width);
+            self.height = Some(video.height);
+            self.frame_rate = video.frame_rate.or(self.frame_rate);
+            self.video_codec = Some(video.codec);
+        }
+        if let Some(audio) = &media.audio {
+            self.audio_codec = Some(audio.codec);
+        }
+    }
+
+    /// Records a tag, returning whether it revealed anything new about
+    /// the stream: its first codec or a completed bitrate window.
+    pub fn tag_received(&mut self, video: bool, data: &[u8], now: Instant) -> bool {
+        let mut changed = false;
+        let (seen, codec) = if video {
+            (&mut self.video_codec, codecs::video_codec(data))
+        } else {
+            (&mut self.audio_codec, codecs::audio_codec(data))
+        };
+        if seen.is_none() && codec.is_some() {
+            *seen = codec;
+            changed = true;
+        }
+
+        self.window_bytes += data.len() as u64;
+        let started_at = *self.window_started_at.get_or_insert(now);
+        let elapsed = now.duration_since(started_at);
+        if elapsed >= BITRATE_WINDOW {
+            self.measured_bitrate_kbps =
+                Some((self.window_bytes * 8 / elapsed.as_millis().max(1) as u64) as u32);
+            self.window_started_at = Some(now);
+            self.window_bytes = 0;
+            changed = true;
+        }
+        changed
+    }
+
+    /// The measured ingest bitrate, or the declared one until a bitrate
+    /// window completes.
+    pub fn bitrate_kbps(&self) -> Option<u32> {
+        self.measured_bitrate_kbps.or(self.declared_bitrate_kbps)
+    }
+
+    /// Returns the limits the stream exceeds that were not reported yet.
+    pub fn check(&mut self, limits: &IngestLimits) -> Vec<Violation> {
+        let mut violations = Vec::new();
+        if let Some(max) = limits.max_bitrate_kbps {
+            if let Some(bitrate) = self.measured_bitrate_kbps.filter(|bitrate| *bitrate > max) {
+                violations.push(Violation {
+                    limit: "bitrate",
+                    detail: format!("measured {} kbps, limit is {} kbps", bitrate, max),
+                });
+            } else if let Some(bitrate) =
+                self.declared_bitrate_kbps.filter(|bitrate| *bitrate > max)
+            {
+                violations.push(Violation {
+                    limit: "bitrate",
+                    detail: format!("declared {} kbps, limit is {} kbps", bitrate, max),
+                });
+            }
+        }
+        if let (Some(max), Some(width)) = (limits.max_width, self.width)
+            && width > max
+        {
+            violations.push(Violation {
+                limit: "width",
+                detail: format!("width {} exceeds {}", width, max),
+            });
+        }
+        if let (Some(max), Some(height)) = (limits.max_height, self.height)
+            && height > max
+        {
+            violations.push(Violation {
+                limit: "height",
+                detail: format!("height {} exceeds {}", height, max),
+            });
+        }
+        // Frame rates derived from VUI timing are rarely exact
+        if let (Some(max), Some(frame_rate)) = (limits.max_frame_rate, self.frame_rate)
+            && frame_rate > max + 0.01
+        {
+            violations.push(Violation {
+                limit: "frame_rate",
+                detail: format!("{} fps exceeds {} fps", frame_rate, max),
+            });
+        }
+        if let Some(codec) = self.video_codec
+            && !is_allowed(&limits.video_codecs, codec)
+        {
+            violations.push(Violation {
+                limit: "video_codec",
+                detail: format!("video codec {} is not allowed", codec),
+            });
+        }
+        if let Some(codec) = self.audio_codec
+            && !is_allowed(&limits.audio_codecs, codec)
+        {
+            violations.push(Violation {
+                limit: "audio_codec",
+                detail: format!("audio codec {} is not allowed", codec),
+            });
+        }
+        violations.retain(|violation| self.reported.insert(violation.limit));
+        violations
+    }
+}
+
+fn is_allowed(allowed: &[String], codec: &str) -> bool {
+    allowed.is_empty()
+        || allowed
+            .iter()
+            .any(|allowed| allowed.eq_ignore_ascii_case(codec))
+}
@@ -0,0 +1,89 @@
+use socket2::{Domain, Socket, Type};
+use std::fmt;
+use std::io;
+use std::net::SocketAddr;
+use std::str::FromStr;
+
+/// Connections queued by the kernel before they are accepted.
+const BACKLOG: i32 = 1024;
+
+/// What clients connected through a listener may do.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct ListenerPolicy {
+    pub publish: bool,
+    pub play: bool,
+}
+
+impl ListenerPolicy {
+    pub const ALL: Self = Self {
+        publish: true,
+        play: true,
+    };
+}
+
+impl fmt::Display for ListenerPolicy {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        f.write_str(match (self.publish, self.play) {
+            (true, true) => "all",
+            (true, false) => "publish",
+            (false, true) => "play",
+            (false, false) => "none",
+        })
+    }
+}
+
+/// An address RTMP connections are accepted on.
+#[derive(Debug, Clone)]
+pub struct ListenAddr {
+    pub address: SocketAddr,
+    pub policy: ListenerPolicy,
+}
+
+impl FromStr for ListenAddr {
+    type Err = String;
+
+    /// Parses `address[=publish|play|all]`, e.g. `[::]:1935=publish`.
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        let (address, policy) = match s.rsplit_once('=') {
+            Some((address, policy)) => (address, policy),
+            None => (s, "all"),
+        };
+        let address = address
+            .parse()
+            .map_err(|e| format!("Invalid listen address '{}': {}", address, e))?;
+        let policy = match policy {
+            "all" => ListenerPolicy::ALL,
+            "publish" => ListenerPolicy {
+                publish: true,
+                play: false,
+            },
+            "play" => ListenerPolicy {
+                publish: false,
+                play: true,
+            },
+            _ => {
+                return Err(format!(
+                    "Invalid listener policy '{}', expected publish, play or all",
+                    policy
+                ));
+            }
+        };
+        Ok(ListenAddr { address, policy })
+    }
+}
+
+impl ListenAddr {
+    /// Binds a non-blocking listener. An IPv6 listener also accepts IPv4
+    /// connections, so `[::]:port` serves both stacks.
+    pub fn bind(&self) -> io::Result<mio::net::TcpListener> {
+        let socket = Socket::new(Domain::for_address(self.address), Type::STREAM, None)?;
+        if self.address.is_ipv6() {
+            socket.set_only_v6(false)?;
+        }
+        socket.set_reuse_address(true)?;
+        socket.bind(&self.address.into())?;
+        socket.listen(BACKLOG)?;
+        socket.set_nonblocking(true)?;
+        mio::net::TcpListener::from_std(socket.into())
+    }
+}
@@ -1,32 +1,62 @@
 #![allow(dead_code)]
 
-mod args;
-mod colors;
-mod connection;
-mod server;
-
-use crate::{
-    args::{Target, TargetArgs},
-    colors::{FG1, FG2},
-};
 use anyhow::{Context, Result, bail};
 use clap::Parser;
-use connection::{Connection, ConnectionError, ReadResult};
+use connection::{Connection, ConnectionError, ReadResult, SocketOptions};
+use events::EventBus;
+use ingests::IngestCatalog;
 use kube::Client;
-use mio::net::{TcpListener, TcpStream};
+use metrics::counter;
+use mio::net::TcpStream;
 use mio::*;
 use owo_colors::OwoColorize;
-use server::{Server, ServerResult};
+use pipelines::{LocalPipelines, PipelineConfig};
+use registry::StreamRegistry;
+use routes::{PublishPolicy, RoutingTable};
+use server::{CloseReason, Server, ServerResult};
 use slab::Slab;
+use std::io;
 use std::net::SocketAddr;
-use std::str::FromStr;
-use std::time::SystemTime;
-use std::{collections::HashSet, time::Duration};
+use std::time::Instant;
+use std::{collections::HashMap, time::Duration};
+use strim::{
+    PushOptions, admin,
+    admin::LocalStreams,
+    args,
+    args::{Target, TargetArgs},
+    bench,
+    colors::{FG1, FG2},
+    connection, control, events, ingests,
+    listeners::{ListenAddr, ListenerPolicy},
+    nats, pipelines, play, publish, registry, routes, server,
+    tuning::SessionTuning,
+    webhooks,
+};
 use strim_common::shutdown::shutdown_signal;
+use tokio::task::JoinHandle;
+use webhooks::{OnPublishHook, WebhookConfig};
+
+/// Tokens of the listeners count down from here, away from those of the
+/// connections.
+const FIRST_LISTENER: usize = usize::MAX - 1;
 
-const SERVER: Token = Token(usize::MAX - 1);
+/// Number of lifecycle events buffered per subscriber before new events
+/// are dropped.
+const EVENT_QUEUE_CAPACITY: usize = 1024;
 
-type ClosedTokens = HashSet<usize>;
+/// How often connections are checked against the configured timeouts.
+/// Matches the poll timeout so an idle server still evaluates them.
+const TIMEOUT_CHECK_INTERVAL: Duration = Duration::from_millis(100);
+
+/// How long a connection the server disconnected is kept open for its
+/// queued output, such as the `onStatus` explaining why, to be written.
+const LINGER_TIMEOUT: Duration = Duration::from_secs(5);
+
+type ClosedTokens = HashMap<usize, CloseReason>;
+
+/// Connections the server disconnected while output was still queued for
+/// them, with the reason they are closed and when to give up on writing.
+type Lingering = HashMap<usize, (CloseReason, Instant)>;
 enum EventResult {
     None,
     ReadResult(Box<ReadResult>),
@@ -41,18 +71,31 @@ struct PullOptions {
     target: String,
 }
 
+/// Connection timeouts evaluated on every poll tick. A zero duration
+/// disables the corresponding timeout.
 #[derive(Debug)]
-pub struct PushOptions {
-    host: String,
-    app: String,
-    source_stream: String,
-    target_stream: String,
+struct ConnectionTimeouts {
+    handshake: Duration,
+    idle: Duration,
+    stall: Duration,
 }
 
 #[derive(Debug)]
 struct AppOptions {
     log_io: bool,
+    socket: SocketOptions,
     push: Option<PushOptions>,
+    timeouts: ConnectionTimeouts,
+    stats_interval: Duration,
+    orphan_sweep_interval: Duration,
+    drain_timeout: Duration,
+}
+
+/// Progress of the drain started by a shutdown signal.
+struct Drain {
+    deadline: Instant,
+    /// Deletions of the `Strim`s created by this pod.
+    deletions: Vec<JoinHandle<()>>,
 }
 
 #[tokio::main]
@@ -61,6 +104,9 @@ async fn main() -> Result<()> {
     let cli = args::Cli::parse();
     match cli.command {
         args::Commands::Server(args) => run_server(args).await,
+        args::Commands::Publish(args) => publish::run(args).await,
+        args::Commands::Play(args) => play::run(args).await,
+        args::Commands::Bench(args) => bench::run(args).await,
     }
 }
 
@@ -70,31 +116,156 @@ async fn run_server(args: args::ServerArgs) -> Result<()> {
 
     // Create a kubernetes client using the default configuration.
     // In-cluster, the kubeconfig will be set by the service account.
-    let client: Client = Client::try_default()
-        .await
-        .expect("Expected a valid KUBECONFIG environment variable.");
+    let client =
+        if args.standalone {
+            println!(
+                "{}",
+                "🏝️ Running standalone, publishes are packaged on this host".color(FG1)
+            );
+            None
+        } else {
+            Some(Client::try_default().await.context(
+                "Failed to create Kubernetes client, pass --standalone to run without one",
+            )?)
+        };
+    let pod_ip = args
+        .pod_ip
+        .clone()
+        .unwrap_or_else(|| "127.0.0.1".to_string());
+    let pod_name = args.pod_name.clone().unwrap_or_else(|| "strim".to_string());
+    let pod_uid = args.pod_uid.clone().unwrap_or_default();
+    let namespace = args.namespace.clone().unwrap_or_default();
+
+    let mut events = EventBus::new(pod_name.clone());
+    if let Some(ref nats_url) = args.nats.nats_url {
+        let rx = events.subscribe(EVENT_QUEUE_CAPACITY);
+        nats::spawn_forwarder(nats_url, args.nats.nats_subject_prefix.clone(), rx)
+            .await
+            .context("Failed to start NATS event publisher")?;
+    }
+    let webhook_config = WebhookConfig {
+        secret: args.webhooks.webhook_secret.clone(),
+        timeout: args.webhooks.webhook_timeout,
+        max_attempts: args.webhooks.webhook_max_attempts,
+    };
+    for url in &args.webhooks.webhook_urls {
+        let rx = events.subscribe(args.webhooks.webhook_queue_capacity);
+        webhooks::spawn_worker(url.clone(), webhook_config.clone(), rx);
+    }
+    let control_events = args
+        .grpc_port
+        .map(|_| events.subscribe(EVENT_QUEUE_CAPACITY));
+    let on_publish = args.webhooks.on_publish_url.clone().map(|url| {
+        OnPublishHook::new(
+            url,
+            args.webhooks.webhook_secret.clone(),
+            args.webhooks.webhook_timeout,
+            args.webhooks.on_publish_fail_open,
+        )
+    });
 
-    let address = format!("0.0.0.0:{}", args.port).parse().unwrap();
-    let listener = TcpListener::bind(&address).unwrap();
-    let mut poll = Poll::new().unwrap();
+    let registry = if args.registry.stream_registry {
+        let pool = strim_common::redis::init_redis(&args.registry.redis).await;
+        let registry = StreamRegistry::new(
+            pool,
+            pod_name.clone(),
+            pod_ip.clone(),
+            args.port,
+            args.registry.stream_registry_ttl,
+        );
+        registry.spawn_heartbeat();
+        Some(registry)
+    } else {
+        None
+    };
+    let local_streams = LocalStreams::default();
+    if let Some(admin_port) = args.admin_port {
+        admin::spawn_admin_server(admin_port, registry.clone(), local_streams.clone())
+            .await
+            .context("Failed to start admin API")?;
+    }
 
+    let ingests = client
+        .clone()
+        .map(|client| IngestCatalog::spawn(client, &namespace));
+    let pipelines = args
+        .standalone
+        .then(|| LocalPipelines::new(pipeline_config(&args)));
+    let routes = match args.routes_file {
+        Some(ref path) => RoutingTable::load(path)?,
+        // Without a routing table, the webhook is only used if configured
+        None if on_publish.is_some() => RoutingTable::live_only(PublishPolicy::Webhook),
+        None => RoutingTable::live_only(PublishPolicy::Open),
+    };
+    routes.check_publish_hook(on_publish.is_some())?;
     println!(
-        "{}",
-        "🟢 strim server listening for RTMP connections".green()
+        "{}{}",
+        "🧭 Serving apps • apps=".color(FG1),
+        routes
+            .app_names()
+            .cloned()
+            .collect::<Vec<_>>()
+            .join(",")
+            .color(FG2),
     );
-    poll.register(&listener, SERVER, Ready::readable(), PollOpt::edge())
-        .unwrap();
+
+    let listen_addrs = match args.listen.is_empty() {
+        true => vec![ListenAddr {
+            address: SocketAddr::from(([0, 0, 0, 0], args.port)),
+            policy: ListenerPolicy::ALL,
+        }],
+        false => args.listen.clone(),
+    };
+    if !listen_addrs
+        .iter()
+        .any(|listen| listen.address.port() == args.port && listen.policy.play)
+    {
+        bail!(
+            "No listener allows playback on port {}, which other replicas and pipelines pull from",
+            args.port
+        );
+    }
+    let mut poll = Poll::new().context("Failed to create poll")?;
+    let mut listeners = Vec::with_capacity(listen_addrs.len());
+    for (index, listen) in listen_addrs.into_iter().enumerate() {
+        let listener = listen.bind().with_context(|| {
+            format!(
+                "Failed to listen for RTMP connections on {}",
+                listen.address
+            )
+        })?;
+        poll.register(
+            &listener,
+            Token(FIRST_LISTENER - index),
+            Ready::readable(),
+            PollOpt::edge(),
+        )
+        .context("Failed to register RTMP listener")?;
+        println!(
+            "{}{}{}{}",
+            "🟢 strim server listening for RTMP connections • address=".green(),
+            listen.address.to_string().green().dimmed(),
+            " • policy=".green(),
+            listen.policy.to_string().green().dimmed(),
+        );
+        listeners.push((listener, listen.policy));
+    }
 
     let mut server = Server::new(
         client,
-        args.pod_ip,
-        args.pod_name,
-        args.pod_uid,
-        args.namespace,
+        pod_ip,
+        pod_name,
+        pod_uid,
+        namespace,
         args.port,
         &app_options.push,
         args.target
-            .map(|target: TargetArgs| -> Result<Option<Target>> {
+            .map(|mut target: TargetArgs| -> Result<Option<Target>> {
+                // Standalone uploaders take their credentials from the
+                // environment rather than a Kubernetes secret
+                if args.standalone {
+                    target.secret.get_or_insert_default();
+                }
                 if target.bucket.as_ref().is_some_and(|b| !b.is_empty()) {
                     Ok(Some(
                         target
@@ -108,7 +279,30 @@ async fn run_server(args: args::ServerArgs) -> Result<()> {
             .transpose()
             .context("Failed to parse target configuration")?
             .flatten(),
+        events,
+        on_publish,
+        registry.clone(),
+        local_streams,
+        args.edge_relay,
+        routes,
+        ingests,
+        pipelines,
+        SessionTuning::from(&args.rtmp),
     );
+    server.spawn_revocation_watch();
+    if let (Some(grpc_port), Some(control_events)) = (args.grpc_port, control_events) {
+        control::spawn_grpc_server(
+            SocketAddr::new(args.grpc_bind_address, grpc_port),
+            args.grpc_token.clone(),
+            server.notifications(),
+            control_events,
+        )
+        .await
+        .context("Failed to start gRPC control plane")?;
+    }
+    if !app_options.orphan_sweep_interval.is_zero() {
+        server.sweep_orphans();
+    }
     let mut connection_count = 1;
     let mut connections = Slab::new();
 
@@ -149,60 +343,145 @@ async fn run_server(args: args::ServerArgs) -> Result<()> {
     //         pull.target.clone(),
     //     );
     // }
-    strim_common::signal_ready();
+    // Standalone hosts have no readiness probe, and /etc may not be writable
+    if !args.standalone {
+        strim_common::signal_ready();
+    }
 
     let mut events = Events::with_capacity(1024);
-    let mut outer_started_at = SystemTime::now();
+    let mut outer_started_at = Instant::now();
     let mut inner_started_at;
     let mut _total_ns = 0;
     let mut _poll_count = 0_u32;
+    let mut last_timeout_check_at = Instant::now();
+    let mut last_stats_at = Instant::now();
+    let mut last_orphan_sweep_at = Instant::now();
+    let mut drain: Option<Drain> = None;
+    let mut lingering = Lingering::new();
 
     loop {
-        if cancel.is_cancelled() {
-            bail!("Context cancelled");
+        if cancel.is_cancelled() && drain.is_none() {
+            strim_common::signal_draining();
+            for (listener, _) in &listeners {
+                poll.deregister(listener)
+                    .context("Failed to stop accepting RTMP connections")?;
+            }
+            println!(
+                "{}{}{}{}",
+                "🚰 Draining connections • connections=".color(FG1),
+                connections.len().to_string().color(FG2),
+                " • timeout=".color(FG1),
+                humantime::format_duration(app_options.drain_timeout)
+                    .to_string()
+                    .color(FG2),
+            );
+            let (results, deletions) = server.begin_drain();
+            drain = Some(Drain {
+                deadline: Instant::now() + app_options.drain_timeout,
+                deletions,
+            });
+            let closed_tokens = handle_server_results(
+                results,
+                &mut server,
+                &mut connections,
+                &mut poll,
+                &app_options,
+                &mut connection_count,
+            );
+            close_connections(closed_tokens, &mut connections, &mut server, &mut lingering);
+        }
+        close_flushed_connections(&mut lingering, &mut connections, &mut server);
+        if let Some(ref current) = drain
+            && (connections.is_empty() || Instant::now() >= current.deadline)
+        {
+            lingering.clear();
+            let remaining: Vec<usize> = connections.iter().map(|(token, _)| token).collect();
+            for token in remaining {
+                close_connection(token, CloseReason::Shutdown, &mut connections, &mut server);
+            }
+            return shutdown(registry, drain.take()).await;
         }
         poll.poll(&mut events, Some(Duration::from_millis(100)))
             .context("Failed to poll for RTMP events")?;
-        inner_started_at = SystemTime::now();
+        inner_started_at = Instant::now();
         _poll_count += 1;
         for event in events.iter() {
-            if cancel.is_cancelled() {
-                bail!("Context cancelled");
-            }
             let mut connections_to_close = ClosedTokens::new();
             match event.token() {
-                SERVER => {
-                    let (socket, _) = listener.accept().unwrap();
-                    println!(
-                        "{}{}{}{}",
-                        "🔌 Accepted new connection • peer_addr=".color(FG1),
-                        socket.peer_addr()?.to_string().color(FG2),
-                        " • local_addr=".color(FG1),
-                        socket.local_addr()?.to_string().color(FG2),
-                    );
-                    let connection =
-                        Connection::new(socket, connection_count, app_options.log_io, true);
-                    let token = connections.insert(connection);
-                    connection_count += 1;
-                    println!(
-                        "{}{}",
-                        "🔗 New connection • id=".color(FG1),
-                        token.to_string().color(FG2),
-                    );
-                    connections[token].token = Some(Token(token));
-                    connections[token].register(&mut poll).unwrap();
+                Token(token)
+                    if token <= FIRST_LISTENER && FIRST_LISTENER - token < listeners.len() =>
+                {
+                    let (ref listener, policy) = listeners[FIRST_LISTENER - token];
+                    loop {
+                        // The listener is edge-triggered, so accept until none are left
+                        let (socket, peer_addr) = match listener.accept() {
+                            Ok(accepted) => accepted,
+                            Err(e) if e.kind() == io::ErrorKind::WouldBlock => break,
+                            Err(e) => {
+                                eprintln!(
+                                    "{}{}",
+                                    "⚠️ Failed to accept connection • error=".yellow(),
+                                    e.to_string().yellow().dimmed(),
+                                );
+                                break;
+                            }
+                        };
+                        // IPv4 clients of a dual-stack listener appear as v4-mapped
+                        let peer_addr =
+                            SocketAddr::new(peer_addr.ip().to_canonical(), peer_addr.port());
+                        println!(
+                            "{}{}{}{}",
+                            "🔌 Accepted new connection • peer_addr=".color(FG1),
+                            peer_addr.to_string().color(FG2),
+                            " • local_addr=".color(FG1),
+                            socket
+                                .local_addr()
+                                .map(|addr| addr.to_string())
+                                .unwrap_or_default()
+                                .color(FG2),
+                        );
+                        let token = match open_connection(
+                            socket,
+                            true,
+                            &mut connections,
+                            &mut poll,
+                            &app_options,
+                            &mut connection_count,
+                        ) {
+                            Ok(token) => token,
+                            Err(e) => {
+                                eprintln!(
+                                    "{}{}{}{}",
+                                    "⚠️ Failed to set up connection • peer_addr=".yellow(),
+                                    peer_addr.to_string().yellow().dimmed(),
+                                    " • error=".yellow(),
+                                    e.to_string().yellow().dimmed(),
+                                );
+                                continue;
+                            }
+                        };
+                        println!(
+                            "{}{}",
+                            "🔗 New connection • id=".color(FG1),
+                            token.to_string().color(FG2),
+                        );
+                        server.connection_accepted(token, peer_addr, policy);
+                    }
                 }
 
                 Token(token) => {
                     match process_event(&event.readiness(), &mut connections, token, &mut poll) {
                         EventResult::None => (),
+                        // The server is done with the connection, only its
+                        // queued output is still being written
+                        EventResult::ReadResult(_) if lingering.contains_key(&token) => (),
                         EventResult::ReadResult(result) => {
                             match *result {
                                 ReadResult::HandshakingInProgress => (),
                                 ReadResult::NoBytesReceived => (),
-                                ReadResult::BytesReceived { buffer, byte_count } => {
+                                ReadResult::BytesReceived { bytes } => {
                                     connections_to_close = handle_read_bytes(
-                                        &buffer[..byte_count],
+                                        &bytes,
                                         token,
                                         &mut server,
                                         &mut connections,
@@ -212,11 +491,11 @@ async fn run_server(args: args::ServerArgs) -> Result<()> {
                                     );
                                 }
 
-                                ReadResult::HandshakeCompleted { buffer, byte_count } => {
+                                ReadResult::HandshakeCompleted { remaining_bytes } => {
                                     // Server will understand that the first call to
                                     // handle_read_bytes signifies that handshaking is completed
                                     connections_to_close = handle_read_bytes(
-                                        &buffer[..byte_count],
+                                        &remaining_bytes,
                                         token,
                                         &mut server,
                                         &mut connections,
@@ -229,25 +508,57 @@ async fn run_server(args: args::ServerArgs) -> Result<()> {
                         }
 
                         EventResult::DisconnectConnection => {
-                            connections_to_close.insert(token);
+                            connections_to_close.insert(token, CloseReason::Disconnected);
                         }
                     }
                 }
             }
 
-            for token in connections_to_close {
-                println!(
-                    "{}{}",
-                    "⚠️ Closing connection • id=".yellow(),
-                    token.to_string().yellow().dimmed()
-                );
-                connections.remove(token);
-                server.notify_connection_closed(token);
-            }
+            close_connections(
+                connections_to_close,
+                &mut connections,
+                &mut server,
+                &mut lingering,
+            );
+        }
+
+        let notification_results = server.handle_notifications();
+        if !notification_results.is_empty() {
+            let closed_tokens = handle_server_results(
+                notification_results,
+                &mut server,
+                &mut connections,
+                &mut poll,
+                &app_options,
+                &mut connection_count,
+            );
+            close_connections(closed_tokens, &mut connections, &mut server, &mut lingering);
+        }
+
+        if last_timeout_check_at.elapsed() >= TIMEOUT_CHECK_INTERVAL {
+            last_timeout_check_at = Instant::now();
+            let timed_out =
+                find_timed_out_connections(&connections, &server, &app_options.timeouts);
+            close_connections(timed_out, &mut connections, &mut server, &mut lingering);
+        }
+
+        if !app_options.stats_interval.is_zero()
+            && last_stats_at.elapsed() >= app_options.stats_interval
+        {
+            last_stats_at = Instant::now();
+            server.report_stats();
+        }
+
+        if drain.is_none()
+            && !app_options.orphan_sweep_interval.is_zero()
+            && last_orphan_sweep_at.elapsed() >= app_options.orphan_sweep_interval
+        {
+            last_orphan_sweep_at = Instant::now();
+            server.sweep_orphans();
         }
 
-        let inner_elapsed = inner_started_at.elapsed().unwrap();
-        let outer_elapsed = outer_started_at.elapsed().unwrap();
+        let inner_elapsed = inner_started_at.elapsed();
+        let outer_elapsed = outer_started_at.elapsed();
         _total_ns += inner_elapsed.subsec_nanos();
 
         if outer_elapsed.as_secs() >= 10 {
@@ -271,14 +582,162 @@ async fn run_server(args: args::ServerArgs) -> Result<()> {
             // Reset so each notification is per that interval
             _total_ns = 0;
             _poll_count = 0;
-            outer_started_at = SystemTime::now();
+            outer_started_at = Instant::now();
+        }
+    }
+}
+
+/// Waits for the `Strim` deletions started by the drain, until its
+/// deadline, and releases this pod's streams from the registry before
+/// exiting.
+async fn shutdown(registry: Option<StreamRegistry>, drain: Option<Drain>) -> Result<()> {
+    if let Some(drain) = drain {
+        let deletions = futures::future::join_all(drain.deletions);
+        let deadline = tokio::time::Instant::from_std(drain.deadline);
+        if tokio::time::timeout_at(deadline, deletions).await.is_err() {
+            eprintln!(
+                "{}",
+                "⚠️ Drain timed out before Strim resources were deleted".yellow()
+            );
         }
     }
+    if let Some(registry) = registry {
+        registry.release_all().await;
+    }
+    println!("{}", "👋 Drain complete, exiting".color(FG1));
+    Ok(())
 }
 
-fn get_app_options(_args: &args::ServerArgs) -> AppOptions {
+/// Closes connections and tells the server they are gone. Connections
+/// the server disconnected are kept open until their queued output, such
+/// as a final `onStatus` or the tail of a push, is written.
+fn close_connections(
+    connections_to_close: ClosedTokens,
+    connections: &mut Slab<Connection>,
+    server: &mut Server,
+    lingering: &mut Lingering,
+) {
+    for (token, reason) in connections_to_close {
+        if reason == CloseReason::ServerRequested
+            && connections
+                .get(token)
+                .is_some_and(Connection::has_pending_output)
+        {
+            lingering
+                .entry(token)
+                .or_insert((reason, Instant::now() + LINGER_TIMEOUT));
+            continue;
+        }
+        let reason = lingering
+            .remove(&token)
+            .map_or(reason, |(reason, _)| reason);
+        close_connection(token, reason, connections, server);
+    }
+}
+
+/// Closes the lingering connections whose output was written or whose
+/// linger timed out.
+fn close_flushed_connections(
+    lingering: &mut Lingering,
+    connections: &mut Slab<Connection>,
+    server: &mut Server,
+) {
+    let now = Instant::now();
+    let done: Vec<usize> = lingering
+        .iter()
+        .filter(|(token, (_, deadline))| {
+            now >= *deadline
+                || !connections
+                    .get(**token)
+                    .is_some_and(Connection::has_pending_output)
+        })
+        .map(|(token, _)| *token)
+        .collect();
+    for token in done {
+        if let Some((reason, _)) = lingering.remove(&token) {
+            close_connection(token, reason, connections, server);
+        }
+    }
+}
+
+fn close_connection(
+    token: usize,
+    reason: CloseReason,
+    connections: &mut Slab<Connection>,
+    server: &mut Server,
+) {
+    if connections.try_remove(token).is_none() {
+        return;
+    }
+    println!(
+        "{}{}{}{}",
+        "⚠️ Closing connection • id=".yellow(),
+        token.to_string().yellow().dimmed(),
+        " • reason=".yellow(),
+        reason.as_str().yellow().dimmed(),
+    );
+    server.notify_connection_closed(token, reason);
+}
+
+/// Returns the connections that exceeded the handshake, idle, or media
+/// stall timeout, along with the reason each one should be closed.
+fn find_timed_out_connections(
+    connections: &Slab<Connection>,
+    server: &Server,
+    timeouts: &ConnectionTimeouts,
+) -> ClosedTokens {
+    let mut timed_out = ClosedTokens::new();
+    for (token, connection) in connections.iter() {
+        if !connection.is_handshake_completed() {
+            if !timeouts.handshake.is_zero() && connection.age() > timeouts.handshake {
+                timed_out.insert(token, CloseReason::HandshakeTimeout);
+            }
+        } else if !timeouts.idle.is_zero() && connection.idle_for() > timeouts.idle {
+            timed_out.insert(token, CloseReason::IdleTimeout);
+        }
+    }
+    if !timeouts.stall.is_zero() {
+        for token in server.stalled_publishers(timeouts.stall) {
+            timed_out.entry(token).or_insert(CloseReason::MediaStall);
+        }
+    }
+    timed_out
+}
+
+fn pipeline_config(args: &args::ServerArgs) -> PipelineConfig {
+    let hls = &args.standalone_hls;
+    PipelineConfig {
+        hls_command: hls.hls_command.clone(),
+        uploader_command: hls.hls_uploader_command.clone(),
+        work_dir: hls
+            .hls_work_dir
+            .clone()
+            .unwrap_or_else(|| std::env::temp_dir().join("strim-hls")),
+        output_dir: hls.hls_output_dir.clone(),
+        nats: args
+            .nats
+            .nats_url
+            .clone()
+            .map(|url| (url, args.nats.nats_subject_prefix.clone())),
+    }
+}
+
+fn get_app_options(args: &args::ServerArgs) -> AppOptions {
     AppOptions {
         log_io: true,
+        socket: SocketOptions {
+            receive_buffer_size: args.rtmp.socket_receive_buffer_size,
+            send_buffer_size: args.rtmp.socket_send_buffer_size,
+            read_buffer_size: args.rtmp.read_buffer_size,
+        },
+        timeouts: ConnectionTimeouts {
+            handshake: args.handshake_timeout,
+            idle: args.idle_timeout,
+            stall: args.stall_timeout,
+        },
+        stats_interval: args.stats_interval,
+        orphan_sweep_interval: args.orphan_sweep_interval,
+        drain_timeout: args.drain_timeout,
         //pull: Some(PullOptions {
         //    host: format!("0.0.0.0:{}", args.port),
         //    app: "live".to_string(),
@@ -294,6 +753,33 @@ fn get_app_options(_args: &args::ServerArgs) -> AppOptions {
     }
 }
 
+/// Wraps a socket in a [`Connection`] registered with `poll`, returning its
+/// token.
+fn open_connection(
+    socket: TcpStream,
+    is_inbound: bool,
+    connections: &mut Slab<Connection>,
+    poll: &mut Poll,
+    app_options: &AppOptions,
+    connection_count: &mut usize,
+) -> io::Result<usize> {
+    let connection = Connection::new(
+        socket,
+        *connection_count,
+        app_options.log_io,
+        is_inbound,
+        app_options.socket,
+    )?;
+    let token = connections.insert(connection);
+    *connection_count += 1;
+    connections[token].token = Some(Token(token));
+    if let Err(e) = connections[token].register(poll) {
+        connections.remove(token);
+        return Err(e);
+    }
+    Ok(token)
+}
+
 fn process_event(
     event: &Ready,
     connections: &mut Slab<Connection>,
@@ -340,14 +826,46 @@ fn handle_read_bytes(
 ) -> ClosedTokens {
     let mut closed_tokens = ClosedTokens::new();
 
-    let mut server_results = match server.bytes_received(from_token, bytes) {
-        Ok(results) => results,
-        Err(error) => {
-            println!("Input caused the following server error: {}", error);
-            closed_tokens.insert(from_token);
-            return closed_tokens;
-        }
-    };
+    // A client's input must not take down the streams of every other client
+    let server_results =
+        match strim_common::contain_panic(|| server.bytes_received(from_token, bytes)) {
+            Ok(Ok(results)) => results,
+            Ok(Err(error)) => {
+                println!("Input caused the following server error: {}", error);
+                closed_tokens.insert(from_token, CloseReason::ProtocolError);
+                return closed_tokens;
+            }
+            Err(_) => {
+                eprintln!(
+                    "{}{}",
+                    "💥 Input caused a panic, closing connection • id=".red(),
+                    from_token.to_string().red().dimmed(),
+                );
+                counter!("strim_input_panics_total").increment(1);
+                closed_tokens.insert(from_token, CloseReason::ProtocolError);
+                return closed_tokens;
+            }
+        };
+
+    handle_server_results(
+        server_results,
+        server,
+        connections,
+        poll,
+        app_options,
+        connection_count,
+    )
+}
+
+fn handle_server_results(
+    mut server_results: Vec<ServerResult>,
+    server: &mut Server,
+    connections: &mut Slab<Connection>,
+    poll: &mut Poll,
+    app_options: &AppOptions,
+    connection_count: &mut usize,
+) -> ClosedTokens {
+    let mut closed_tokens = ClosedTokens::new();
 
     for result in server_results.drain(..) {
         match result {
@@ -355,39 +873,82 @@ fn handle_read_bytes(
                 target_connection_id,
                 packet,
             } => {
-                if let Some(connection) = connections.get_mut(target_connection_id) {
-                    connection.enqueue_packet(poll, packet).unwrap();
+                if let Some(connection) = connections.get_mut(target_connection_id)
+                    && let Err(e) = connection.enqueue_packet(poll, packet)
+                {
+                    println!(
+                        "Error occurred while queueing a packet for {}: {:?}",
+                        target_connection_id, e
+                    );
+                    closed_tokens.insert(target_connection_id, CloseReason::Disconnected);
                 }
             }
 
             ServerResult::DisconnectConnection { connection_id } => {
-                closed_tokens.insert(connection_id);
+                closed_tokens.insert(connection_id, CloseReason::ServerRequested);
             }
 
-            ServerResult::StartPushing => {
-                if let Some(ref push) = app_options.push {
-                    println!(
-                        "Starting push to rtmp://{}/{}/{}",
-                        push.host, push.app, push.target_stream
-                    );
-
-                    let mut push_host = push.host.clone();
-                    if !push_host.contains(":") {
-                        push_host += ":1935";
+            ServerResult::PushConnected { push_id, stream } => {
+                let stream = match TcpStream::from_stream(stream) {
+                    Ok(stream) => stream,
+                    Err(e) => {
+                        server.push_client_failed(push_id, &e.to_string());
+                        continue;
                     }
+                };
+                let token = match open_connection(
+                    stream,
+                    false,
+                    connections,
+                    poll,
+                    app_options,
+                    connection_count,
+                ) {
+                    Ok(token) => token,
+                    Err(e) => {
+                        server.push_client_failed(push_id, &e.to_string());
+                        continue;
+                    }
+                };
 
-                    let addr = SocketAddr::from_str(&push_host).unwrap();
-                    let stream = TcpStream::connect(&addr).unwrap();
-                    let connection =
-                        Connection::new(stream, *connection_count, app_options.log_io, false);
-                    let token = connections.insert(connection);
-                    *connection_count += 1;
-
-                    println!("Push client started with connection id {}", token);
-                    connections[token].token = Some(Token(token));
-                    connections[token].register(poll).unwrap();
-                    server.register_push_client(token);
-                }
+                println!("Push client started with connection id {}", token);
+                server.register_push_client(push_id, token);
+            }
+
+            ServerResult::RelayConnected {
+                app_name,
+                stream_key,
+                stream,
+            } => {
+                let stream = match TcpStream::from_stream(stream) {
+                    Ok(stream) => stream,
+                    Err(e) => {
+                        server.relay_client_failed(app_name, stream_key, &e.to_string());
+                        continue;
+                    }
+                };
+                let token = match open_connection(
+                    stream,
+                    false,
+                    connections,
+                    poll,
+                    app_options,
+                    connection_count,
+                ) {
+                    Ok(token) => token,
+                    Err(e) => {
+                        server.relay_client_failed(app_name, stream_key, &e.to_string());
+                        continue;
+                    }
+                };
+                println!(
+                    "{}{}{}{}",
+                    "🛰️ Relay client started • connection_id=".color(FG1),
+                    token.to_string().color(FG2),
+                    " • stream_key=".color(FG1),
+                    stream_key.color(FG2),
+                );
+                server.register_pull_client(token, app_name, stream_key.clone(), stream_key);
             }
         }
     }
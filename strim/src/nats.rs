@@ -0,0 +1,58 @@
+use anyhow::{Context, Result};
+use metrics::counter;
+use owo_colors::OwoColorize;
+use std::sync::Arc;
+use tokio::sync::mpsc;
+
+use crate::{
+    colors::{FG1, FG2},
+    events::Event,
+};
+
+/// Connects to NATS and spawns a task that publishes every event received
+/// on `rx` to `{subject_prefix}.{event subject}`.
+pub async fn spawn_forwarder(
+    url: &str,
+    subject_prefix: String,
+    mut rx: mpsc::Receiver<Arc<Event>>,
+) -> Result<()> {
+    let client = async_nats::ConnectOptions::new()
+        .retry_on_initial_connect()
+        .connect(url)
+        .await
+        .with_context(|| format!("Failed to connect to NATS at {}", url))?;
+    println!(
+        "{}{}{}{}",
+        "📡 Publishing lifecycle events to NATS • url=".color(FG1),
+        url.color(FG2),
+        " • subject_prefix=".color(FG1),
+        subject_prefix.color(FG2),
+    );
+    tokio::spawn(async move {
+        while let Some(event) = rx.recv().await {
+            let subject = format!("{}.{}", subject_prefix, event.kind.subject());
+            let payload = match serde_json::to_vec(&*event) {
+                Ok(payload) => payload,
+                Err(e) => {
+                    eprintln!(
+                        "{}{}",
+                        "❌ Failed to serialize event • error=".red(),
+                        format!("{:?}", e).red().dimmed(),
+                    );
+                    continue;
+                }
+            };
+            if let Err(e) = client.publish(subject.clone(), payload.into()).await {
+                counter!("strim_nats_publish_failures_total").increment(1);
+                eprintln!(
+                    "{}{}{}{}",
+                    "❌ Failed to publish event to NATS • subject=".red(),
+                    subject.red().dimmed(),
+                    " • error=".red(),
+                    format!("{:?}", e).red().dimmed(),
+                );
+            }
+        }
+    });
+    Ok(())
+}
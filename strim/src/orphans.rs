@@ -0,0 +1,121 @@
+use kube::{
+    Api, ResourceExt,
+    api::{DeleteParams, ListParams},
+};
+use owo_colors::OwoColorize;
+use std::{future::Future, time::Duration};
+use strim_types::Strim;
+
+use crate::{
+    colors::{FG1, FG2},
+    events::{EventBus, EventKind},
+    revocations::is_created_by,
+};
+
+/// Attempts of a `Strim` create or delete before giving up. Anything
+/// left behind is removed by the next orphan sweep.
+const MAX_ATTEMPTS: u32 = 5;
+
+/// Initial delay between attempts, doubled after every failure.
+const INITIAL_BACKOFF: Duration = Duration::from_millis(500);
+
+/// Upper bound for the delay between attempts.
+const MAX_BACKOFF: Duration = Duration::from_secs(30);
+
+/// Runs a Kubernetes API call, retrying it with exponential backoff while
+/// it fails with an error that may be transient.
+pub async fn with_backoff<T, F, Fut>(action: &str, name: &str, mut call: F) -> kube::Result<T>
+where
+    F: FnMut() -> Fut,
+    Fut: Future<Output = kube::Result<T>>,
+{
+    let mut backoff = INITIAL_BACKOFF;
+    let mut attempt = 1;
+    loop {
+        match call().await {
+            Err(e) if attempt < MAX_ATTEMPTS && is_transient(&e) => {
+                eprintln!(
+                    "{}{}{}{}{}{}{}{}",
+                    "⚠️ Strim ".yellow(),
+                    action.yellow(),
+                    " failed, retrying • name=".yellow(),
+                    name.yellow().dimmed(),
+                    " • attempt=".yellow(),
+                    attempt.to_string().yellow().dimmed(),
+                    " • error=".yellow(),
+                    e.to_string().yellow().dimmed(),
+                );
+                tokio::time::sleep(backoff).await;
+                backoff = (backoff * 2).min(MAX_BACKOFF);
+                attempt += 1;
+            }
+            result => return result,
+        }
+    }
+}
+
+/// Errors the API server may not return if asked again.
+fn is_transient(error: &kube::Error) -> bool {
+    match error {
+        kube::Error::Api(e) => e.code == 429 || e.code >= 500,
+        _ => true,
+    }
+}
+
+/// Lists the `Strim`s created by the pod with `pod_uid` that are not
+/// being deleted. Those whose publisher is gone are orphans, left behind
+/// when the strim process restarts or a delete call fails for good.
+/// Whether a publisher is gone is only decided once the list is in, as a
+/// `Strim` may be created while it is fetched.
+pub async fn list_candidates(api: &Api<Strim>, pod_uid: &str) -> Option<Vec<Strim>> {
+    let strims = match api.list(&ListParams::default()).await {
+        Ok(strims) => strims,
+        Err(e) => {
+            eprintln!(
+                "{}{}",
+                "❌ Failed to list Strim resources for orphans • error=".red(),
+                format!("{:?}", e).red().dimmed(),
+            );
+            return None;
+        }
+    };
+    Some(
+        strims
+            .into_iter()
+            .filter(|strim| {
+                is_created_by(strim, pod_uid) && strim.metadata.deletion_timestamp.is_none()
+            })
+            .collect(),
+    )
+}
+
+/// Deletes orphaned `Strim`s found by [`list_candidates`].
+pub async fn delete(api: Api<Strim>, orphans: Vec<Strim>, events: EventBus) {
+    let params = DeleteParams::default();
+    for strim in orphans {
+        let name = strim.name_any();
+        match with_backoff("delete", &name, || api.delete(&name, &params)).await {
+            Ok(_) => {
+                println!(
+                    "{}{}",
+                    "🧹 Deleted orphaned Strim resource • name=".color(FG1),
+                    name.color(FG2),
+                );
+                events.emit(EventKind::StrimDeleted {
+                    namespace: strim.namespace().unwrap_or_default(),
+                    name,
+                });
+            }
+            Err(kube::Error::Api(e)) if e.code == 404 => {}
+            Err(e) => {
+                eprintln!(
+                    "{}{}{}{}",
+                    "❌ Failed to delete orphaned Strim resource • name=".red(),
+                    name.red().dimmed(),
+                    " • error=".red(),
+                    format!("{:?}", e).red().dimmed(),
+                );
+            }
+        }
+    }
+}
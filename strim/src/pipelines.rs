@@ -0,0 +1,282 @@
+use anyhow::{Context, Result, bail};
+use owo_colors::OwoColorize;
+use std::{
+    collections::HashMap,
+    path::{Component, Path, PathBuf},
+    time::Duration,
+};
+use strim_types::{StrimRecording, StrimSpec};
+use tokio::{
+    process::{Child, Command},
+    task::JoinHandle,
+};
+
+use crate::colors::{FG1, FG2};
+
+/// Name of the playlist ffmpeg writes.
+const PLAYLIST: &str = "index.m3u8";
+
+/// Tells ffmpeg an appending recording's playlist is in place. Peggy
+/// writes it after downloading the playlist from S3.
+const RESTORED_MARKER: &str = ".restored";
+
+/// How long ffmpeg is given to exit on its own once its publish ends.
+const EXIT_TIMEOUT: Duration = Duration::from_secs(10);
+
+/// How long the uploader is given to upload the last segments after
+/// ffmpeg exits.
+const UPLOAD_GRACE: Duration = Duration::from_secs(5);
+
+/// Whether `segment` can name a directory under the local output
+/// directory, which stream keys and stable ids do once written locally.
+pub fn is_safe_path_segment(segment: &str) -> bool {
+    !segment.is_empty() && segment != "." && segment != ".." && !segment.contains(['/', '\\', '\0'])
+}
+
+/// The directory under `output_dir` a local target's `key_prefix` is
+/// written to. Prefixes that would leave `output_dir` are refused.
+fn local_hls_dir(output_dir: &Path, key_prefix: &str) -> Result<PathBuf> {
+    let prefix = Path::new(key_prefix);
+    let is_relative = prefix
+        .components()
+        .all(|component| matches!(component, Component::Normal(_)));
+    let hls_dir = output_dir.join(prefix);
+    if !is_relative || !hls_dir.starts_with(output_dir) {
+        bail!(
+            "Key prefix {:?} is outside the HLS output directory",
+            key_prefix
+        );
+    }
+    Ok(hls_dir)
+}
+
+/// How local HLS pipelines are spawned.
+pub struct PipelineConfig {
+    pub hls_command: String,
+    pub uploader_command: String,
+    pub work_dir: PathBuf,
+    pub output_dir: Option<PathBuf>,
+    /// NATS server and subject prefix the uploader reads cue points from.
+    pub nats: Option<(String, String)>,
+}
+
+struct LocalPipeline {
+    name: String,
+    ffmpeg: Child,
+    uploader: Option<Child>,
+    /// Directory segments were staged in for the uploader, removed once
+    /// it exits.
+    staging_dir: Option<PathBuf>,
+}
+
+/// Packages published streams into HLS on this host in standalone mode,
+/// doing the work of the pod the operator creates for a `Strim`. A target
+/// with an empty bucket stands for the local output directory.
+pub struct LocalPipelines {
+    config: PipelineConfig,
+    running: HashMap<usize, LocalPipeline>,
+}
+
+impl LocalPipelines {
+    pub fn new(config: PipelineConfig) -> Self {
+        LocalPipelines {
+            config,
+            running: HashMap::new(),
+        }
+    }
+
+    /// Whether streams without an S3 target are written to a local
+    /// directory.
+    pub fn writes_locally(&self) -> bool {
+        self.config.output_dir.is_some()
+    }
+
+    /// Connections whose publish has a pipeline running.
+    pub fn connection_ids(&self) -> Vec<usize> {
+        self.running.keys().copied().collect()
+    }
+
+    /// Starts packaging the stream described by `spec`, published on
+    /// `connection_id`.
+    pub fn start(&mut self, connection_id: usize, name: String, stable_id: &str, spec: &StrimSpec) {
+        match self.spawn(&name, stable_id, spec) {
+            Ok(pipeline) => {
+                println!(
+                    "{}{}{}{}",
+                    "🎬 Started local HLS pipeline • name=".color(FG1),
+                    name.color(FG2),
+                    " • source=".color(FG1),
+                    spec.source.internal_url.color(FG2),
+                );
+                if let Some(previous) = self.running.insert(connection_id, pipeline) {
+                    tokio::spawn(stop(previous));
+                }
+            }
+            Err(e) => eprintln!(
+                "{}{}{}{}",
+                "❌ Failed to start local HLS pipeline • name=".red(),
+                name.red().dimmed(),
+                " • error=".red(),
+                format!("{:?}", e).red().dimmed(),
+            ),
+        }
+    }
+
+    /// Stops the pipeline of the publish on `connection_id`, returning the
+    /// task waiting for its processes to exit.
+    pub fn stop(&mut self, connection_id: usize) -> Option<JoinHandle<()>> {
+        let pipeline = self.running.remove(&connection_id)?;
+        Some(tokio::spawn(stop(pipeline)))
+    }
+
+    fn spawn(&self, name: &str, stable_id: &str, spec: &StrimSpec) -> Result<LocalPipeline> {
+        let target = &spec.target;
+        let (hls_dir, staging_dir) = if target.bucket.is_empty() {
+            let output_dir = self
+                .config
+                .output_dir
+                .as_ref()
+                .context("No HLS output directory is configured")?;
+            (local_hls_dir(output_dir, &target.key_prefix)?, None)
+        } else {
+            let dir = self.config.work_dir.join(name);
+            (dir.clone(), Some(dir))
+        };
+        std::fs::create_dir_all(&hls_dir)
+            .with_context(|| format!("Failed to create HLS directory {:?}", hls_dir))?;
+
+        let recording = match spec.recording {
+            Some(StrimRecording::Record) => Some("record"),
+            Some(StrimRecording::Append) => Some("append"),
+            None => None,
+        };
+        if staging_dir.is_none() {
+            if recording == Some("append") {
+                // The playlist to append to is already in place
+                std::fs::write(hls_dir.join(RESTORED_MARKER), b"")
+                    .context("Failed to write restored marker")?;
+            } else {
+                // Start a new playlist rather than continuing the last publish's
+                let _ = std::fs::remove_file(hls_dir.join(PLAYLIST));
+            }
+        }
+
+        // Started first so an appending recording's playlist is restored
+        // while ffmpeg waits for it.
+        let uploader = match staging_dir {
+            Some(_) => Some(self.spawn_uploader(name, stable_id, &hls_dir, spec, recording)?),
+            None => None,
+        };
+
+        let mut ffmpeg = Command::new(&self.config.hls_command);
+        ffmpeg
+            .env("RTMP_URL", &spec.source.internal_url)
+            .env("HLS_DIR", &hls_dir)
+            .env_remove("METRICS_PORT")
+            .kill_on_drop(true);
+        if let Some(ref hls) = spec.hls {
+            if let Some(segment_duration) = hls.segment_duration {
+                ffmpeg.env("HLS_TIME", segment_duration.to_string());
+            }
+            if let Some(list_size) = hls.list_size {
+                ffmpeg.env("HLS_LIST_SIZE", list_size.to_string());
+            }
+        }
+        if let Some(recording) = recording {
+            ffmpeg.env("HLS_RECORDING", recording);
+        }
+        let ffmpeg = ffmpeg
+            .spawn()
+            .with_context(|| format!("Failed to run {}", self.config.hls_command))?;
+
+        Ok(LocalPipeline {
+            name: name.to_string(),
+            ffmpeg,
+            uploader,
+            staging_dir,
+        })
+    }
+
+    fn spawn_uploader(
+        &self,
+        name: &str,
+        stable_id: &str,
+        hls_dir: &Path,
+        spec: &StrimSpec,
+        recording: Option<&str>,
+    ) -> Result<Child> {
+        let target = &spec.target;
+        let mut uploader = Command::new(&self.config.uploader_command);
+        uploader
+            .env("NODE_ID", name)
+            .env("HLS_DIR", hls_dir)
+            .env("S3_BUCKET", &target.bucket)
+            .env("S3_REGION", &target.region)
+            .env("S3_KEY_PREFIX", &target.key_prefix)
+            .env_remove("METRICS_PORT")
+            .kill_on_drop(true);
+        if !target.endpoint.is_empty() {
+            uploader.env("S3_ENDPOINT", &target.endpoint);
+        }
+        if let Some(ref after) = target.delete_old_segments_after {
+            uploader.env("DELETE_OLD_SEGMENTS_AFTER", after);
+        }
+        if let Some(recording) = recording {
+            uploader.env("HLS_RECORDING", recording);
+        }
+        if let Some((ref url, ref subject_prefix)) = self.config.nats {
+            uploader
+                .env("NATS_URL", url)
+                .env("NATS_SUBJECT_PREFIX", subject_prefix)
+                .env("STABLE_ID", stable_id);
+        }
+        uploader
+            .spawn()
+            .with_context(|| format!("Failed to run {}", self.config.uploader_command))
+    }
+}
+
+/// Lets ffmpeg finish the stream, which ends with its publish, and the
+/// uploader upload what ffmpeg wrote last.
+async fn stop(mut pipeline: LocalPipeline) {
+    wait_or_terminate(&mut pipeline.ffmpeg, EXIT_TIMEOUT).await;
+    if let Some(mut uploader) = pipeline.uploader {
+        wait_or_terminate(&mut uploader, UPLOAD_GRACE).await;
+    }
+    if let Some(dir) = pipeline.staging_dir
+        && let Err(e) = tokio::fs::remove_dir_all(&dir).await
+    {
+        eprintln!(
+            "{}{}{}{}",
+            "⚠️ Failed to remove HLS staging directory • path=".yellow(),
+            dir.to_string_lossy().yellow().dimmed(),
+            " • error=".yellow(),
+            e.to_string().yellow().dimmed(),
+        );
+    }
+    println!(
+        "{}{}",
+        "🏁 Stopped local HLS pipeline • name=".color(FG1),
+        pipeline.name.color(FG2),
+    );
+}
+
+/// Waits up to `patience` for `child` to exit, then asks it to with
+/// SIGTERM, killing it if it is still running after [`EXIT_TIMEOUT`].
+async fn wait_or_terminate(child: &mut Child, patience: Duration) {
+    if tokio::time::timeout(patience, child.wait()).await.is_ok() {
+        return;
+    }
+    if let Some(pid) = child.id() {
+        // SAFETY: `pid` is a child of this process that was not reaped yet
+        unsafe {
+            libc::kill(pid as libc::pid_t, libc::SIGTERM);
+        }
+    }
+    if tokio::time::timeout(EXIT_TIMEOUT, child.wait())
+        .await
+        .is_err()
+    {
+        let _ = child.kill().await;
+    }
+}
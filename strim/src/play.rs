@@ -0,0 +1,142 @@
+use anyhow::{Context, Result};
+use owo_colors::OwoColorize;
+use rml_rtmp::sessions::{ClientSessionConfig, ClientSessionEvent};
+use std::fs::File;
+use std::io::BufWriter;
+use strim_common::shutdown::shutdown_signal;
+use tokio::time::{Duration, Instant, sleep_until};
+
+use crate::args::PlayArgs;
+use crate::client::RtmpClient;
+use crate::codecs;
+use crate::colors::{FG1, FG2};
+use crate::flv::{self, AUDIO_TAG, FlvWriter, SCRIPT_TAG, VIDEO_TAG};
+use crate::routes;
+
+/// What was received over some period.
+#[derive(Default)]
+struct Counts {
+    video_tags: u64,
+    audio_tags: u64,
+    keyframes: u64,
+    bytes: u64,
+}
+
+/// Watches a stream, writing it to an FLV file or printing what is
+/// received every second.
+pub async fn run(args: PlayArgs) -> Result<()> {
+    let source = routes::parse_stream_url(&args.url)?;
+    let mut writer = match args.output {
+        Some(ref path) => {
+            let file = File::create(path)
+                .with_context(|| format!("Failed to create {}", path.display()))?;
+            Some(FlvWriter::new(BufWriter::new(file))?)
+        }
+        None => None,
+    };
+    let mut client = RtmpClient::connect(&source, ClientSessionConfig::new()).await?;
+    client.play(&source.stream).await?;
+    println!("{}{}", "📥 Playing • url=".color(FG1), args.url.color(FG2));
+
+    let started_at = Instant::now();
+    let stop_at = args.duration.map(|duration| started_at + duration);
+    let mut second =
+        tokio::time::interval_at(started_at + Duration::from_secs(1), Duration::from_secs(1));
+    let shutdown = shutdown_signal();
+    tokio::pin!(shutdown);
+    let mut window = Counts::default();
+    let mut total = Counts::default();
+    let result = loop {
+        tokio::select! {
+            _ = second.tick() => {
+                if writer.is_none() {
+                    print_second(&window);
+                }
+                window = Counts::default();
+            }
+            _ = sleep_until(stop_at.unwrap_or(started_at)), if stop_at.is_some() => break Ok(()),
+            _ = &mut shutdown => break Ok(()),
+            readable = client.readable() => {
+                if let Err(e) = readable {
+                    break Err(e);
+                }
+                if let Err(e) = client.read().await {
+                    break Err(e);
+                }
+            }
+        }
+        let mut ended = false;
+        for event in client.take_events() {
+            let (tag_type, timestamp, data) = match event {
+                ClientSessionEvent::VideoDataReceived { timestamp, data } => {
+                    window.video_tags += 1;
+                    total.video_tags += 1;
+                    if codecs::is_video_keyframe(&data) {
+                        window.keyframes += 1;
+                        total.keyframes += 1;
+                    }
+                    (VIDEO_TAG, timestamp.value, data.to_vec())
+                }
+                ClientSessionEvent::AudioDataReceived { timestamp, data } => {
+                    window.audio_tags += 1;
+                    total.audio_tags += 1;
+                    (AUDIO_TAG, timestamp.value, data.to_vec())
+                }
+                ClientSessionEvent::StreamMetadataReceived { metadata } => {
+                    (SCRIPT_TAG, 0, flv::metadata_tag(&metadata))
+                }
+                ClientSessionEvent::UnhandleableOnStatusCode { code }
+                    if code == "NetStream.Play.UnpublishNotify"
+                        || code == "NetStream.Play.Stop" =>
+                {
+                    ended = true;
+                    continue;
+                }
+                _ => continue,
+            };
+            window.bytes += data.len() as u64;
+            total.bytes += data.len() as u64;
+            if let Some(ref mut writer) = writer {
+                writer.write_tag(tag_type, timestamp, &data)?;
+            }
+        }
+        if ended {
+            println!("{}", "🛑 Stream ended".color(FG1));
+            break Ok(());
+        }
+    };
+    if let Some(ref mut writer) = writer {
+        writer.flush()?;
+    }
+    println!(
+        "{}{}{}{}{}{}{}{}{}{}",
+        "✔️ Finished playing • duration=".color(FG1),
+        humantime::format_duration(Duration::from_millis(
+            started_at.elapsed().as_millis() as u64
+        ))
+        .color(FG2),
+        " • video_tags=".color(FG1),
+        total.video_tags.color(FG2),
+        " • audio_tags=".color(FG1),
+        total.audio_tags.color(FG2),
+        " • keyframes=".color(FG1),
+        total.keyframes.color(FG2),
+        " • bytes=".color(FG1),
+        total.bytes.color(FG2),
+    );
+    result
+}
+
+fn print_second(counts: &Counts) {
+    println!(
+        "{}{}{}{}{}{}{}{}",
+        "📊 Received • video_fps=".color(FG1),
+        counts.video_tags.color(FG2),
+        " • audio_tags=".color(FG1),
+        counts.audio_tags.color(FG2),
+        " • keyframes=".color(FG1),
+        counts.keyframes.color(FG2),
+        " • kbps=".color(FG1),
+        (counts.bytes * 8 / 1000).color(FG2),
+    );
+}
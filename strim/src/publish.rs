@@ -0,0 +1,107 @@
+use anyhow::{Context, Result, bail};
+use owo_colors::OwoColorize;
+use rml_rtmp::sessions::ClientSessionConfig;
+use tokio::time::{Duration, Instant, sleep_until};
+
+use crate::args::PublishArgs;
+use crate::client::RtmpClient;
+use crate::colors::{FG1, FG2};
+use crate::flv::{self, AUDIO_TAG, FlvTag, SCRIPT_TAG, VIDEO_TAG};
+use crate::routes;
+
+/// Spacing of the frames of a file too short to tell, matching 25 fps.
+const DEFAULT_FRAME_INTERVAL: u32 = 40;
+
+/// Streams an FLV file to an RTMP server, for smoke tests without an
+/// encoder.
+pub async fn run(args: PublishArgs) -> Result<()> {
+    let bytes = tokio::fs::read(&args.file)
+        .await
+        .with_context(|| format!("Failed to read {}", args.file.display()))?;
+    let tags =
+        flv::parse(&bytes).with_context(|| format!("Failed to parse {}", args.file.display()))?;
+    if tags.is_empty() {
+        bail!("{} does not contain any tags", args.file.display());
+    }
+    let destination = routes::parse_stream_url(&args.url)?;
+    let mut client = RtmpClient::connect(&destination, ClientSessionConfig::new()).await?;
+    client.publish(&destination.stream).await?;
+    println!(
+        "{}{}{}{}{}{}",
+        "📢 Publishing • url=".color(FG1),
+        args.url.color(FG2),
+        " • file=".color(FG1),
+        args.file.display().color(FG2),
+        " • tags=".color(FG1),
+        tags.len().color(FG2),
+    );
+
+    let first_timestamp = tags[0].timestamp;
+    let loop_duration = loop_duration(&tags);
+    let started_at = Instant::now();
+    let mut offset = 0_u64;
+    let mut loops = 0;
+    loop {
+        for tag in &tags {
+            let timestamp = offset + u64::from(tag.timestamp.saturating_sub(first_timestamp));
+            let deadline = started_at + Duration::from_millis(timestamp);
+            // Keep up with the acknowledgements and pings of the server
+            // while waiting for the tag to be due
+            loop {
+                tokio::select! {
+                    _ = sleep_until(deadline) => break,
+                    readable = client.readable() => {
+                        readable?;
+                        client.read().await?;
+                    }
+                }
+            }
+            let timestamp = timestamp as u32;
+            match tag.tag_type {
+                VIDEO_TAG => client.send_video(tag.data.clone(), timestamp).await?,
+                AUDIO_TAG => client.send_audio(tag.data.clone(), timestamp).await?,
+                SCRIPT_TAG => {
+                    if let Some(metadata) = flv::parse_metadata(&tag.data) {
+                        client.send_metadata(&metadata).await?;
+                    }
+                }
+                _ => {}
+            }
+        }
+        loops += 1;
+        if args.loops != 0 && loops >= args.loops {
+            break;
+        }
+        offset += loop_duration;
+        println!(
+            "{}{}",
+            "🔁 Looping file • loops=".color(FG1),
+            loops.color(FG2),
+        );
+    }
+    println!(
+        "{}{}",
+        "✔️ Finished publishing • loops=".color(FG1),
+        loops.color(FG2),
+    );
+    Ok(())
+}
+
+/// How far timestamps advance with each loop, so that the next one starts
+/// a frame after the last one ended.
+fn loop_duration(tags: &[FlvTag]) -> u64 {
+    let video: Vec<u32> = tags
+        .iter()
+        .filter(|tag| tag.tag_type == VIDEO_TAG)
+        .map(|tag| tag.timestamp)
+        .collect();
+    let frame_interval = video
+        .windows(2)
+        .map(|pair| pair[1].saturating_sub(pair[0]))
+        .filter(|interval| *interval > 0)
+        .min()
+        .unwrap_or(DEFAULT_FRAME_INTERVAL);
+    let first = tags.first().map_or(0, |tag| tag.timestamp);
+    let last = tags.iter().map(|tag| tag.timestamp).max().unwrap_or(first);
+    u64::from(last - first) + u64::from(frame_interval)
+}
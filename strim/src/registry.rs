@@ -0,0 +1,234 @@
+use anyhow::{Context, Result};
+use deadpool_redis::Pool;
+use owo_colors::OwoColorize;
+use redis::{AsyncCommands, Script};
+use serde::{Deserialize, Serialize};
+use std::{
+    collections::HashMap,
+    sync::{Arc, Mutex},
+    time::Duration,
+};
+
+use crate::colors::{FG1, FG2};
+
+/// Prefix of the keys holding one [`StreamRecord`] per live stream.
+const KEY_PREFIX: &str = "strim:streams:";
+
+/// Refreshes the TTL of a record, recreating it if it already expired,
+/// unless another pod has claimed the stream in the meantime.
+const HEARTBEAT_SCRIPT: &str = r#"
+local current = redis.call('GET', KEYS[1])
+if current and cjson.decode(current).pod_name ~= ARGV[2] then
+    return 0
+end
+redis.call('SET', KEYS[1], ARGV[1], 'PX', ARGV[3])
+return 1
+"#;
+
+/// Deletes a record only if it is owned by the given pod.
+const RELEASE_SCRIPT: &str = r#"
+local current = redis.call('GET', KEYS[1])
+if current and cjson.decode(current).pod_name == ARGV[1] then
+    return redis.call('DEL', KEYS[1])
+end
+return 0
+"#;
+
+/// Where a live stream is being published, as stored in Redis.
+#[derive(Serialize, Deserialize, Clone, Debug)]
+pub struct StreamRecord {
+    pub stable_id: String,
+    pub app_name: String,
+    pub pod_name: String,
+    pub pod_ip: String,
+    pub port: u16,
+    pub started_at: String,
+}
+
+fn key(stable_id: &str) -> String {
+    format!("{}{}", KEY_PREFIX, stable_id)
+}
+
+/// Cluster-wide registry of live streams, shared by every strim replica.
+///
+/// Each published stream is claimed under its `stable_id` with a TTL that
+/// is refreshed by a heartbeat, so the streams of a pod that dies without
+/// cleaning up expire on their own.
+#[derive(Clone)]
+pub struct StreamRegistry {
+    pool: Pool,
+    pod_name: String,
+    pod_ip: String,
+    port: u16,
+    ttl: Duration,
+    /// Serialized records of the streams claimed by this pod.
+    owned: Arc<Mutex<HashMap<String, String>>>,
+}
+
+impl StreamRegistry {
+    pub fn new(pool: Pool, pod_name: String, pod_ip: String, port: u16, ttl: Duration) -> Self {
+        Self {
+            pool,
+            pod_name,
+            pod_ip,
+            port,
+            ttl,
+            owned: Arc::new(Mutex::new(HashMap::new())),
+        }
+    }
+
+    fn ttl_ms(&self) -> u64 {
+        self.ttl.as_millis().max(1) as u64
+    }
+
+    /// Claims `stable_id` for this pod. Returns the current owner if the
+    /// stream is already being published anywhere in the cluster.
+    pub async fn claim(&self, stable_id: &str, app_name: &str) -> Result<Option<StreamRecord>> {
+        let record = StreamRecord {
+            stable_id: stable_id.to_string(),
+            app_name: app_name.to_string(),
+            pod_name: self.pod_name.clone(),
+            pod_ip: self.pod_ip.clone(),
+            port: self.port,
+            started_at: chrono::Utc::now().to_rfc3339(),
+        };
+        let value = serde_json::to_string(&record)?;
+        let mut conn = self.pool.get().await?;
+        let claimed: Option<String> = redis::cmd("SET")
+            .arg(key(stable_id))
+            .arg(&value)
+            .arg("NX")
+            .arg("PX")
+            .arg(self.ttl_ms())
+            .query_async(&mut conn)
+            .await
+            .context("Failed to claim stream")?;
+        if claimed.is_some() {
+            self.owned
+                .lock()
+                .unwrap()
+                .insert(stable_id.to_string(), value);
+            return Ok(None);
+        }
+        let current: Option<String> = conn.get(key(stable_id)).await?;
+        Ok(current.and_then(|current| serde_json::from_str(&current).ok()))
+    }
+
+    /// Removes the record of `stable_id` if it is owned by this pod.
+    pub async fn release(&self, stable_id: &str) -> Result<()> {
+        self.owned.lock().unwrap().remove(stable_id);
+        let mut conn = self.pool.get().await?;
+        let _: i64 = Script::new(RELEASE_SCRIPT)
+            .key(key(stable_id))
+            .arg(&self.pod_name)
+            .invoke_async(&mut conn)
+            .await
+            .context("Failed to release stream")?;
+        Ok(())
+    }
+
+    /// Releases every stream claimed by this pod. Called on shutdown.
+    pub async fn release_all(&self) {
+        let stable_ids: Vec<String> = self.owned.lock().unwrap().keys().cloned().collect();
+        for stable_id in stable_ids {
+            if let Err(e) = self.release(&stable_id).await {
+                eprintln!(
+                    "{}{}{}{}",
+                    "❌ Failed to release stream from registry • stable_id=".red(),
+                    stable_id.red().dimmed(),
+                    " • error=".red(),
+                    format!("{:?}", e).red().dimmed(),
+                );
+            }
+        }
+    }
+
+    /// Returns where `stable_id` is currently being published, if anywhere.
+    pub async fn lookup(&self, stable_id: &str) -> Result<Option<StreamRecord>> {
+        let mut conn = self.pool.get().await?;
+        let value: Option<String> = conn.get(key(stable_id)).await?;
+        Ok(value.and_then(|value| serde_json::from_str(&value).ok()))
+    }
+
+    /// Lists every live stream in the cluster.
+    pub async fn list(&self) -> Result<Vec<StreamRecord>> {
+        let mut conn = self.pool.get().await?;
+        let keys: Vec<String> = {
+            let mut iter = conn
+                .scan_match::<_, String>(format!("{}*", KEY_PREFIX))
+                .await?;
+            let mut keys = Vec::new();
+            while let Some(key) = iter.next_item().await {
+                keys.push(key);
+            }
+            keys
+        };
+        if keys.is_empty() {
+            return Ok(Vec::new());
+        }
+        let values: Vec<Option<String>> = conn.mget(&keys).await?;
+        Ok(values
+            .into_iter()
+            .flatten()
+            .filter_map(|value| serde_json::from_str(&value).ok())
+            .collect())
+    }
+
+    /// Spawns the task that keeps this pod's records alive.
+    pub fn spawn_heartbeat(&self) {
+        println!(
+            "{}{}{}{}",
+            "🗂️ Registering streams in Redis • pod_name=".color(FG1),
+            self.pod_name.color(FG2),
+            " • ttl=".color(FG1),
+            humantime::format_duration(self.ttl).to_string().color(FG2),
+        );
+        let registry = self.clone();
+        tokio::spawn(async move {
+            let mut interval =
+                tokio::time::interval((registry.ttl / 3).max(Duration::from_millis(100)));
+            loop {
+                interval.tick().await;
+                if let Err(e) = registry.heartbeat().await {
+                    eprintln!(
+                        "{}{}",
+                        "❌ Stream registry heartbeat failed • error=".red(),
+                        format!("{:?}", e).red().dimmed(),
+                    );
+                }
+            }
+        });
+    }
+
+    async fn heartbeat(&self) -> Result<()> {
+        let owned: Vec<(String, String)> = self
+            .owned
+            .lock()
+            .unwrap()
+            .iter()
+            .map(|(stable_id, value)| (stable_id.clone(), value.clone()))
+            .collect();
+        if owned.is_empty() {
+            return Ok(());
+        }
+        let mut conn = self.pool.get().await?;
+        for (stable_id, value) in owned {
+            let refreshed: i64 = Script::new(HEARTBEAT_SCRIPT)
+                .key(key(&stable_id))
+                .arg(&value)
+                .arg(&self.pod_name)
+                .arg(self.ttl_ms())
+                .invoke_async(&mut conn)
+                .await?;
+            if refreshed == 0 {
+                eprintln!(
+                    "{}{}",
+                    "⚠️ Stream was claimed by another pod • stable_id=".yellow(),
+                    stable_id.yellow().dimmed(),
+                );
+                self.owned.lock().unwrap().remove(&stable_id);
+            }
+        }
+        Ok(())
+    }
+}
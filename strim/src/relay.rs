@@ -0,0 +1,62 @@
+use anyhow::Result;
+use kube::{Api, Client, api::ListParams};
+use strim_common::annotations;
+use strim_types::Strim;
+
+use crate::registry::StreamRegistry;
+
+/// The strim pod a stream is being published to.
+#[derive(Debug, Clone)]
+pub struct Origin {
+    pub host: String,
+    pub port: u16,
+}
+
+/// Finds the pod hosting `stable_id`, first in the stream registry and then
+/// among the `Strim` resources in `namespace`.
+pub async fn find_origin(
+    registry: Option<&StreamRegistry>,
+    client: Option<Client>,
+    namespace: &str,
+    stable_id: &str,
+) -> Result<Option<Origin>> {
+    if let Some(registry) = registry
+        && let Some(record) = registry.lookup(stable_id).await?
+    {
+        return Ok(Some(Origin {
+            host: record.pod_ip,
+            port: record.port,
+        }));
+    }
+    // Without Kubernetes, only the registry knows where streams are
+    let Some(client) = client else {
+        return Ok(None);
+    };
+    let strim_api: Api<Strim> = Api::namespaced(client, namespace);
+    let strims = strim_api.list(&ListParams::default()).await?;
+    Ok(strims
+        .items
+        .into_iter()
+        .filter(|strim| {
+            strim
+                .metadata
+                .annotations
+                .as_ref()
+                .and_then(|a| a.get(annotations::STABLE_ID))
+                .is_some_and(|id| id == stable_id)
+        })
+        .find_map(|strim| parse_internal_url(&strim.spec.source.internal_url)))
+}
+
+/// Extracts the host and port from an `rtmp://host:port/app/stream` url.
+fn parse_internal_url(url: &str) -> Option<Origin> {
+    let authority = url.strip_prefix("rtmp://")?.split('/').next()?;
+    let (host, port) = match authority.rsplit_once(':') {
+        Some((host, port)) => (host, port.parse().ok()?),
+        None => (authority, 1935),
+    };
+    Some(Origin {
+        host: host.to_string(),
+        port,
+    })
+}
@@ -0,0 +1,94 @@
+use futures::StreamExt;
+use kube::{
+    Api, Client, ResourceExt,
+    runtime::{WatchStreamExt, watcher},
+};
+use owo_colors::OwoColorize;
+use strim_common::annotations;
+use strim_types::Strim;
+use tokio::sync::mpsc;
+
+use crate::{
+    colors::{FG1, FG2},
+    server::ServerNotification,
+};
+
+/// Why a `Strim` created by this pod should no longer be streamed.
+#[derive(Debug)]
+pub enum StrimRevocation {
+    Deleted,
+    /// Carries the value of the moderation annotation.
+    Moderated(String),
+}
+
+/// Watches the `Strim` resources created by the pod with `pod_uid`,
+/// notifying the server when one is deleted or moderated.
+pub fn spawn(
+    client: Client,
+    namespace: &str,
+    pod_uid: String,
+    notifications_tx: mpsc::UnboundedSender<ServerNotification>,
+) {
+    println!(
+        "{}{}",
+        "👀 Watching Strim resources for revocations • namespace=".color(FG1),
+        namespace.color(FG2),
+    );
+    let api: Api<Strim> = Api::namespaced(client, namespace);
+    let stream = watcher(api, watcher::Config::default()).default_backoff();
+    tokio::spawn(async move {
+        futures::pin_mut!(stream);
+        while let Some(result) = stream.next().await {
+            let (strim, deleted) = match result {
+                Ok(watcher::Event::Apply(strim) | watcher::Event::InitApply(strim)) => {
+                    (strim, false)
+                }
+                Ok(watcher::Event::Delete(strim)) => (strim, true),
+                Ok(watcher::Event::Init | watcher::Event::InitDone) => continue,
+                Err(e) => {
+                    eprintln!(
+                        "{}{}",
+                        "❌ Strim watch failed • error=".red(),
+                        format!("{:?}", e).red().dimmed(),
+                    );
+                    continue;
+                }
+            };
+            if !is_created_by(&strim, &pod_uid) {
+                continue;
+            }
+            let reason = if deleted {
+                StrimRevocation::Deleted
+            } else {
+                match strim
+                    .annotations()
+                    .get(annotations::MODERATION)
+                    .filter(|moderation| !moderation.is_empty())
+                {
+                    Some(moderation) => StrimRevocation::Moderated(moderation.clone()),
+                    None => continue,
+                }
+            };
+            let notification = ServerNotification::StrimRevoked {
+                name: strim.name_any(),
+                reason,
+            };
+            if notifications_tx.send(notification).is_err() {
+                break;
+            }
+        }
+    });
+}
+
+/// Whether `strim` was created by the strim server in the pod with
+/// `pod_uid`.
+pub fn is_created_by(strim: &Strim, pod_uid: &str) -> bool {
+    strim
+        .annotations()
+        .get(annotations::CREATED_BY)
+        .is_some_and(|created_by| created_by == "strim")
+        && strim
+            .owner_references()
+            .iter()
+            .any(|owner| owner.uid == pod_uid)
+}
@@ -0,0 +1,255 @@
+use anyhow::{Context, Result, bail};
+use serde::{Deserialize, Serialize};
+use std::collections::HashMap;
+use strim_types::StrimHls;
+
+use crate::args::MAX_CHUNK_SIZE;
+
+/// Name of the app served when no routing table is configured.
+const DEFAULT_APP: &str = "live";
+
+/// Who may publish to an app.
+#[derive(Deserialize, Debug, Clone, Copy, Default, PartialEq, Eq)]
+#[serde(rename_all = "camelCase")]
+pub enum PublishPolicy {
+    /// Any publisher is accepted.
+    Open,
+    /// Publishers are authorized by the `on_publish` webhook. The server
+    /// refuses to start if an app has this policy and no webhook is
+    /// configured.
+    #[default]
+    Webhook,
+    /// Every publish is rejected.
+    Closed,
+}
+
+/// What happens when a publisher exceeds an app's ingest limits.
+#[derive(Deserialize, Serialize, Debug, Clone, Copy, Default, PartialEq, Eq)]
+#[serde(rename_all = "camelCase")]
+pub enum LimitAction {
+    /// The violation is logged, counted and raised as an event.
+    #[default]
+    Warn,
+    /// As with `Warn`, and the publisher is sent an error status and
+    /// disconnected.
+    Disconnect,
+}
+
+/// Caps on what publishers may send to an app. Each limit is checked
+/// against the stream's `onMetaData`, its sequence headers and the
+/// measured ingest bitrate.
+#[derive(Deserialize, Debug, Clone, Default)]
+#[serde(rename_all = "camelCase", deny_unknown_fields)]
+pub struct IngestLimits {
+    /// Combined audio and video bitrate.
+    pub max_bitrate_kbps: Option<u32>,
+    pub max_width: Option<u32>,
+    pub max_height: Option<u32>,
+    pub max_frame_rate: Option<f64>,
+    /// Video codecs publishers may use, e.g. `h264` or `hevc`. Any codec
+    /// is allowed when empty.
+    #[serde(default)]
+    pub video_codecs: Vec<String>,
+    /// Audio codecs publishers may use, e.g. `aac`. Any codec is allowed
+    /// when empty.
+    #[serde(default)]
+    pub audio_codecs: Vec<String>,
+    #[serde(default)]
+    pub action: LimitAction,
+}
+
+/// Storage target overriding the server-wide `TARGET_*` settings.
+#[derive(Deserialize, Debug, Clone)]
+#[serde(rename_all = "camelCase", deny_unknown_fields)]
+pub struct AppTarget {
+    pub bucket: String,
+    pub endpoint: String,
+    pub region: String,
+    pub secret: String,
+    #[serde(default)]
+    pub key_prefix: String,
+    pub delete_old_segments_after: Option<String>,
+}
+
+/// An RTMP server that streams published to an app are pushed to.
+#[derive(Debug, Clone)]
+pub struct PushDestination {
+    /// `host:port` of the RTMP server.
+    pub host: String,
+    pub app: String,
+    /// Stream key to publish as. `{stable_id}` is replaced with the
+    /// stable id of the source stream.
+    pub stream: String,
+}
+
+impl<'de> Deserialize<'de> for PushDestination {
+    fn deserialize<D: serde::Deserializer<'de>>(deserializer: D) -> Result<Self, D::Error> {
+        let url = String::deserialize(deserializer)?;
+        parse_push_url(&url).map_err(serde::de::Error::custom)
+    }
+}
+
+/// Parses `rtmp://host[:port]/app/stream` into a [`PushDestination`].
+pub fn parse_push_url(url: &str) -> Result<PushDestination> {
+    let rest = url
+        .strip_prefix("rtmp://")
+        .with_context(|| format!("Push url '{}' must start with rtmp://", url))?;
+    let mut parts = rest.splitn(3, '/');
+    let (Some(host), Some(app), Some(stream)) = (parts.next(), parts.next(), parts.next()) else {
+        bail!("Push url '{}' must be rtmp://host[:port]/app/stream", url);
+    };
+    if host.is_empty() || app.is_empty() || stream.is_empty() {
+        bail!("Push url '{}' must be rtmp://host[:port]/app/stream", url);
+    }
+    let host = if host.contains(':') {
+        host.to_string()
+    } else {
+        format!("{}:1935", host)
+    };
+    Ok(PushDestination {
+        host,
+        app: app.to_string(),
+        stream: stream.to_string(),
+    })
+}
+
+/// Overrides of the server-wide RTMP settings for the pushes of an app, for
+/// destinations that are picky about how they are connected to.
+#[derive(Deserialize, Debug, Clone, Default)]
+#[serde(rename_all = "camelCase", deny_unknown_fields)]
+pub struct PushSession {
+    pub chunk_size: Option<u32>,
+    pub window_ack_size: Option<u32>,
+    pub flash_version: Option<String>,
+    /// `{host}` and `{app}` are replaced with those of the destination.
+    pub tc_url: Option<String>,
+}
+
+/// Parses a stream url the way encoders do, taking its last segment as
+/// the stream key, so that `rtmp://host/live/{stable_id}/{key}` connects
+/// to the `live/{stable_id}` app.
+pub fn parse_stream_url(url: &str) -> Result<PushDestination> {
+    let mut destination = parse_push_url(url)?;
+    if let Some((prefix, key)) = destination.stream.rsplit_once('/') {
+        if prefix.is_empty() || key.is_empty() {
+            bail!("Stream url '{}' must be rtmp://host[:port]/app/stream", url);
+        }
+        destination.app = format!("{}/{}", destination.app, prefix);
+        destination.stream = key.to_string();
+    }
+    Ok(destination)
+}
+
+/// How streams published to one app are handled.
+#[derive(Deserialize, Debug, Clone)]
+#[serde(rename_all = "camelCase", deny_unknown_fields)]
+pub struct AppRoute {
+    /// Whether a `Strim` resource is created for each published stream.
+    #[serde(default = "default_create_strim")]
+    pub create_strim: bool,
+
+    #[serde(default)]
+    pub publish: PublishPolicy,
+
+    /// Falls back to the server-wide target when unset.
+    pub target: Option<AppTarget>,
+
+    pub hls: Option<StrimHls>,
+
+    #[serde(default)]
+    pub push: Vec<PushDestination>,
+
+    pub push_session: Option<PushSession>,
+
+    pub limits: Option<IngestLimits>,
+}
+
+impl Default for AppRoute {
+    fn default() -> Self {
+        Self {
+            create_strim: default_create_strim(),
+            publish: PublishPolicy::default(),
+            target: None,
+            hls: None,
+            push: Vec::new(),
+            push_session: None,
+            limits: None,
+        }
+    }
+}
+
+fn default_create_strim() -> bool {
+    true
+}
+
+/// The apps this server accepts connections for. Connections to any other
+/// app are rejected.
+#[derive(Deserialize, Debug, Clone)]
+#[serde(deny_unknown_fields)]
+pub struct RoutingTable {
+    apps: HashMap<String, AppRoute>,
+}
+
+impl RoutingTable {
+    /// Serves only the `live` app, with default settings and the given
+    /// publish policy.
+    pub fn live_only(publish: PublishPolicy) -> Self {
+        let route = AppRoute {
+            publish,
+            ..AppRoute::default()
+        };
+        Self {
+            apps: HashMap::from([(DEFAULT_APP.to_string(), route)]),
+        }
+    }
+
+    /// Loads the routing table from a YAML (or JSON) file, such as a
+    /// mounted ConfigMap.
+    pub fn load(path: &str) -> Result<Self> {
+        let contents = std::fs::read_to_string(path)
+            .with_context(|| format!("Failed to read routing table from {}", path))?;
+        let table: RoutingTable = serde_yaml::from_str(&contents)
+            .with_context(|| format!("Failed to parse routing table from {}", path))?;
+        if table.apps.is_empty() {
+            bail!("Routing table {} does not define any apps", path);
+        }
+        for (app_name, route) in &table.apps {
+            if let Some(chunk_size) = route.push_session.as_ref().and_then(|s| s.chunk_size)
+                && !(128..=MAX_CHUNK_SIZE).contains(&chunk_size)
+            {
+                bail!(
+                    "Push chunk size {} of app {} must be between 128 and {}",
+                    chunk_size,
+                    app_name,
+                    MAX_CHUNK_SIZE
+                );
+            }
+        }
+        Ok(table)
+    }
+
+    /// Fails if an app is authorized by the `on_publish` webhook while none
+    /// is configured, rather than accepting every publisher to it.
+    pub fn check_publish_hook(&self, has_publish_hook: bool) -> Result<()> {
+        if has_publish_hook {
+            return Ok(());
+        }
+        for (app_name, route) in &self.apps {
+            if route.publish == PublishPolicy::Webhook {
+                bail!(
+                    "App {} authorizes publishes with the on_publish webhook, but ON_PUBLISH_URL is not set",
+                    app_name
+                );
+            }
+        }
+        Ok(())
+    }
+
+    pub fn get(&self, app_name: &str) -> Option<&AppRoute> {
+        self.apps.get(app_name)
+    }
+
+    pub fn app_names(&self) -> impl Iterator<Item = &String> {
+        self.apps.keys()
+    }
+}
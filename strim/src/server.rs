@@ -1,30 +1,56 @@
 use super::{
     PushOptions,
+    admin::{LocalStream, LocalStreams},
     args::Target,
+    codecs::{self, MediaInfo},
     colors::{FG1, FG2},
+    control::{ControlRequest, proto},
+    data::{self, DataMessage, DataTap, Received},
+    events::{CodecInfo, EventBus, EventKind},
+    ingests::IngestCatalog,
+    limits::IngestMeter,
+    listeners::ListenerPolicy,
+    orphans,
+    pipelines::{self, LocalPipelines},
+    registry::StreamRegistry,
+    relay::{self, Origin},
+    revocations::{self, StrimRevocation},
+    routes::{self, AppRoute, LimitAction, PublishPolicy, PushDestination, RoutingTable},
+    tuning::SessionTuning,
+    webhooks::{OnPublishHook, PublishAuthorizationRequest},
 };
 //use base64::{Engine as _, engine::general_purpose::URL_SAFE_NO_PAD};
 use bytes::Bytes;
 use k8s_openapi::apimachinery::pkg::apis::meta::v1::{ObjectMeta, OwnerReference};
-use kube::{Api, Client};
+use kube::{
+    Api, Client, ResourceExt,
+    api::{DeleteParams, Patch, PatchParams, PostParams},
+};
+use metrics::{counter, gauge};
 use owo_colors::OwoColorize;
 use rml_rtmp::chunk_io::Packet;
 use rml_rtmp::sessions::{
     ClientSession, ClientSessionConfig, ClientSessionEvent, ClientSessionResult,
 };
-use rml_rtmp::sessions::{PublishRequestType, StreamMetadata};
-use rml_rtmp::sessions::{
-    ServerSession, ServerSessionConfig, ServerSessionEvent, ServerSessionResult,
-};
+use rml_rtmp::sessions::{PublishMode, PublishRequestType, StreamMetadata};
+use rml_rtmp::sessions::{ServerSession, ServerSessionEvent, ServerSessionResult};
 use rml_rtmp::time::RtmpTimestamp;
-use serde::Deserialize;
+use serde::{Deserialize, Serialize};
 use sha2::Digest;
 use slab::Slab;
 use std::collections::{BTreeMap, HashMap, HashSet};
+use std::io;
+use std::net::SocketAddr;
 use std::rc::Rc;
+use std::time::{Duration, Instant};
 use strim_common::annotations;
-use strim_types::{Strim, StrimSource, StrimSpec, StrimTarget};
+use strim_types::{
+    Strim, StrimPushStats, StrimRecording, StrimSource, StrimSpec, StrimStats, StrimTarget,
+};
+use tokio::{sync::mpsc, task::JoinHandle};
+use tonic::Status;
 
+#[allow(dead_code)]
 #[derive(Deserialize, Clone, Debug)]
 struct StreamKeyPayload {
     pub stream_key: String,
@@ -60,6 +86,9 @@ struct InboundClient {
     current_action: InboundClientAction,
     connection_id: usize,
     has_received_video_keyframe: bool,
+    data_tap: DataTap,
+    /// Size of the chunks the session sends to the peer.
+    chunk_size: u32,
 }
 
 impl InboundClient {
@@ -90,25 +119,177 @@ struct PullClient {
     pull_stream: String,
     pull_target_stream: String,
     state: PullState,
+    data_tap: DataTap,
 }
 
-#[derive(PartialEq, Clone, Debug)]
-enum PushState {
+#[derive(PartialEq, Clone, Debug, Serialize)]
+pub enum PushState {
     Inactive,
     WaitingForConnection,
     Handshaking,
     Connecting,
     Connected,
     Pushing,
+    /// The connection was lost or could not be established, and is retried
+    /// after a backoff.
+    Reconnecting,
+}
+
+impl PushState {
+    pub fn as_str(&self) -> &'static str {
+        match self {
+            PushState::Inactive => "inactive",
+            PushState::WaitingForConnection => "waiting_for_connection",
+            PushState::Reconnecting => "reconnecting",
+            PushState::Handshaking => "handshaking",
+            PushState::Connecting => "connecting",
+            PushState::Connected => "connected",
+            PushState::Pushing => "pushing",
+        }
+    }
 }
 
 struct PushClient {
     session: Option<ClientSession>,
     connection_id: Option<usize>,
+    /// `host:port` of the RTMP server being pushed to.
+    push_host: String,
     push_app: String,
     push_source_stream: String,
     push_target_stream: String,
     state: PushState,
+    /// Stream the session publishes on, learned from the first media it
+    /// sends.
+    stream_id: Option<u32>,
+    /// Size of the chunks the session sends to the peer.
+    chunk_size: u32,
+    /// Config the session is created with on each connection attempt.
+    session_config: ClientSessionConfig,
+    /// Distinguishes this push's connection attempts from those of a later
+    /// push reusing its id.
+    ticket: u64,
+    /// Connection attempts that failed since the push last got to
+    /// [`PushState::Pushing`].
+    failed_attempts: u32,
+    /// Set when the push was stopped on request, so its connection closing
+    /// does not start a reconnection.
+    stopping: bool,
+}
+
+impl PushClient {
+    fn url(&self) -> String {
+        format!(
+            "rtmp://{}/{}/{}",
+            self.push_host, self.push_app, self.push_target_stream
+        )
+    }
+
+    fn set_state(&mut self, state: PushState, events: &EventBus) {
+        self.state = state;
+        events.emit(EventKind::PushStateChanged {
+            app_name: self.push_app.clone(),
+            host: self.push_host.clone(),
+            target_stream: self.push_target_stream.clone(),
+            state: self.state.clone(),
+            failed_attempts: self.failed_attempts,
+        });
+    }
+
+    /// Closes the connection of a push whose session failed, which
+    /// reconnects it.
+    fn abort(
+        &self,
+        action: &str,
+        error: &dyn std::fmt::Debug,
+        server_results: &mut Vec<ServerResult>,
+    ) {
+        eprintln!(
+            "{}{}{}{}{}{}",
+            "❌ Push failed • url=".red(),
+            self.url().red().dimmed(),
+            " • action=".red(),
+            action.red().dimmed(),
+            " • error=".red(),
+            format!("{:?}", error).red().dimmed(),
+        );
+        if let Some(connection_id) = self.connection_id {
+            server_results.push(ServerResult::DisconnectConnection { connection_id });
+        }
+    }
+
+    /// Forgets the session of a connection that closed.
+    fn reset_session(&mut self) {
+        self.session = None;
+        self.connection_id = None;
+        self.stream_id = None;
+        self.chunk_size = 0;
+    }
+}
+
+/// Delay before the first reconnection of a push. Doubled after each
+/// failed attempt.
+const PUSH_INITIAL_BACKOFF: Duration = Duration::from_secs(1);
+
+/// Longest delay between reconnections of a push.
+const PUSH_MAX_BACKOFF: Duration = Duration::from_secs(30);
+
+/// How long resolving and connecting to a push destination may take.
+const PUSH_CONNECT_TIMEOUT: Duration = Duration::from_secs(10);
+
+/// Delay before the origin of a relayed stream is looked up again after
+/// the relay failed. Doubled after each failed attempt.
+const RELAY_INITIAL_BACKOFF: Duration = Duration::from_secs(1);
+
+/// Longest delay between attempts to relay a stream.
+const RELAY_MAX_BACKOFF: Duration = Duration::from_secs(30);
+
+/// How long resolving and connecting to the origin of a relay may take.
+const RELAY_CONNECT_TIMEOUT: Duration = Duration::from_secs(10);
+
+/// Number of audio tags after which a stream that has not sent any video
+/// and did not declare its tracks is treated as audio-only (about two
+/// seconds of AAC).
+const AUDIO_ONLY_AFTER_TAGS: u32 = 100;
+
+/// Which tracks a stream carries, as declared by its metadata and
+/// observed from the tags received so far.
+#[derive(Default)]
+struct TrackLayout {
+    /// Whether `onMetaData` declared a video track. `None` when the
+    /// metadata said nothing about either track.
+    declared_video: Option<bool>,
+    video_seen: bool,
+    /// Audio tags received before any video.
+    audio_tags: u32,
+}
+
+impl TrackLayout {
+    fn metadata_received(&mut self, metadata: &StreamMetadata) {
+        let video = metadata.video_codec_id.is_some()
+            || metadata.video_width.is_some()
+            || metadata.video_height.is_some();
+        let audio = metadata.audio_codec_id.is_some()
+            || metadata.audio_sample_rate.is_some()
+            || metadata.audio_channels.is_some();
+        self.declared_video = (video || audio).then_some(video);
+    }
+
+    fn tag_received(&mut self, data_type: &ReceivedDataType) {
+        match data_type {
+            ReceivedDataType::Video => self.video_seen = true,
+            ReceivedDataType::Audio if !self.video_seen => {
+                self.audio_tags = self.audio_tags.saturating_add(1)
+            }
+            ReceivedDataType::Audio => {}
+        }
+    }
+
+    /// Audio-only streams have no keyframe to wait for, so their audio is
+    /// delivered to watchers straight away.
+    fn is_audio_only(&self) -> bool {
+        !self.video_seen
+            && (self.declared_video == Some(false) || self.audio_tags >= AUDIO_ONLY_AFTER_TAGS)
+    }
 }
 
 struct MediaChannel {
@@ -117,6 +298,134 @@ struct MediaChannel {
     metadata: Option<Rc<StreamMetadata>>,
     video_sequence_header: Option<Bytes>,
     audio_sequence_header: Option<Bytes>,
+    last_media_received_at: Option<Instant>,
+    app_name: Option<String>,
+    stable_id: Option<String>,
+    /// Connection of the pull client relaying this stream from its origin.
+    relay_connection_id: Option<usize>,
+    tracks: TrackLayout,
+    /// Parsed from the cached sequence headers.
+    media: MediaInfo,
+    ingest: IngestMeter,
+    published_at: Option<chrono::DateTime<chrono::Utc>>,
+}
+
+impl MediaChannel {
+    fn new() -> Self {
+        MediaChannel {
+            publishing_client_id: None,
+            watching_client_ids: HashSet::new(),
+            metadata: None,
+            video_sequence_header: None,
+            audio_sequence_header: None,
+            last_media_received_at: None,
+            app_name: None,
+            stable_id: None,
+            relay_connection_id: None,
+            tracks: TrackLayout::default(),
+            media: MediaInfo::default(),
+            ingest: IngestMeter::default(),
+            published_at: None,
+        }
+    }
+}
+
+/// Why a connection was closed. Recorded in the logs and the
+/// `strim_connections_closed_total` metric.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize)]
+#[serde(rename_all = "snake_case")]
+pub enum CloseReason {
+    /// The peer closed the socket or a socket error occurred.
+    Disconnected,
+
+    /// The peer sent input the RTMP session could not process.
+    ProtocolError,
+
+    /// The server decided to drop the connection, e.g. a rejected publish.
+    ServerRequested,
+
+    /// The connection did not complete the RTMP handshake in time.
+    HandshakeTimeout,
+
+    /// No bytes were read from or written to the connection in time.
+    IdleTimeout,
+
+    /// The publisher stopped sending audio/video without unpublishing.
+    MediaStall,
+
+    /// The connection was still open when the shutdown drain timed out.
+    Shutdown,
+}
+
+impl CloseReason {
+    pub fn as_str(&self) -> &'static str {
+        match self {
+            CloseReason::Disconnected => "disconnected",
+            CloseReason::ProtocolError => "protocol_error",
+            CloseReason::ServerRequested => "server_requested",
+            CloseReason::HandshakeTimeout => "handshake_timeout",
+            CloseReason::IdleTimeout => "idle_timeout",
+            CloseReason::MediaStall => "media_stall",
+            CloseReason::Shutdown => "shutdown",
+        }
+    }
+}
+
+/// Outcome of an asynchronous publish authorization.
+#[derive(Debug)]
+pub enum PublishDecision {
+    Allowed,
+    Denied { reason: String },
+}
+
+/// Results of asynchronous work, such as webhooks, delivered back to the
+/// server and processed on the RTMP event loop.
+#[derive(Debug)]
+pub enum ServerNotification {
+    PublishDecision {
+        connection_id: usize,
+        ticket: u64,
+        stable_id: String,
+        decision: PublishDecision,
+    },
+    OriginResolved {
+        app_name: String,
+        stream_key: String,
+        origin: Option<Origin>,
+    },
+    /// A `Strim` created by this pod was deleted or moderated.
+    StrimRevoked {
+        name: String,
+        reason: StrimRevocation,
+    },
+    /// A call to the gRPC control plane.
+    Control(ControlRequest),
+    /// The `Strim`s created by this pod, listed by an orphan sweep.
+    OrphanCandidates { candidates: Vec<Strim> },
+    /// A connection attempt of a push finished.
+    PushConnectFinished {
+        push_id: usize,
+        ticket: u64,
+        result: io::Result<std::net::TcpStream>,
+    },
+    /// A connection attempt to the origin of a relayed stream finished.
+    RelayConnectFinished {
+        app_name: String,
+        stream_key: String,
+        result: io::Result<std::net::TcpStream>,
+    },
+}
+
+/// A publish request waiting on the `on_publish` webhook or the stream
+/// registry.
+struct PendingPublish {
+    /// Distinguishes this request from a later one on a reused connection id.
+    ticket: u64,
+    request_id: u32,
+    app_name: String,
+    stable_id: String,
+    stream_key: String,
+    recording: Option<StrimRecording>,
 }
 
 #[derive(Debug)]
@@ -128,7 +437,18 @@ pub enum ServerResult {
         target_connection_id: usize,
         packet: Packet,
     },
-    StartPushing,
+    /// A push connected to its destination. The stream is non-blocking.
+    PushConnected {
+        push_id: usize,
+        stream: std::net::TcpStream,
+    },
+    /// A relay connected to the origin of its stream. The stream is
+    /// non-blocking.
+    RelayConnected {
+        app_name: String,
+        stream_key: String,
+        stream: std::net::TcpStream,
+    },
 }
 
 pub struct ResourceReference {
@@ -137,7 +457,8 @@ pub struct ResourceReference {
 }
 
 pub struct Server {
-    client: Client,
+    /// Unset in standalone mode.
+    client: Option<Client>,
     pod_ip: String,
     pod_name: String,
     pod_uid: String,
@@ -146,16 +467,49 @@ pub struct Server {
     clients: Slab<InboundClient>,
     connection_to_client_map: HashMap<usize, usize>,
     connection_gc: HashMap<usize, ResourceReference>,
+    /// Remote addresses of inbound connections.
+    peer_addrs: HashMap<usize, SocketAddr>,
+    /// Policies of the listeners inbound connections were accepted on.
+    listener_policies: HashMap<usize, ListenerPolicy>,
     channels: HashMap<String, MediaChannel>,
-    pull_client: Option<PullClient>,
-    push_client: Option<PushClient>,
+    pull_clients: HashMap<usize, PullClient>,
+    /// Whether watchers of streams hosted on other pods are served by
+    /// relaying the stream from its origin.
+    edge_relay: bool,
+    /// Stream keys whose origin is currently being looked up or connected
+    /// to.
+    relay_lookups: HashSet<String>,
+    /// Failed attempts to relay each stream since it was last relayed.
+    relay_failures: HashMap<String, u32>,
+    /// Connections to close on the next tick of the event loop.
+    deferred_disconnects: Vec<usize>,
+    push_clients: Slab<PushClient>,
+    /// Push configured on the command line for a single source stream.
+    static_push: Option<(String, String, PushDestination)>,
+    routes: RoutingTable,
+    ingests: Option<IngestCatalog>,
     target: Option<Target>,
+    events: EventBus,
+    on_publish: Option<OnPublishHook>,
+    registry: Option<StreamRegistry>,
+    local_streams: LocalStreams,
+    pending_publishes: HashMap<usize, PendingPublish>,
+    next_publish_ticket: u64,
+    next_push_ticket: u64,
+    notifications_tx: mpsc::UnboundedSender<ServerNotification>,
+    notifications_rx: mpsc::UnboundedReceiver<ServerNotification>,
+    /// Set once the server starts shutting down. New publishes are rejected.
+    draining: bool,
+    /// Package publishes on this host instead of creating `Strim`s, in
+    /// standalone mode.
+    pipelines: Option<LocalPipelines>,
+    tuning: SessionTuning,
 }
 
 impl Server {
     #[allow(clippy::too_many_arguments)]
     pub fn new(
-        client: Client,
+        client: Option<Client>,
         pod_ip: String,
         pod_name: String,
         pod_uid: String,
@@ -163,16 +517,35 @@ impl Server {
         port: u16,
         push_options: &Option<PushOptions>,
         target: Option<Target>,
+        events: EventBus,
+        on_publish: Option<OnPublishHook>,
+        registry: Option<StreamRegistry>,
+        local_streams: LocalStreams,
+        edge_relay: bool,
+        routes: RoutingTable,
+        ingests: Option<IngestCatalog>,
+        pipelines: Option<LocalPipelines>,
+        tuning: SessionTuning,
     ) -> Server {
-        let push_client = push_options.as_ref().map(|options| PushClient {
-            push_app: options.app.clone(),
-            push_source_stream: options.source_stream.clone(),
-            push_target_stream: options.target_stream.clone(),
-            connection_id: None,
-            session: None,
-            state: PushState::Inactive,
+        let static_push = push_options.as_ref().map(|options| {
+            let host = if options.host.contains(':') {
+                options.host.clone()
+            } else {
+                format!("{}:1935", options.host)
+            };
+            (
+                options.app.clone(),
+                options.source_stream.clone(),
+                PushDestination {
+                    host,
+                    app: options.app.clone(),
+                    stream: options.target_stream.clone(),
+                },
+            )
         });
 
+        let (notifications_tx, notifications_rx) = mpsc::unbounded_channel();
+
         Server {
             client,
             pod_ip,
@@ -183,14 +556,34 @@ impl Server {
             clients: Slab::with_capacity(1024),
             connection_to_client_map: HashMap::with_capacity(1024),
             channels: HashMap::new(),
-            pull_client: None,
-            push_client,
+            pull_clients: HashMap::new(),
+            edge_relay,
+            relay_lookups: HashSet::new(),
+            relay_failures: HashMap::new(),
+            deferred_disconnects: Vec::new(),
+            push_clients: Slab::new(),
+            static_push,
+            routes,
+            ingests,
             connection_gc: HashMap::new(),
+            peer_addrs: HashMap::new(),
+            listener_policies: HashMap::new(),
             target,
+            events,
+            on_publish,
+            registry,
+            local_streams,
+            pending_publishes: HashMap::new(),
+            next_publish_ticket: 0,
+            next_push_ticket: 0,
+            notifications_tx,
+            notifications_rx,
+            draining: false,
+            pipelines,
+            tuning,
         }
     }
 
-    #[allow(dead_code)]
     pub fn register_pull_client(
         &mut self,
         connection_id: usize,
@@ -201,31 +594,157 @@ impl Server {
         // Pre-create the target channel.
         self.channels
             .entry(target_stream.clone())
-            .or_insert(MediaChannel {
-                publishing_client_id: Some(connection_id),
-                watching_client_ids: HashSet::new(),
-                metadata: None,
-                video_sequence_header: None,
-                audio_sequence_header: None,
-            });
+            .or_insert_with(MediaChannel::new)
+            .relay_connection_id = Some(connection_id);
 
-        self.pull_client = Some(PullClient {
-            session: None,
-            pull_app: app,
-            pull_stream: stream,
-            pull_target_stream: target_stream,
-            state: PullState::Handshaking,
+        self.pull_clients.insert(
             connection_id,
-        });
+            PullClient {
+                session: None,
+                pull_app: app,
+                pull_stream: stream,
+                pull_target_stream: target_stream,
+                state: PullState::Handshaking,
+                data_tap: DataTap::default(),
+                connection_id,
+            },
+        );
     }
 
-    pub fn register_push_client(&mut self, connection_id: usize) {
-        if let Some(ref mut client) = self.push_client {
+    pub fn register_push_client(&mut self, push_id: usize, connection_id: usize) {
+        if let Some(client) = self.push_clients.get_mut(push_id) {
             client.connection_id = Some(connection_id);
-            client.state = PushState::Handshaking;
+            client.set_state(PushState::Handshaking, &self.events);
+        }
+    }
+
+    /// Retries the connection of a push that could not be established.
+    pub fn push_client_failed(&mut self, push_id: usize, error: &str) {
+        let Some(client) = self.push_clients.get(push_id) else {
+            return;
+        };
+        eprintln!(
+            "{}{}{}{}",
+            "❌ Failed to connect to push destination • host=".red(),
+            client.push_host.red().dimmed(),
+            " • error=".red(),
+            error.red().dimmed(),
+        );
+        self.retry_push(push_id);
+    }
+
+    /// Forgets a push, which stops it.
+    fn remove_push(&mut self, push_id: usize) {
+        if let Some(mut client) = self.push_clients.try_remove(push_id) {
+            client.set_state(PushState::Inactive, &self.events);
+        }
+    }
+
+    /// Connects a push again after a backoff while its source stream is
+    /// still published, or forgets it otherwise.
+    fn retry_push(&mut self, push_id: usize) {
+        let Some(client) = self.push_clients.get_mut(push_id) else {
+            return;
+        };
+        let source_published = self
+            .channels
+            .get(&client.push_source_stream)
+            .is_some_and(|channel| channel.publishing_client_id.is_some());
+        if self.draining || client.stopping || !source_published {
+            self.remove_push(push_id);
+            return;
+        }
+        client.reset_session();
+        let backoff = PUSH_INITIAL_BACKOFF
+            .saturating_mul(2u32.saturating_pow(client.failed_attempts))
+            .min(PUSH_MAX_BACKOFF);
+        client.failed_attempts += 1;
+        client.set_state(PushState::Reconnecting, &self.events);
+        counter!("strim_push_reconnects_total").increment(1);
+        eprintln!(
+            "{}{}{}{}{}{}",
+            "⚠️ Reconnecting push • url=".yellow(),
+            client.url().yellow().dimmed(),
+            " • attempt=".yellow(),
+            client.failed_attempts.to_string().yellow().dimmed(),
+            " • backoff=".yellow(),
+            humantime::format_duration(backoff)
+                .to_string()
+                .yellow()
+                .dimmed(),
+        );
+        self.connect_push(push_id, backoff);
+    }
+
+    /// Resolves and connects to the destination of a push after `delay`,
+    /// off the event loop. The outcome is handled in
+    /// [`Self::push_connect_finished`].
+    fn connect_push(&self, push_id: usize, delay: Duration) {
+        let Some(client) = self.push_clients.get(push_id) else {
+            return;
+        };
+        let host = client.push_host.clone();
+        let ticket = client.ticket;
+        let notifications_tx = self.notifications_tx.clone();
+        tokio::spawn(async move {
+            tokio::time::sleep(delay).await;
+            let result = match tokio::time::timeout(
+                PUSH_CONNECT_TIMEOUT,
+                tokio::net::TcpStream::connect(&host),
+            )
+            .await
+            {
+                Ok(result) => result.and_then(|stream| stream.into_std()),
+                Err(_) => Err(io::Error::new(
+                    io::ErrorKind::TimedOut,
+                    format!("Timed out after {:?}", PUSH_CONNECT_TIMEOUT),
+                )),
+            };
+            // The server is shutting down
+            let _ = notifications_tx.send(ServerNotification::PushConnectFinished {
+                push_id,
+                ticket,
+                result,
+            });
+        });
+    }
+
+    fn push_connect_finished(
+        &mut self,
+        push_id: usize,
+        ticket: u64,
+        result: io::Result<std::net::TcpStream>,
+        server_results: &mut Vec<ServerResult>,
+    ) {
+        match self.push_clients.get(push_id) {
+            // The push was stopped meanwhile
+            Some(client) if client.ticket == ticket => {}
+            _ => return,
+        }
+        match result {
+            Ok(stream) => {
+                let source_published = self.push_clients.get(push_id).is_some_and(|client| {
+                    self.channels
+                        .get(&client.push_source_stream)
+                        .is_some_and(|channel| channel.publishing_client_id.is_some())
+                });
+                if source_published && !self.draining {
+                    server_results.push(ServerResult::PushConnected { push_id, stream });
+                } else {
+                    self.remove_push(push_id);
+                }
+            }
+            Err(e) => self.push_client_failed(push_id, &e.to_string()),
         }
     }
 
+    fn push_id_for_connection(&self, connection_id: usize) -> Option<usize> {
+        self.push_clients
+            .iter()
+            .find(|(_, client)| client.connection_id == Some(connection_id))
+            .map(|(push_id, _)| push_id)
+    }
+
     pub fn bytes_received(
         &mut self,
         connection_id: usize,
@@ -233,66 +752,79 @@ impl Server {
     ) -> Result<Vec<ServerResult>, String> {
         let mut server_results = Vec::new();
 
-        let push_client_connection_id = self.push_client.as_ref().and_then(|c| c.connection_id);
-
-        let pull_client_connection_id = self.pull_client.as_ref().map(|c| c.connection_id);
-
-        if pull_client_connection_id
-            .as_ref()
-            .is_some_and(|id| *id == connection_id)
-        {
-            // These bytes were received by the current pull client
+        if let Some(pull_client) = self.pull_clients.get_mut(&connection_id) {
+            // These bytes were received by a pull client
 
             let mut initial_session_results = Vec::new();
-            if let Some(ref mut pull_client) = self.pull_client
-                && pull_client.session.is_none()
-            {
-                let (session, session_results) =
-                    ClientSession::new(ClientSessionConfig::new()).unwrap();
-                pull_client.session = Some(session);
-
-                for result in session_results {
-                    initial_session_results.push(result);
+            let session = match pull_client.session {
+                Some(ref mut session) => session,
+                None => {
+                    let (session, session_results) = ClientSession::new(self.tuning.pull_config())
+                        .map_err(|error| error.to_string())?;
+                    initial_session_results.extend(session_results);
+                    pull_client.session.insert(session)
                 }
-            }
+            };
 
-            let session_results = match self
-                .pull_client
-                .as_mut()
-                .unwrap()
-                .session
-                .as_mut()
-                .unwrap()
-                .handle_input(bytes)
-            {
+            let session_results = match session.handle_input(bytes) {
                 Ok(results) => results,
                 Err(error) => return Err(error.to_string()),
             };
+            let data_messages = pull_client.data_tap.feed(bytes)?;
 
             if !initial_session_results.is_empty() {
-                self.handle_push_session_results(initial_session_results, &mut server_results);
+                self.handle_pull_session_results(
+                    connection_id,
+                    initial_session_results,
+                    &mut server_results,
+                );
             }
 
-            self.handle_pull_session_results(session_results, &mut server_results);
-        } else if push_client_connection_id
-            .as_ref()
-            .is_some_and(|id| *id == connection_id)
-        {
-            // These bytes were received by the current push client
+            let received = data::interleave(session_results, data_messages, |result| {
+                matches!(
+                    result,
+                    ClientSessionResult::RaisedEvent(
+                        ClientSessionEvent::AudioDataReceived { .. }
+                            | ClientSessionEvent::VideoDataReceived { .. }
+                    )
+                )
+            });
+            if received.is_empty() {
+                // Still lets a client that just finished handshaking connect
+                self.handle_pull_session_results(connection_id, Vec::new(), &mut server_results);
+            }
+            for received in received {
+                match received {
+                    Received::Session(result) => self.handle_pull_session_results(
+                        connection_id,
+                        vec![result],
+                        &mut server_results,
+                    ),
+                    Received::Data(message) => self.handle_pull_data_message_received(
+                        connection_id,
+                        message,
+                        &mut server_results,
+                    ),
+                }
+            }
+        } else if let Some(push_id) = self.push_id_for_connection(connection_id) {
+            // These bytes were received by a push client
             let mut initial_session_results = Vec::new();
 
-            let session_results = if let Some(ref mut push_client) = self.push_client {
-                if push_client.session.is_none() {
-                    let (session, session_results) =
-                        ClientSession::new(ClientSessionConfig::new()).unwrap();
-                    push_client.session = Some(session);
-
-                    for result in session_results {
-                        initial_session_results.push(result);
+            let session_results = if let Some(push_client) = self.push_clients.get_mut(push_id) {
+                let session = match push_client.session {
+                    Some(ref mut session) => session,
+                    None => {
+                        let config = push_client.session_config.clone();
+                        push_client.chunk_size = config.chunk_size;
+                        let (session, session_results) =
+                            ClientSession::new(config).map_err(|error| error.to_string())?;
+                        initial_session_results.extend(session_results);
+                        push_client.session.insert(session)
                     }
-                }
+                };
 
-                match push_client.session.as_mut().unwrap().handle_input(bytes) {
+                match session.handle_input(bytes) {
                     Ok(results) => results,
                     Err(error) => return Err(error.to_string()),
                 }
@@ -301,14 +833,19 @@ impl Server {
             };
 
             if !initial_session_results.is_empty() {
-                self.handle_push_session_results(initial_session_results, &mut server_results);
+                self.handle_push_session_results(
+                    push_id,
+                    initial_session_results,
+                    &mut server_results,
+                );
             }
 
-            self.handle_push_session_results(session_results, &mut server_results);
+            self.handle_push_session_results(push_id, session_results, &mut server_results);
         } else {
             // Since the pull client did not send these bytes, map it to an inbound client
             if !self.connection_to_client_map.contains_key(&connection_id) {
-                let config = ServerSessionConfig::new();
+                let config = self.tuning.server_config();
+                let chunk_size = config.chunk_size;
                 let (session, initial_session_results) = match ServerSession::new(config) {
                     Ok(results) => results,
                     Err(error) => return Err(error.to_string()),
@@ -324,6 +861,8 @@ impl Server {
                     connection_id,
                     current_action: InboundClientAction::Waiting,
                     has_received_video_keyframe: false,
+                    data_tap: DataTap::default(),
+                    chunk_size,
                 };
 
                 let client_id = self.clients.insert(client);
@@ -332,52 +871,182 @@ impl Server {
             }
 
             let client_results;
+            let data_messages;
             {
-                let client_id = self.connection_to_client_map.get(&connection_id).unwrap();
-                let client = self.clients.get_mut(*client_id).unwrap();
+                let Some(client) = self
+                    .connection_to_client_map
+                    .get(&connection_id)
+                    .and_then(|client_id| self.clients.get_mut(*client_id))
+                else {
+                    return Err(format!("No client for connection {}", connection_id));
+                };
                 client_results = match client.session.handle_input(bytes) {
                     Ok(results) => results,
                     Err(error) => return Err(error.to_string()),
                 };
+                data_messages = client.data_tap.feed(bytes)?;
             }
 
-            self.handle_server_session_results(connection_id, client_results, &mut server_results);
+            let received = data::interleave(client_results, data_messages, |result| {
+                matches!(
+                    result,
+                    ServerSessionResult::RaisedEvent(
+                        ServerSessionEvent::AudioDataReceived { .. }
+                            | ServerSessionEvent::VideoDataReceived { .. }
+                    )
+                )
+            });
+            for received in received {
+                match received {
+                    Received::Session(result) => self.handle_server_session_results(
+                        connection_id,
+                        vec![result],
+                        &mut server_results,
+                    ),
+                    Received::Data(message) => self.handle_publisher_data_message_received(
+                        connection_id,
+                        message,
+                        &mut server_results,
+                    ),
+                }
+            }
         }
 
         Ok(server_results)
     }
 
-    pub fn notify_connection_closed(&mut self, connection_id: usize) {
-        if let Some(r) = self.connection_gc.remove(&connection_id) {
-            let strim_api: Api<Strim> = Api::namespaced(self.client.clone(), &r.namespace);
+    /// Starts disconnecting the publishers of `Strim`s deleted or moderated
+    /// by someone else, so `kubectl delete strim` stops a stream.
+    pub fn spawn_revocation_watch(&self) {
+        let Some(ref client) = self.client else {
+            return;
+        };
+        revocations::spawn(
+            client.clone(),
+            &self.namespace,
+            self.pod_uid.clone(),
+            self.notifications_tx.clone(),
+        );
+    }
+
+    /// Sender for asynchronous work to report back to the event loop, such
+    /// as control plane calls.
+    pub fn notifications(&self) -> mpsc::UnboundedSender<ServerNotification> {
+        self.notifications_tx.clone()
+    }
+
+    /// Deletes the `Strim`s this pod created whose publisher is gone.
+    pub fn sweep_orphans(&self) {
+        let Some(ref client) = self.client else {
+            return;
+        };
+        let api: Api<Strim> = Api::namespaced(client.clone(), &self.namespace);
+        let pod_uid = self.pod_uid.clone();
+        let tx = self.notifications_tx.clone();
+        tokio::spawn(async move {
+            if let Some(candidates) = orphans::list_candidates(&api, &pod_uid).await {
+                let _ = tx.send(ServerNotification::OrphanCandidates { candidates });
+            }
+        });
+    }
+
+    /// Deletes the listed `Strim`s that no publish on this pod owns. This
+    /// is decided after listing, so that a `Strim` created meanwhile is
+    /// not taken for an orphan.
+    fn handle_orphan_candidates(&self, candidates: Vec<Strim>) {
+        let Some(ref client) = self.client else {
+            return;
+        };
+        let orphans: Vec<Strim> = candidates
+            .into_iter()
+            .filter(|strim| {
+                let name = strim.name_any();
+                !self.connection_gc.values().any(|owned| owned.name == name)
+            })
+            .collect();
+        if orphans.is_empty() {
+            return;
+        }
+        tokio::spawn(orphans::delete(
+            Api::namespaced(client.clone(), &self.namespace),
+            orphans,
+            self.events.clone(),
+        ));
+    }
+
+    /// Records the peer and listener policy of a newly accepted connection.
+    pub fn connection_accepted(
+        &mut self,
+        connection_id: usize,
+        peer_addr: SocketAddr,
+        policy: ListenerPolicy,
+    ) {
+        self.peer_addrs.insert(connection_id, peer_addr);
+        self.listener_policies.insert(connection_id, policy);
+    }
+
+    /// What the client on `connection_id` may do, given the listener it
+    /// connected through. Outbound connections are not restricted.
+    fn listener_policy(&self, connection_id: usize) -> ListenerPolicy {
+        self.listener_policies
+            .get(&connection_id)
+            .copied()
+            .unwrap_or(ListenerPolicy::ALL)
+    }
+
+    /// Writes the live stats of every stream published to this pod to the
+    /// status of its `Strim`.
+    pub fn report_stats(&self) {
+        let Some(ref kube_client) = self.client else {
+            return;
+        };
+        for (stream_key, channel) in self.channels.iter() {
+            let Some(client) = channel
+                .publishing_client_id
+                .and_then(|client_id| self.clients.get(client_id))
+            else {
+                continue;
+            };
+            let Some(strim) = self.connection_gc.get(&client.connection_id) else {
+                continue;
+            };
+            let stats = StrimStats {
+                publisher_ip: self
+                    .peer_addrs
+                    .get(&client.connection_id)
+                    .map(|addr| addr.ip().to_string()),
+                published_at: channel.published_at.map(|at| at.to_rfc3339()),
+                bitrate_kbps: channel.ingest.bitrate_kbps(),
+                watchers: channel.watching_client_ids.len() as u32,
+                video: channel.media.video.as_ref().map(ToString::to_string),
+                audio: channel.media.audio.as_ref().map(ToString::to_string),
+                pushes: self
+                    .push_clients
+                    .iter()
+                    .filter(|(_, push)| push.push_source_stream == *stream_key)
+                    .map(|(_, push)| StrimPushStats {
+                        url: push.url(),
+                        state: push.state.as_str().to_string(),
+                        failed_attempts: push.failed_attempts,
+                    })
+                    .collect(),
+            };
+            let api: Api<Strim> = Api::namespaced(kube_client.clone(), &strim.namespace);
+            let name = strim.name.clone();
+            let patch = serde_json::json!({ "status": { "stats": stats } });
             tokio::spawn(async move {
-                match strim_api.delete(&r.name, &Default::default()).await {
-                    Ok(_) => {
-                        println!(
-                            "{}{}{}{}",
-                            "🗑️ Successfully deleted Strim resource • namespace=".color(FG1),
-                            r.namespace.color(FG2),
-                            " • name=".color(FG1),
-                            r.name.color(FG2),
-                        );
-                    }
-                    Err(kube::Error::Api(ae)) if ae.code == 404 => {
-                        println!(
-                            "{}{}{}{}",
-                            "💨 Strim resource not found when attempting to delete • namespace="
-                                .yellow(),
-                            r.namespace.yellow().dimmed(),
-                            " • name=".yellow(),
-                            r.name.yellow().dimmed(),
-                        );
-                    }
+                match api
+                    .patch_status(&name, &PatchParams::default(), &Patch::Merge(&patch))
+                    .await
+                {
+                    Ok(_) => {}
+                    // The Strim may still be being created
+                    Err(kube::Error::Api(e)) if e.code == 404 => {}
                     Err(e) => {
-                        println!(
-                            "{}{}{}{}{}{}",
-                            "❌ Failed to delete Strim resource • namespace=".red(),
-                            r.namespace.red().dimmed(),
-                            " • name=".red(),
-                            r.name.red().dimmed(),
+                        eprintln!(
+                            "{}{}{}{}",
+                            "❌ Failed to write Strim stats • name=".red(),
+                            name.red().dimmed(),
                             " • error=".red(),
                             format!("{:?}", e).red().dimmed(),
                         );
@@ -385,12 +1054,152 @@ impl Server {
                 }
             });
         }
-        if self
-            .pull_client
-            .as_ref()
-            .is_some_and(|c| c.connection_id == connection_id)
-        {
-            self.pull_client = None;
+    }
+
+    /// Returns the connection ids of publishers whose channel has not
+    /// received any audio or video for longer than `stall_timeout`.
+    pub fn stalled_publishers(&self, stall_timeout: Duration) -> Vec<usize> {
+        self.channels
+            .values()
+            .filter(|channel| {
+                channel
+                    .last_media_received_at
+                    .is_some_and(|at| at.elapsed() > stall_timeout)
+            })
+            .filter_map(|channel| channel.publishing_client_id)
+            .filter_map(|client_id| self.clients.get(client_id))
+            .filter(|client| matches!(client.current_action, InboundClientAction::Publishing(_)))
+            .map(|client| client.connection_id)
+            .collect()
+    }
+
+    /// Starts draining the server for shutdown. Publishers are told their
+    /// publish was stopped, watchers that their stream was unpublished,
+    /// and all of them are disconnected once that is sent. Pushes run
+    /// until their source stream ends. Returns the deletions of the
+    /// `Strim`s created by this pod so they can be awaited before exiting.
+    pub fn begin_drain(&mut self) -> (Vec<ServerResult>, Vec<JoinHandle<()>>) {
+        self.draining = true;
+        // Decisions that arrive from now on are ignored
+        self.pending_publishes.clear();
+        let mut server_results = Vec::new();
+        for (client_id, client) in self.clients.iter() {
+            match &client.current_action {
+                InboundClientAction::Publishing(_) => {
+                    self.stop_publisher(client_id, "Server is shutting down", &mut server_results);
+                    continue;
+                }
+                InboundClientAction::Watching {
+                    stream_key,
+                    stream_id,
+                } => match data::status_packet(
+                    *stream_id,
+                    client.chunk_size,
+                    "status",
+                    "NetStream.Play.UnpublishNotify",
+                    &format!("{} is now unpublished", stream_key),
+                ) {
+                    Ok(packet) => server_results.push(ServerResult::OutboundPacket {
+                        target_connection_id: client.connection_id,
+                        packet,
+                    }),
+                    Err(e) => eprintln!(
+                        "{}{}",
+                        "❌ Failed to serialize unpublish status • error=".red(),
+                        e.red().dimmed(),
+                    ),
+                },
+                InboundClientAction::Waiting => (),
+            }
+            server_results.push(ServerResult::DisconnectConnection {
+                connection_id: client.connection_id,
+            });
+        }
+        for connection_id in self.pull_clients.keys() {
+            server_results.push(ServerResult::DisconnectConnection {
+                connection_id: *connection_id,
+            });
+        }
+        let owned = match self.pipelines {
+            Some(ref pipelines) => pipelines.connection_ids(),
+            None => self.connection_gc.keys().copied().collect(),
+        };
+        let deletions = owned
+            .into_iter()
+            .filter_map(|connection_id| self.delete_strim(connection_id))
+            .collect();
+        (server_results, deletions)
+    }
+
+    /// Processes the results of asynchronous work that completed since the
+    /// last call. Called on every tick of the RTMP event loop.
+    pub fn handle_notifications(&mut self) -> Vec<ServerResult> {
+        let mut server_results = Vec::new();
+        while let Ok(notification) = self.notifications_rx.try_recv() {
+            match notification {
+                ServerNotification::PublishDecision {
+                    connection_id,
+                    ticket,
+                    stable_id,
+                    decision,
+                } => self.handle_publish_decision(
+                    connection_id,
+                    ticket,
+                    stable_id,
+                    decision,
+                    &mut server_results,
+                ),
+                ServerNotification::OriginResolved {
+                    app_name,
+                    stream_key,
+                    origin,
+                } => self.handle_origin_resolved(app_name, stream_key, origin),
+                ServerNotification::StrimRevoked { name, reason } => {
+                    self.handle_strim_revoked(name, reason, &mut server_results)
+                }
+                ServerNotification::Control(request) => {
+                    self.handle_control(request, &mut server_results)
+                }
+                ServerNotification::PushConnectFinished {
+                    push_id,
+                    ticket,
+                    result,
+                } => self.push_connect_finished(push_id, ticket, result, &mut server_results),
+                ServerNotification::RelayConnectFinished {
+                    app_name,
+                    stream_key,
+                    result,
+                } => self.relay_connect_finished(app_name, stream_key, result, &mut server_results),
+                ServerNotification::OrphanCandidates { candidates } => {
+                    self.handle_orphan_candidates(candidates)
+                }
+            }
+        }
+        for connection_id in self.deferred_disconnects.drain(..) {
+            server_results.push(ServerResult::DisconnectConnection { connection_id });
+        }
+        server_results
+    }
+
+    pub fn notify_connection_closed(&mut self, connection_id: usize, reason: CloseReason) {
+        counter!("strim_connections_closed_total", "reason" => reason.as_str()).increment(1);
+        self.events.emit(EventKind::ConnectionClosed {
+            connection_id,
+            reason,
+        });
+        self.pending_publishes.remove(&connection_id);
+        self.peer_addrs.remove(&connection_id);
+        self.listener_policies.remove(&connection_id);
+        self.delete_strim(connection_id);
+        if let Some(push_id) = self.push_id_for_connection(connection_id) {
+            self.retry_push(push_id);
+        } else if let Some(pull_client) = self.pull_clients.remove(&connection_id) {
+            if let Some(channel) = self.channels.get_mut(&pull_client.pull_target_stream)
+                && channel.relay_connection_id == Some(connection_id)
+            {
+                channel.relay_connection_id = None;
+            }
+            self.retry_relay(pull_client.pull_app, pull_client.pull_target_stream);
         } else {
             match self.connection_to_client_map.remove(&connection_id) {
                 None => (),
@@ -398,12 +1207,12 @@ impl Server {
                     let client = self.clients.remove(client_id);
                     match client.current_action {
                         InboundClientAction::Publishing(stream_key) => {
-                            self.publishing_ended(stream_key)
+                            self.publishing_ended(connection_id, stream_key)
                         }
                         InboundClientAction::Watching {
                             stream_key,
                             stream_id: _,
-                        } => self.play_ended(client_id, stream_key),
+                        } => self.play_ended(client_id, connection_id, stream_key),
                         InboundClientAction::Waiting => (),
                     }
                 }
@@ -411,19 +1220,71 @@ impl Server {
         }
     }
 
-    fn handle_server_session_results(
-        &mut self,
-        executed_connection_id: usize,
-        session_results: Vec<ServerSessionResult>,
-        server_results: &mut Vec<ServerResult>,
-    ) {
-        for result in session_results {
-            match result {
-                ServerSessionResult::OutboundResponse(packet) => {
-                    server_results.push(ServerResult::OutboundPacket {
-                        target_connection_id: executed_connection_id,
-                        packet,
-                    })
+    /// Deletes the `Strim` created for a publish on `connection_id`, or
+    /// stops its local pipeline, returning the task doing so.
+    fn delete_strim(&mut self, connection_id: usize) -> Option<JoinHandle<()>> {
+        if let Some(ref mut pipelines) = self.pipelines {
+            return pipelines.stop(connection_id);
+        }
+        let r = self.connection_gc.remove(&connection_id)?;
+        let strim_api: Api<Strim> = Api::namespaced(self.client.clone()?, &r.namespace);
+        let events = self.events.clone();
+        Some(tokio::spawn(async move {
+            let params = DeleteParams::default();
+            match orphans::with_backoff("delete", &r.name, || strim_api.delete(&r.name, &params))
+                .await
+            {
+                Ok(_) => {
+                    println!(
+                        "{}{}{}{}",
+                        "🗑️ Successfully deleted Strim resource • namespace=".color(FG1),
+                        r.namespace.color(FG2),
+                        " • name=".color(FG1),
+                        r.name.color(FG2),
+                    );
+                    events.emit(EventKind::StrimDeleted {
+                        namespace: r.namespace,
+                        name: r.name,
+                    });
+                }
+                Err(kube::Error::Api(ae)) if ae.code == 404 => {
+                    println!(
+                        "{}{}{}{}",
+                        "💨 Strim resource not found when attempting to delete • namespace="
+                            .yellow(),
+                        r.namespace.yellow().dimmed(),
+                        " • name=".yellow(),
+                        r.name.yellow().dimmed(),
+                    );
+                }
+                Err(e) => {
+                    println!(
+                        "{}{}{}{}{}{}",
+                        "❌ Failed to delete Strim resource • namespace=".red(),
+                        r.namespace.red().dimmed(),
+                        " • name=".red(),
+                        r.name.red().dimmed(),
+                        " • error=".red(),
+                        format!("{:?}", e).red().dimmed(),
+                    );
+                }
+            }
+        }))
+    }
+
+    fn handle_server_session_results(
+        &mut self,
+        executed_connection_id: usize,
+        session_results: Vec<ServerSessionResult>,
+        server_results: &mut Vec<ServerResult>,
+    ) {
+        for result in session_results {
+            match result {
+                ServerSessionResult::OutboundResponse(packet) => {
+                    server_results.push(ServerResult::OutboundPacket {
+                        target_connection_id: executed_connection_id,
+                        packet,
+                    })
                 }
 
                 ServerSessionResult::RaisedEvent(event) => {
@@ -458,13 +1319,14 @@ impl Server {
                 request_id,
                 app_name,
                 stream_key,
-                mode: _,
+                mode,
             } => {
                 self.handle_publish_requested(
                     executed_connection_id,
                     request_id,
                     app_name,
                     stream_key,
+                    recording(&mode),
                     server_results,
                 );
             }
@@ -473,7 +1335,7 @@ impl Server {
                 app_name: _,
                 stream_key,
             } => {
-                let _ = stream_key;
+                self.handle_publish_finished(executed_connection_id, stream_key);
             }
 
             ServerSessionEvent::PlayStreamRequested {
@@ -558,13 +1420,43 @@ impl Server {
             " • app_name=".color(FG1),
             app_name.color(FG2),
         );
+        // Publishers connect to `app/stable_id`, players to `app`.
+        let route_name = app_name.split('/').next().unwrap_or_default();
+        let is_known_app = self.routes.get(route_name).is_some();
         let accept_result;
         {
-            let client_id = self
-                .connection_to_client_map
-                .get(&requested_connection_id)
-                .unwrap();
-            let client = self.clients.get_mut(*client_id).unwrap();
+            let Some(&client_id) = self.connection_to_client_map.get(&requested_connection_id)
+            else {
+                // The client disconnected meanwhile
+                return;
+            };
+            let Some(client) = self.clients.get_mut(client_id) else {
+                return;
+            };
+            if !is_known_app {
+                eprintln!(
+                    "{}{}{}{}",
+                    "🚫 Rejecting connection to unknown app • connection_id=".red(),
+                    requested_connection_id.red().dimmed(),
+                    " • app_name=".red(),
+                    app_name.red().dimmed(),
+                );
+                if let Ok(results) = client.session.reject_request(
+                    request_id,
+                    "NetConnection.Connect.Rejected",
+                    "Unknown app",
+                ) {
+                    self.handle_server_session_results(
+                        requested_connection_id,
+                        results,
+                        server_results,
+                    );
+                }
+                server_results.push(ServerResult::DisconnectConnection {
+                    connection_id: requested_connection_id,
+                });
+                return;
+            }
             accept_result = client.session.accept_request(request_id);
         }
 
@@ -599,6 +1491,7 @@ impl Server {
         request_id: u32,
         app_name: String,
         stream_key: String,
+        recording: Option<StrimRecording>,
         server_results: &mut Vec<ServerResult>,
     ) {
         let (app_name, stable_id) = match app_name.split_once('/') {
@@ -626,48 +1519,316 @@ impl Server {
             " • stream_key=".color(FG1),
             stream_key.color(FG2),
         );
-        match self.channels.get(&stream_key) {
-            None => (),
-            Some(channel) => match channel.publishing_client_id {
-                None => (),
-                Some(_) => {
-                    eprintln!(
-                        "{}",
-                        format!(
-                            "Stream key '{}' is already being published to, rejecting publish request",
-                            stream_key
-                        )
-                        .red()
-                    );
-                    server_results.push(ServerResult::DisconnectConnection {
-                        connection_id: requested_connection_id,
-                    });
+        if self.draining {
+            self.reject_publish(
+                requested_connection_id,
+                request_id,
+                stable_id,
+                "Server is shutting down",
+                server_results,
+            );
+            return;
+        }
+        if !self.listener_policy(requested_connection_id).publish {
+            self.reject_publish(
+                requested_connection_id,
+                request_id,
+                stable_id,
+                "Publishing is not allowed on this port",
+                server_results,
+            );
+            return;
+        }
+        let publish_policy = match self.routes.get(app_name) {
+            Some(route) => route.publish,
+            None => {
+                self.reject_publish(
+                    requested_connection_id,
+                    request_id,
+                    stable_id,
+                    "Unknown app",
+                    server_results,
+                );
+                return;
+            }
+        };
+        if publish_policy == PublishPolicy::Closed {
+            self.reject_publish(
+                requested_connection_id,
+                request_id,
+                stable_id,
+                "Publishing to this app is disabled",
+                server_results,
+            );
+            return;
+        }
+        let writes_locally = self
+            .pipelines
+            .as_ref()
+            .is_some_and(LocalPipelines::writes_locally);
+        if writes_locally
+            && !(pipelines::is_safe_path_segment(&stream_key)
+                && pipelines::is_safe_path_segment(stable_id))
+        {
+            // Both name directories under the local output directory
+            self.reject_publish(
+                requested_connection_id,
+                request_id,
+                stable_id,
+                "Stream key and stable id must be plain path segments",
+                server_results,
+            );
+            return;
+        }
+        if self.reject_if_already_published(requested_connection_id, &stream_key, server_results) {
+            return;
+        }
+
+        let use_webhook = publish_policy == PublishPolicy::Webhook;
+        if use_webhook && self.on_publish.is_none() {
+            // Checked at startup, but never fail open
+            self.reject_publish(
+                requested_connection_id,
+                request_id,
+                stable_id,
+                "Publish webhook is not configured",
+                server_results,
+            );
+            return;
+        }
+        if use_webhook || self.registry.is_some() {
+            let ticket = self.next_publish_ticket;
+            self.next_publish_ticket += 1;
+            self.pending_publishes.insert(
+                requested_connection_id,
+                PendingPublish {
+                    ticket,
+                    request_id,
+                    app_name: app_name.to_string(),
+                    stable_id: stable_id.to_string(),
+                    stream_key: stream_key.clone(),
+                    recording,
+                },
+            );
+            self.authorize_publish(
+                ticket,
+                use_webhook,
+                PublishAuthorizationRequest {
+                    pod_name: self.pod_name.clone(),
+                    connection_id: requested_connection_id,
+                    app_name: app_name.to_string(),
+                    stable_id: stable_id.to_string(),
+                    stream_key,
+                },
+            );
+            return;
+        }
+
+        self.accept_publish(
+            requested_connection_id,
+            request_id,
+            app_name,
+            stable_id,
+            stream_key,
+            recording,
+            server_results,
+        );
+    }
+
+    /// Disconnects the requesting connection if `stream_key` already has a
+    /// publisher. Returns `true` if the publish request was rejected.
+    fn reject_if_already_published(
+        &self,
+        requested_connection_id: usize,
+        stream_key: &str,
+        server_results: &mut Vec<ServerResult>,
+    ) -> bool {
+        let is_published = self
+            .channels
+            .get(stream_key)
+            .is_some_and(|channel| channel.publishing_client_id.is_some());
+        if is_published {
+            eprintln!(
+                "{}",
+                format!(
+                    "Stream key '{}' is already being published to, rejecting publish request",
+                    stream_key
+                )
+                .red()
+            );
+            server_results.push(ServerResult::DisconnectConnection {
+                connection_id: requested_connection_id,
+            });
+        }
+        is_published
+    }
+
+    /// Consults the `on_publish` webhook and then claims the stream in the
+    /// registry, off the event loop. The outcome is delivered back as a
+    /// [`ServerNotification::PublishDecision`].
+    fn authorize_publish(
+        &self,
+        ticket: u64,
+        use_webhook: bool,
+        request: PublishAuthorizationRequest,
+    ) {
+        let on_publish = self.on_publish.clone().filter(|_| use_webhook);
+        let registry = self.registry.clone();
+        let tx = self.notifications_tx.clone();
+        tokio::spawn(async move {
+            let mut decision = match on_publish {
+                Some(on_publish) => on_publish.authorize(&request).await,
+                None => PublishDecision::Allowed,
+            };
+            if let (PublishDecision::Allowed, Some(registry)) = (&decision, registry) {
+                match registry.claim(&request.stable_id, &request.app_name).await {
+                    Ok(None) => {}
+                    Ok(Some(owner)) => {
+                        decision = PublishDecision::Denied {
+                            reason: format!(
+                                "Stream is already being published on {}",
+                                owner.pod_name
+                            ),
+                        };
+                    }
+                    Err(e) => {
+                        // The registry is advisory: an unavailable Redis
+                        // must not take ingest down with it.
+                        eprintln!(
+                            "{}{}{}{}",
+                            "⚠️ Failed to claim stream in registry • stable_id=".yellow(),
+                            request.stable_id.yellow().dimmed(),
+                            " • error=".yellow(),
+                            format!("{:?}", e).yellow().dimmed(),
+                        );
+                    }
+                }
+            }
+            let _ = tx.send(ServerNotification::PublishDecision {
+                connection_id: request.connection_id,
+                ticket,
+                stable_id: request.stable_id,
+                decision,
+            });
+        });
+    }
+
+    fn handle_publish_decision(
+        &mut self,
+        connection_id: usize,
+        ticket: u64,
+        stable_id: String,
+        decision: PublishDecision,
+        server_results: &mut Vec<ServerResult>,
+    ) {
+        // The connection may have closed while the decision was pending.
+        let is_current = self
+            .pending_publishes
+            .get(&connection_id)
+            .is_some_and(|pending| pending.ticket == ticket);
+        if !is_current {
+            if let PublishDecision::Allowed = decision {
+                self.release_stream(stable_id);
+            }
+            return;
+        }
+        let Some(pending) = self.pending_publishes.remove(&connection_id) else {
+            return;
+        };
+        match decision {
+            PublishDecision::Allowed => {
+                if self.reject_if_already_published(
+                    connection_id,
+                    &pending.stream_key,
+                    server_results,
+                ) {
                     return;
                 }
-            },
+                self.accept_publish(
+                    connection_id,
+                    pending.request_id,
+                    &pending.app_name,
+                    &pending.stable_id,
+                    pending.stream_key,
+                    pending.recording,
+                    server_results,
+                );
+            }
+            PublishDecision::Denied { reason } => {
+                self.reject_publish(
+                    connection_id,
+                    pending.request_id,
+                    &pending.stable_id,
+                    &reason,
+                    server_results,
+                );
+            }
+        }
+    }
+
+    fn reject_publish(
+        &mut self,
+        connection_id: usize,
+        request_id: u32,
+        stable_id: &str,
+        reason: &str,
+        server_results: &mut Vec<ServerResult>,
+    ) {
+        eprintln!(
+            "{}{}{}{}{}{}",
+            "🚫 Publish denied • connection_id=".red(),
+            connection_id.red().dimmed(),
+            " • stable_id=".red(),
+            stable_id.red().dimmed(),
+            " • reason=".red(),
+            reason.red().dimmed(),
+        );
+        if let Some(client_id) = self.connection_to_client_map.get(&connection_id)
+            && let Some(client) = self.clients.get_mut(*client_id)
+            && let Ok(results) =
+                client
+                    .session
+                    .reject_request(request_id, "NetStream.Publish.Rejected", reason)
+        {
+            self.handle_server_session_results(connection_id, results, server_results);
         }
+        server_results.push(ServerResult::DisconnectConnection { connection_id });
+    }
 
+    #[allow(clippy::too_many_arguments)]
+    fn accept_publish(
+        &mut self,
+        requested_connection_id: usize,
+        request_id: u32,
+        app_name: &str,
+        stable_id: &str,
+        stream_key: String,
+        recording: Option<StrimRecording>,
+        server_results: &mut Vec<ServerResult>,
+    ) {
+        let route = self.routes.get(app_name).cloned().unwrap_or_default();
         let accept_result;
         {
-            let client_id = self
-                .connection_to_client_map
-                .get(&requested_connection_id)
-                .unwrap();
-            let client = self.clients.get_mut(*client_id).unwrap();
+            let Some(&client_id) = self.connection_to_client_map.get(&requested_connection_id)
+            else {
+                // The client disconnected meanwhile
+                return;
+            };
+            let Some(client) = self.clients.get_mut(client_id) else {
+                return;
+            };
             client.current_action = InboundClientAction::Publishing(stream_key.clone());
 
             let channel = self
                 .channels
                 .entry(stream_key.clone())
-                .or_insert(MediaChannel {
-                    publishing_client_id: None,
-                    watching_client_ids: HashSet::new(),
-                    metadata: None,
-                    video_sequence_header: None,
-                    audio_sequence_header: None,
-                });
+                .or_insert_with(MediaChannel::new);
 
-            channel.publishing_client_id = Some(*client_id);
+            channel.publishing_client_id = Some(client_id);
+            channel.last_media_received_at = Some(Instant::now());
+            channel.app_name = Some(app_name.to_string());
+            channel.stable_id = Some(stable_id.to_string());
+            channel.published_at = Some(chrono::Utc::now());
             accept_result = client.session.accept_request(request_id);
         }
 
@@ -683,23 +1844,20 @@ impl Server {
             }
 
             Ok(results) => {
-                if let Some(ref mut client) = self.push_client
-                    && client.state == PushState::Inactive
-                {
-                    if app_name == client.push_app && stream_key == client.push_source_stream {
-                        eprintln!(
-                            "{}",
-                            "✔️ Publishing on the push source stream key!".color(FG1)
-                        );
-                        client.state = PushState::WaitingForConnection;
-                        server_results.push(ServerResult::StartPushing)
-                    } else {
-                        eprintln!(
-                            "{}",
-                            "⚠️ Not publishing on the push source stream key!".yellow()
-                        );
-                    }
-                }
+                self.local_streams.lock().unwrap().insert(
+                    stable_id.to_string(),
+                    LocalStream {
+                        stable_id: stable_id.to_string(),
+                        app_name: app_name.to_string(),
+                        media: MediaInfo::default(),
+                    },
+                );
+                self.events.emit(EventKind::PublishStarted {
+                    connection_id: requested_connection_id,
+                    app_name: app_name.to_string(),
+                    stable_id: stable_id.to_string(),
+                });
+                self.start_pushes(app_name, stable_id, &stream_key, &route);
 
                 self.handle_server_session_results(
                     requested_connection_id,
@@ -708,12 +1866,125 @@ impl Server {
                 );
             }
         }
+        if !route.create_strim {
+            return;
+        }
+        let ingest = self
+            .ingests
+            .as_ref()
+            .and_then(|ingests| ingests.find(app_name, stable_id));
+        let mut hls = route.hls.clone();
+        let mut transcribe = false;
+        let strim_target = match (ingest, &route.target, &self.target) {
+            (Some(ingest), _, _) => {
+                println!(
+                    "{}{}{}{}",
+                    "🎯 Using StrimIngest target • name=".color(FG1),
+                    ingest.name_any().color(FG2),
+                    " • stable_id=".color(FG1),
+                    stable_id.color(FG2),
+                );
+                hls = ingest.spec.hls.clone().or(hls);
+                transcribe = ingest.spec.transcribe;
+                let target = &ingest.spec.target;
+                StrimTarget {
+                    key_prefix: format!("{}{}/", target.key_prefix, stream_key),
+                    delete_old_segments_after: Some(
+                        target
+                            .delete_old_segments_after
+                            .clone()
+                            .unwrap_or_else(|| "30m".to_string()),
+                    ),
+                    ..target.clone()
+                }
+            }
+            (None, Some(target), _) => StrimTarget {
+                bucket: target.bucket.clone(),
+                endpoint: target.endpoint.clone(),
+                region: target.region.clone(),
+                secret: target.secret.clone(),
+                key_prefix: format!("{}{}/", target.key_prefix, stream_key),
+                delete_old_segments_after: Some(
+                    target
+                        .delete_old_segments_after
+                        .clone()
+                        .unwrap_or_else(|| "30m".to_string()),
+                ),
+            },
+            (None, None, Some(target)) => StrimTarget {
+                bucket: target.bucket.clone(),
+                endpoint: target.endpoint.clone(),
+                region: target.region.clone(),
+                secret: target.secret.clone(),
+                key_prefix: format!("{}/", stream_key),
+                delete_old_segments_after: Some("30m".to_string()),
+            },
+            (None, None, None)
+                if self
+                    .pipelines
+                    .as_ref()
+                    .is_some_and(LocalPipelines::writes_locally) =>
+            {
+                StrimTarget {
+                    key_prefix: format!("{}/", stream_key),
+                    delete_old_segments_after: None,
+                    ..Default::default()
+                }
+            }
+            (None, None, None) => {
+                if recording.is_some() {
+                    println!(
+                        "{}{}",
+                        "⚠️ No target configured, streaming recorded publish live only • stable_id="
+                            .yellow(),
+                        stable_id.yellow().dimmed(),
+                    );
+                }
+                return; // no s3 upload
+            }
+        };
+        let strim_target = match recording {
+            // Recordings are kept in full. Appends share one prefix per
+            // stream so they continue the same playlist.
+            Some(StrimRecording::Record) => StrimTarget {
+                delete_old_segments_after: None,
+                ..strim_target
+            },
+            Some(StrimRecording::Append) => StrimTarget {
+                key_prefix: format!(
+                    "{}{}/",
+                    strim_target
+                        .key_prefix
+                        .strip_suffix(&format!("{}/", stream_key))
+                        .unwrap_or(&strim_target.key_prefix),
+                    stable_id
+                ),
+                delete_old_segments_after: None,
+                ..strim_target
+            },
+            None => strim_target,
+        };
         let random_usize = rand::random::<u64>() as usize;
         let (name, _hash) = pod_name(&self.pod_ip, stable_id, &stream_key, random_usize);
-        let target = match self.target {
-            Some(ref target) => target,
-            None => return, // no s3 upload``
+        let spec = StrimSpec {
+            source: StrimSource {
+                internal_url: format!(
+                    "rtmp://{}:{}/{}/{}",
+                    self.pod_ip, self.port, app_name, stable_id
+                ),
+            },
+            target: strim_target,
+            transcribe,
+            hls,
+            recording,
+        };
+        let Some(ref client) = self.client else {
+            if let Some(ref mut pipelines) = self.pipelines {
+                pipelines.start(requested_connection_id, name, stable_id, &spec);
+            }
+            return;
         };
+        let strim_api: Api<Strim> = Api::namespaced(client.clone(), &self.namespace);
         self.connection_gc.insert(
             requested_connection_id,
             ResourceReference {
@@ -742,28 +2013,19 @@ impl Server {
                 }]),
                 ..Default::default()
             },
-            spec: StrimSpec {
-                source: StrimSource {
-                    internal_url: format!(
-                        "rtmp://{}:{}/live/{}",
-                        self.pod_ip, self.port, stable_id
-                    ),
-                },
-                target: StrimTarget {
-                    bucket: target.bucket.clone(),
-                    endpoint: target.endpoint.clone(),
-                    region: target.region.clone(),
-                    secret: target.secret.clone(),
-                    key_prefix: format!("{}/", stream_key),
-                    delete_old_segments_after: Some("30m".to_string()),
-                },
-                transcribe: false,
-            },
+            spec,
             ..Default::default()
         };
-        let strim_api: Api<Strim> = Api::namespaced(self.client.clone(), &self.namespace);
+        let events = self.events.clone();
+        let stable_id = stable_id.to_string();
         tokio::spawn(async move {
-            match strim_api.create(&Default::default(), &strim_resource).await {
+            let name = strim_resource.name_any();
+            let params = PostParams::default();
+            match orphans::with_backoff("create", &name, || {
+                strim_api.create(&params, &strim_resource)
+            })
+            .await
+            {
                 Ok(_) => {
                     println!(
                         "{}{}{}{}",
@@ -782,6 +2044,11 @@ impl Server {
                             .unwrap_or(&"<unknown>".to_string())
                             .color(FG2)
                     );
+                    events.emit(EventKind::StrimCreated {
+                        namespace: strim_resource.metadata.namespace.unwrap_or_default(),
+                        name: strim_resource.metadata.name.unwrap_or_default(),
+                        stable_id,
+                    });
                 }
                 Err(e) => {
                     eprintln!(
@@ -810,6 +2077,18 @@ impl Server {
         });
     }
 
+    /// Players, including relays from other pods, address a stream by its
+    /// public `stable_id` while channels are keyed by the publisher's secret
+    /// stream key. Maps the former onto the latter when it is live here.
+    /// The result must never leave the server, as it allows publishing.
+    fn resolve_play_stream_key(&self, stream_key: String) -> String {
+        self.channels
+            .iter()
+            .find(|(_, channel)| channel.stable_id.as_deref() == Some(stream_key.as_str()))
+            .map(|(key, _)| key.clone())
+            .unwrap_or(stream_key)
+    }
+
     fn handle_play_requested(
         &mut self,
         requested_connection_id: usize,
@@ -830,13 +2109,44 @@ impl Server {
             " • request_id=".color(FG1),
             request_id.color(FG2),
         );
+        if !self.listener_policy(requested_connection_id).play {
+            eprintln!(
+                "{}{}{}{}",
+                "🚫 Play denied • connection_id=".red(),
+                requested_connection_id.red().dimmed(),
+                " • reason=".red(),
+                "Playback is not allowed on this port".red().dimmed(),
+            );
+            if let Some(client_id) = self.connection_to_client_map.get(&requested_connection_id)
+                && let Some(client) = self.clients.get_mut(*client_id)
+                && let Ok(results) = client.session.reject_request(
+                    request_id,
+                    "NetStream.Play.Failed",
+                    "Playback is not allowed on this port",
+                )
+            {
+                self.handle_server_session_results(
+                    requested_connection_id,
+                    results,
+                    server_results,
+                );
+            }
+            server_results.push(ServerResult::DisconnectConnection {
+                connection_id: requested_connection_id,
+            });
+            return;
+        }
+        let channel_key = self.resolve_play_stream_key(stream_key.clone());
         let accept_result;
         {
-            let client_id = self
-                .connection_to_client_map
-                .get(&requested_connection_id)
-                .unwrap();
-            let client = self.clients.get_mut(*client_id).unwrap();
+            let Some(&client_id) = self.connection_to_client_map.get(&requested_connection_id)
+            else {
+                // The client disconnected meanwhile
+                return;
+            };
+            let Some(client) = self.clients.get_mut(client_id) else {
+                return;
+            };
             client.current_action = InboundClientAction::Watching {
                 stream_key: stream_key.clone(),
                 stream_id,
@@ -844,16 +2154,10 @@ impl Server {
 
             let channel = self
                 .channels
-                .entry(stream_key.clone())
-                .or_insert(MediaChannel {
-                    publishing_client_id: None,
-                    watching_client_ids: HashSet::new(),
-                    metadata: None,
-                    video_sequence_header: None,
-                    audio_sequence_header: None,
-                });
+                .entry(channel_key.clone())
+                .or_insert_with(MediaChannel::new);
 
-            channel.watching_client_ids.insert(*client_id);
+            channel.watching_client_ids.insert(client_id);
             accept_result = match client.session.accept_request(request_id) {
                 Err(error) => Err(error),
                 Ok(mut results) => {
@@ -959,6 +2263,12 @@ impl Server {
             }
 
             Ok(results) => {
+                self.events.emit(EventKind::PlayStarted {
+                    connection_id: requested_connection_id,
+                    app_name: app_name.clone(),
+                    stream_key,
+                });
+                self.maybe_find_origin(app_name, channel_key, Duration::ZERO);
                 self.handle_server_session_results(
                     requested_connection_id,
                     results,
@@ -987,6 +2297,16 @@ impl Server {
             None => return,
         };
 
+        if let (Some(app_name), Some(stable_id)) = (&channel.app_name, &channel.stable_id) {
+            self.events.emit(EventKind::CodecInfo {
+                app_name: app_name.clone(),
+                stable_id: stable_id.clone(),
+                codec: CodecInfo::from(&metadata),
+            });
+        }
+
+        channel.tracks.metadata_received(&metadata);
+        channel.ingest.metadata_received(&metadata);
         let metadata = Rc::new(metadata);
         channel.metadata = Some(metadata.clone());
 
@@ -1023,6 +2343,8 @@ impl Server {
                 }
             }
         }
+
+        self.enforce_ingest_limits(&stream_key, server_results);
     }
 
     fn handle_audio_video_data_received(
@@ -1033,27 +2355,50 @@ impl Server {
         data_type: ReceivedDataType,
         server_results: &mut Vec<ServerResult>,
     ) {
+        let mut media_changed = None;
+        let ingest_changed;
         {
             let channel = match self.channels.get_mut(&stream_key) {
                 Some(channel) => channel,
                 None => return,
             };
 
+            let now = Instant::now();
+            channel.last_media_received_at = Some(now);
+            channel.tracks.tag_received(&data_type);
+            ingest_changed = channel.ingest.tag_received(
+                matches!(data_type, ReceivedDataType::Video),
+                &data,
+                now,
+            );
+            let audio_only = channel.tracks.is_audio_only();
+
             // If this is an audio or video sequence header we need to save it, so it can be
             // distributed to any late coming watchers
+            let previous_media = channel.media.clone();
             match data_type {
                 ReceivedDataType::Video => {
-                    if is_video_sequence_header(data.clone()) {
+                    if codecs::is_video_sequence_header(&data)
+                        && channel.video_sequence_header.as_ref() != Some(&data)
+                    {
                         channel.video_sequence_header = Some(data.clone());
+                        channel.media.video = codecs::parse_video_sequence_header(&data);
                     }
                 }
 
                 ReceivedDataType::Audio => {
-                    if is_audio_sequence_header(data.clone()) {
+                    if codecs::is_audio_sequence_header(&data)
+                        && channel.audio_sequence_header.as_ref() != Some(&data)
+                    {
                         channel.audio_sequence_header = Some(data.clone());
+                        channel.media.audio = codecs::parse_audio_sequence_header(&data);
                     }
                 }
             }
+            if channel.media != previous_media {
+                channel.ingest.media_info_changed(&channel.media);
+                media_changed = Some(previous_media);
+            }
 
             for client_id in &channel.watching_client_ids {
                 let client = match self.clients.get_mut(*client_id) {
@@ -1069,12 +2414,14 @@ impl Server {
                 let should_send_to_client = match data_type {
                     ReceivedDataType::Video => {
                         client.has_received_video_keyframe
-                            || (is_video_sequence_header(data.clone())
-                                || is_video_keyframe(data.clone()))
+                            || (codecs::is_video_sequence_header(&data)
+                                || codecs::is_video_keyframe(&data))
                     }
 
                     ReceivedDataType::Audio => {
-                        client.has_received_video_keyframe || is_audio_sequence_header(data.clone())
+                        client.has_received_video_keyframe
+                            || audio_only
+                            || codecs::is_audio_sequence_header(&data)
                     }
                 };
 
@@ -1090,7 +2437,7 @@ impl Server {
                         true,
                     ),
                     ReceivedDataType::Video => {
-                        if is_video_keyframe(data.clone()) {
+                        if codecs::is_video_keyframe(&data) {
                             client.has_received_video_keyframe = true;
                         }
 
@@ -1126,43 +2473,519 @@ impl Server {
             }
         }
 
+        if ingest_changed || media_changed.is_some() {
+            self.enforce_ingest_limits(&stream_key, server_results);
+        }
+        if let Some(previous_media) = media_changed {
+            self.media_info_changed(&stream_key, &previous_media);
+        }
+
         let mut push_results = Vec::new();
-        {
-            if let Some(ref mut client) = self.push_client
-                && client.state == PushState::Pushing
-            {
-                let result = match data_type {
-                    ReceivedDataType::Video => client.session.as_mut().unwrap().publish_video_data(
-                        data.clone(),
-                        timestamp,
-                        true,
-                    ),
+        for (push_id, client) in self.push_clients.iter_mut() {
+            if client.state != PushState::Pushing || client.push_source_stream != stream_key {
+                continue;
+            }
+            let Some(session) = client.session.as_mut() else {
+                continue;
+            };
+            let result = match data_type {
+                ReceivedDataType::Video => {
+                    session.publish_video_data(data.clone(), timestamp, true)
+                }
+                ReceivedDataType::Audio => {
+                    session.publish_audio_data(data.clone(), timestamp, true)
+                }
+            };
 
-                    ReceivedDataType::Audio => client.session.as_mut().unwrap().publish_audio_data(
-                        data.clone(),
-                        timestamp,
-                        true,
-                    ),
+            match result {
+                Ok(client_result) => push_results.push((push_id, client_result)),
+                Err(error) => client.abort("push a/v data", &error, server_results),
+            }
+        }
+
+        for (push_id, result) in push_results {
+            self.handle_push_session_results(push_id, vec![result], server_results);
+        }
+    }
+
+    fn handle_publisher_data_message_received(
+        &mut self,
+        connection_id: usize,
+        message: DataMessage,
+        server_results: &mut Vec<ServerResult>,
+    ) {
+        let stream_key = match self
+            .connection_to_client_map
+            .get(&connection_id)
+            .and_then(|client_id| self.clients.get(*client_id))
+            .map(|client| &client.current_action)
+        {
+            Some(InboundClientAction::Publishing(stream_key)) => stream_key.clone(),
+            _ => return,
+        };
+
+        self.handle_data_message_received(stream_key, message, server_results);
+    }
+
+    /// Forwards an AMF0 data message to the watchers and push clients of a
+    /// stream, in order with its audio and video.
+    fn handle_data_message_received(
+        &mut self,
+        stream_key: String,
+        message: DataMessage,
+        server_results: &mut Vec<ServerResult>,
+    ) {
+        let channel = match self.channels.get(&stream_key) {
+            Some(channel) => channel,
+            None => return,
+        };
+
+        if let (Some(cue), Some(app_name), Some(stable_id)) =
+            (message.cue_point(), &channel.app_name, &channel.stable_id)
+        {
+            println!(
+                "{}{}{}{}",
+                "📍 Cue point received • stable_id=".color(FG1),
+                stable_id.color(FG2),
+                " • kind=".color(FG1),
+                format!("{:?}", cue.kind).color(FG2),
+            );
+            self.events.emit(EventKind::CuePoint {
+                app_name: app_name.clone(),
+                stable_id: stable_id.clone(),
+                cue,
+            });
+        }
+
+        for client_id in &channel.watching_client_ids {
+            let client = match self.clients.get(*client_id) {
+                Some(client) => client,
+                None => continue,
+            };
+
+            let active_stream_id = match client.get_active_stream_id() {
+                Some(stream_id) => stream_id,
+                None => continue,
+            };
+
+            match message.to_packet(active_stream_id, client.chunk_size) {
+                Ok(packet) => server_results.push(ServerResult::OutboundPacket {
+                    target_connection_id: client.connection_id,
+                    packet,
+                }),
+
+                Err(error) => {
+                    eprintln!(
+                        "{}",
+                        format!(
+                            "❌ Error sending data message to client on connection id {}: {:?}",
+                            client.connection_id, error
+                        )
+                        .red(),
+                    );
+                    server_results.push(ServerResult::DisconnectConnection {
+                        connection_id: client.connection_id,
+                    });
+                }
+            }
+        }
+
+        for (_, client) in self.push_clients.iter() {
+            if client.state != PushState::Pushing || client.push_source_stream != stream_key {
+                continue;
+            }
+
+            let (Some(connection_id), Some(stream_id)) = (client.connection_id, client.stream_id)
+            else {
+                continue;
+            };
+
+            match message.to_packet(stream_id, client.chunk_size) {
+                Ok(packet) => server_results.push(ServerResult::OutboundPacket {
+                    target_connection_id: connection_id,
+                    packet,
+                }),
+                Err(error) => {
+                    eprintln!(
+                        "{}",
+                        format!("❌ Error pushing data message: {:?}", error).red(),
+                    );
+                }
+            }
+        }
+    }
+
+    /// Checks a published stream against its app's ingest limits, reporting
+    /// each limit it exceeds and, if the app says so, disconnecting the
+    /// publisher.
+    fn enforce_ingest_limits(&mut self, stream_key: &str, server_results: &mut Vec<ServerResult>) {
+        let Some(channel) = self.channels.get_mut(stream_key) else {
+            return;
+        };
+        let (Some(app_name), Some(stable_id)) = (&channel.app_name, &channel.stable_id) else {
+            return;
+        };
+        let Some(limits) = self
+            .routes
+            .get(app_name)
+            .and_then(|route| route.limits.as_ref())
+        else {
+            return;
+        };
+        let violations = channel.ingest.check(limits);
+        if violations.is_empty() {
+            return;
+        }
+
+        let action = match limits.action {
+            LimitAction::Warn => "warn",
+            LimitAction::Disconnect => "disconnect",
+        };
+        for violation in &violations {
+            eprintln!(
+                "{}{}{}{}{}{}{}{}",
+                "⚠️ Ingest limit exceeded • stable_id=".yellow(),
+                stable_id.yellow().dimmed(),
+                " • limit=".yellow(),
+                violation.limit.yellow().dimmed(),
+                " • detail=".yellow(),
+                violation.detail.yellow().dimmed(),
+                " • action=".yellow(),
+                action.yellow().dimmed(),
+            );
+            counter!(
+                "strim_ingest_limit_violations_total",
+                "app" => app_name.clone(),
+                "limit" => violation.limit,
+                "action" => action,
+            )
+            .increment(1);
+            self.events.emit(EventKind::IngestLimitExceeded {
+                app_name: app_name.clone(),
+                stable_id: stable_id.clone(),
+                limit: violation.limit.to_string(),
+                detail: violation.detail.clone(),
+                action: limits.action,
+            });
+        }
+        if limits.action != LimitAction::Disconnect {
+            return;
+        }
+
+        let Some(client_id) = channel.publishing_client_id else {
+            return;
+        };
+        let description = violations
+            .iter()
+            .map(|violation| violation.detail.as_str())
+            .collect::<Vec<_>>()
+            .join("; ");
+        self.stop_publisher(
+            client_id,
+            &format!("Ingest limits exceeded: {}", description),
+            server_results,
+        );
+    }
+
+    /// Tells a publisher why its publish was stopped and disconnects it.
+    fn stop_publisher(
+        &self,
+        client_id: usize,
+        description: &str,
+        server_results: &mut Vec<ServerResult>,
+    ) {
+        let Some(client) = self.clients.get(client_id) else {
+            return;
+        };
+        if let Some(stream_id) = client.data_tap.stream_id() {
+            match data::status_packet(
+                stream_id,
+                client.chunk_size,
+                "error",
+                "NetStream.Publish.Rejected",
+                description,
+            ) {
+                Ok(packet) => server_results.push(ServerResult::OutboundPacket {
+                    target_connection_id: client.connection_id,
+                    packet,
+                }),
+                Err(e) => eprintln!(
+                    "{}{}",
+                    "❌ Failed to serialize publish status • error=".red(),
+                    e.red().dimmed(),
+                ),
+            }
+        }
+        server_results.push(ServerResult::DisconnectConnection {
+            connection_id: client.connection_id,
+        });
+    }
+
+    fn handle_control(&mut self, request: ControlRequest, server_results: &mut Vec<ServerResult>) {
+        match request {
+            ControlRequest::ListStreams { reply } => {
+                let _ = reply.send(self.stream_snapshots());
+            }
+            ControlRequest::DisconnectClient {
+                connection_id,
+                reason,
+                reply,
+            } => {
+                let result = if let Some(client_id) =
+                    self.connection_to_client_map.get(&connection_id).copied()
+                {
+                    let reason = match reason.is_empty() {
+                        true => "Disconnected by an operator".to_string(),
+                        false => reason,
+                    };
+                    self.stop_publisher(client_id, &reason, server_results);
+                    Ok(())
+                } else if self.pull_clients.contains_key(&connection_id)
+                    || self.push_id_for_connection(connection_id).is_some()
+                {
+                    server_results.push(ServerResult::DisconnectConnection { connection_id });
+                    Ok(())
+                } else {
+                    Err(Status::not_found(format!(
+                        "No connection with id {}",
+                        connection_id
+                    )))
                 };
+                let _ = reply.send(result);
+            }
+            ControlRequest::StartPush {
+                stable_id,
+                url,
+                reply,
+            } => {
+                let result = match (
+                    self.published_stream_key(&stable_id),
+                    routes::parse_push_url(&url),
+                ) {
+                    (None, _) => Err(Status::not_found(format!(
+                        "Stream {} is not published",
+                        stable_id
+                    ))),
+                    (_, Err(e)) => Err(Status::invalid_argument(e.to_string())),
+                    (Some(stream_key), Ok(destination)) => {
+                        Ok(self.start_push(&stream_key, destination))
+                    }
+                };
+                let _ = reply.send(result);
+            }
+            ControlRequest::StopPush { push_id, reply } => {
+                let result = match self.push_clients.get_mut(push_id) {
+                    None => Err(Status::not_found(format!("No push with id {}", push_id))),
+                    Some(client) => {
+                        match client.connection_id {
+                            Some(connection_id) => {
+                                client.stopping = true;
+                                server_results
+                                    .push(ServerResult::DisconnectConnection { connection_id });
+                            }
+                            // Drops the outcome of a pending connection attempt
+                            None => self.remove_push(push_id),
+                        }
+                        Ok(())
+                    }
+                };
+                let _ = reply.send(result);
+            }
+        }
+    }
 
-                match result {
-                    Ok(client_result) => push_results.push(client_result),
-                    Err(error) => {
+    /// Stream key of the stream with `stable_id` published to this pod.
+    fn published_stream_key(&self, stable_id: &str) -> Option<String> {
+        self.channels
+            .iter()
+            .find(|(_, channel)| {
+                channel.publishing_client_id.is_some()
+                    && channel.stable_id.as_deref() == Some(stable_id)
+            })
+            .map(|(stream_key, _)| stream_key.clone())
+    }
+
+    /// The streams published to this pod, as reported by the control plane.
+    fn stream_snapshots(&self) -> Vec<proto::Stream> {
+        self.channels
+            .iter()
+            .filter_map(|(stream_key, channel)| {
+                let client = self.clients.get(channel.publishing_client_id?)?;
+                let connection_id = client.connection_id;
+                Some(proto::Stream {
+                    stable_id: channel.stable_id.clone()?,
+                    app_name: channel.app_name.clone()?,
+                    publisher_connection_id: connection_id as u64,
+                    publisher_ip: self
+                        .peer_addrs
+                        .get(&connection_id)
+                        .map(|addr| addr.ip().to_string()),
+                    published_at: channel.published_at.map(|at| at.to_rfc3339()),
+                    bitrate_kbps: channel.ingest.bitrate_kbps(),
+                    watcher_connection_ids: channel
+                        .watching_client_ids
+                        .iter()
+                        .filter_map(|client_id| self.clients.get(*client_id))
+                        .map(|client| client.connection_id as u64)
+                        .collect(),
+                    video: channel.media.video.as_ref().map(ToString::to_string),
+                    audio: channel.media.audio.as_ref().map(ToString::to_string),
+                    pushes: self
+                        .push_clients
+                        .iter()
+                        .filter(|(_, push)| push.push_source_stream == *stream_key)
+                        .map(|(push_id, push)| proto::Push {
+                            push_id: push_id as u64,
+                            url: push.url(),
+                            state: push.state.as_str().to_string(),
+                            connection_id: push.connection_id.map(|id| id as u64),
+                            failed_attempts: push.failed_attempts,
+                        })
+                        .collect(),
+                })
+            })
+            .collect()
+    }
+
+    /// Disconnects the publisher of a `Strim` that was deleted or
+    /// moderated.
+    fn handle_strim_revoked(
+        &mut self,
+        name: String,
+        reason: StrimRevocation,
+        server_results: &mut Vec<ServerResult>,
+    ) {
+        let Some(connection_id) = self
+            .connection_gc
+            .iter()
+            .find(|(_, strim)| strim.name == name)
+            .map(|(connection_id, _)| *connection_id)
+        else {
+            return;
+        };
+        let description = match &reason {
+            // Nothing left to clean up when the publisher disconnects
+            StrimRevocation::Deleted => {
+                self.connection_gc.remove(&connection_id);
+                "Stream was stopped".to_string()
+            }
+            StrimRevocation::Moderated(moderation) => {
+                format!("Stream was stopped by a moderator ({})", moderation)
+            }
+        };
+        eprintln!(
+            "{}{}{}{}",
+            "🚫 Stopping publisher of revoked Strim • name=".yellow(),
+            name.yellow().dimmed(),
+            " • reason=".yellow(),
+            format!("{:?}", reason).yellow().dimmed(),
+        );
+        if let Some(client_id) = self.connection_to_client_map.get(&connection_id) {
+            self.stop_publisher(*client_id, &description, server_results);
+        }
+    }
+
+    /// Reports new sequence headers of a published stream in the admin
+    /// API, the `strim_stream_media_info` metric and its `Strim`'s
+    /// annotations.
+    fn media_info_changed(&mut self, stream_key: &str, previous: &MediaInfo) {
+        let Some(channel) = self.channels.get(stream_key) else {
+            return;
+        };
+        let (Some(app_name), Some(stable_id)) = (&channel.app_name, &channel.stable_id) else {
+            // Relayed streams are reported by their origin
+            return;
+        };
+        let video = channel.media.video.as_ref().map(ToString::to_string);
+        let audio = channel.media.audio.as_ref().map(ToString::to_string);
+        println!(
+            "{}{}{}{}{}{}",
+            "🎞️ Media info • stable_id=".color(FG1),
+            stable_id.color(FG2),
+            " • video=".color(FG1),
+            video.as_deref().unwrap_or("none").color(FG2),
+            " • audio=".color(FG1),
+            audio.as_deref().unwrap_or("none").color(FG2),
+        );
+        add_to_media_info_gauge(app_name, previous, -1.0);
+        add_to_media_info_gauge(app_name, &channel.media, 1.0);
+        if let Some(stream) = self.local_streams.lock().unwrap().get_mut(stable_id) {
+            stream.media = channel.media.clone();
+        }
+
+        let (Some(strim), Some(kube_client)) = (
+            channel
+                .publishing_client_id
+                .and_then(|client_id| self.clients.get(client_id))
+                .and_then(|client| self.connection_gc.get(&client.connection_id)),
+            &self.client,
+        ) else {
+            return;
+        };
+        let api: Api<Strim> = Api::namespaced(kube_client.clone(), &strim.namespace);
+        let name = strim.name.clone();
+        let patch = serde_json::json!({
+            "metadata": {
+                "annotations": {
+                    annotations::VIDEO: video,
+                    annotations::AUDIO: audio,
+                }
+            }
+        });
+        tokio::spawn(async move {
+            // The Strim may still be being created
+            for _ in 0..5 {
+                match api
+                    .patch(&name, &PatchParams::default(), &Patch::Merge(&patch))
+                    .await
+                {
+                    Ok(_) => return,
+                    Err(kube::Error::Api(e)) if e.code == 404 => {
+                        tokio::time::sleep(Duration::from_secs(1)).await;
+                    }
+                    Err(e) => {
                         eprintln!(
-                            "{}",
-                            format!("❌ Error pushing a/v data: {:?}", error).red(),
+                            "{}{}{}{}",
+                            "❌ Failed to annotate Strim with media info • name=".red(),
+                            name.red().dimmed(),
+                            " • error=".red(),
+                            format!("{:?}", e).red().dimmed(),
                         );
+                        return;
                     }
                 }
             }
-        }
+        });
+    }
 
-        if !push_results.is_empty() {
-            self.handle_push_session_results(push_results, server_results);
+    /// Ends a publish the client stopped without closing its connection,
+    /// as OBS does on "Stop Streaming". The connection may publish again.
+    fn handle_publish_finished(&mut self, connection_id: usize, stream_key: String) {
+        let Some(client) = self
+            .connection_to_client_map
+            .get(&connection_id)
+            .and_then(|client_id| self.clients.get_mut(*client_id))
+        else {
+            return;
+        };
+        match &client.current_action {
+            InboundClientAction::Publishing(published) if *published == stream_key => {
+                client.current_action = InboundClientAction::Waiting;
+            }
+            _ => return,
         }
+        println!(
+            "{}{}{}{}",
+            "🏁 Publish finished • connection_id=".color(FG1),
+            connection_id.to_string().color(FG2),
+            " • stream_key=".color(FG1),
+            stream_key.color(FG2),
+        );
+        self.delete_strim(connection_id);
+        self.publishing_ended(connection_id, stream_key);
     }
 
-    fn publishing_ended(&mut self, stream_key: String) {
+    fn publishing_ended(&mut self, connection_id: usize, stream_key: String) {
         let channel = match self.channels.get_mut(&stream_key) {
             Some(channel) => channel,
             None => return,
@@ -1170,25 +2993,284 @@ impl Server {
 
         channel.publishing_client_id = None;
         channel.metadata = None;
+        channel.last_media_received_at = None;
+        // A new publisher may not carry the same tracks
+        channel.video_sequence_header = None;
+        channel.audio_sequence_header = None;
+        channel.tracks = TrackLayout::default();
+        channel.ingest = IngestMeter::default();
+        channel.published_at = None;
+        let media = std::mem::take(&mut channel.media);
+        let mut unconnected_pushes = Vec::new();
+        for (push_id, client) in self.push_clients.iter() {
+            if client.push_source_stream != stream_key {
+                continue;
+            }
+            match client.connection_id {
+                Some(connection_id) => self.deferred_disconnects.push(connection_id),
+                None => unconnected_pushes.push(push_id),
+            }
+        }
+        if let (Some(app_name), Some(stable_id)) =
+            (channel.app_name.take(), channel.stable_id.take())
+        {
+            add_to_media_info_gauge(&app_name, &media, -1.0);
+            self.local_streams.lock().unwrap().remove(&stable_id);
+            self.events.emit(EventKind::PublishStopped {
+                connection_id,
+                app_name,
+                stable_id: stable_id.clone(),
+            });
+            self.release_stream(stable_id);
+        }
+        // Pushes waiting to reconnect have no connection to close
+        for push_id in unconnected_pushes {
+            self.remove_push(push_id);
+        }
     }
 
-    fn play_ended(&mut self, client_id: usize, stream_key: String) {
-        let channel = match self.channels.get_mut(&stream_key) {
+    /// Removes a stream claimed by this pod from the registry.
+    fn release_stream(&self, stable_id: String) {
+        let Some(registry) = self.registry.clone() else {
+            return;
+        };
+        tokio::spawn(async move {
+            if let Err(e) = registry.release(&stable_id).await {
+                eprintln!(
+                    "{}{}{}{}",
+                    "❌ Failed to release stream from registry • stable_id=".red(),
+                    stable_id.red().dimmed(),
+                    " • error=".red(),
+                    format!("{:?}", e).red().dimmed(),
+                );
+            }
+        });
+    }
+
+    fn play_ended(&mut self, client_id: usize, connection_id: usize, stream_key: String) {
+        // Watchers may have asked for the channel by its stable id
+        let channel = match self
+            .channels
+            .values_mut()
+            .find(|channel| channel.watching_client_ids.contains(&client_id))
+        {
             Some(channel) => channel,
             None => return,
         };
 
         channel.watching_client_ids.remove(&client_id);
+        if channel.watching_client_ids.is_empty()
+            && let Some(relay_connection_id) = channel.relay_connection_id
+        {
+            println!(
+                "{}{}",
+                "🔚 Last watcher left, stopping relay • stream_key=".color(FG1),
+                stream_key.color(FG2),
+            );
+            self.deferred_disconnects.push(relay_connection_id);
+        }
+        self.events.emit(EventKind::PlayStopped {
+            connection_id,
+            stream_key,
+        });
+    }
+
+    /// Looks up the origin of a stream that has watchers on this pod but
+    /// no local publisher after `delay`, so that it can be relayed from
+    /// there.
+    fn maybe_find_origin(&mut self, app_name: String, stream_key: String, delay: Duration) {
+        if !self.edge_relay || self.relay_lookups.contains(&stream_key) {
+            return;
+        }
+        let is_served = self.channels.get(&stream_key).is_some_and(|channel| {
+            channel.publishing_client_id.is_some() || channel.relay_connection_id.is_some()
+        });
+        if is_served {
+            return;
+        }
+        self.relay_lookups.insert(stream_key.clone());
+        let registry = self.registry.clone();
+        let client = self.client.clone();
+        let namespace = self.namespace.clone();
+        let pod_ip = self.pod_ip.clone();
+        let port = self.port;
+        let tx = self.notifications_tx.clone();
+        tokio::spawn(async move {
+            tokio::time::sleep(delay).await;
+            let origin = match relay::find_origin(
+                registry.as_ref(),
+                client,
+                &namespace,
+                &stream_key,
+            )
+            .await
+            {
+                // Never relay a stream from this pod to itself.
+                Ok(origin) => origin.filter(|o| o.host != pod_ip || o.port != port),
+                Err(e) => {
+                    eprintln!(
+                        "{}{}{}{}",
+                        "❌ Failed to look up stream origin • stream_key=".red(),
+                        stream_key.red().dimmed(),
+                        " • error=".red(),
+                        format!("{:?}", e).red().dimmed(),
+                    );
+                    None
+                }
+            };
+            let _ = tx.send(ServerNotification::OriginResolved {
+                app_name,
+                stream_key,
+                origin,
+            });
+        });
+    }
+
+    /// Whether a stream has watchers on this pod but is neither published
+    /// nor relayed here.
+    fn needs_relay(&self, stream_key: &str) -> bool {
+        !self.draining
+            && self.channels.get(stream_key).is_some_and(|channel| {
+                !channel.watching_client_ids.is_empty()
+                    && channel.publishing_client_id.is_none()
+                    && channel.relay_connection_id.is_none()
+            })
+    }
+
+    fn handle_origin_resolved(
+        &mut self,
+        app_name: String,
+        stream_key: String,
+        origin: Option<Origin>,
+    ) {
+        let Some(origin) = origin else {
+            self.relay_lookups.remove(&stream_key);
+            self.relay_failures.remove(&stream_key);
+            println!(
+                "{}{}",
+                "💨 No origin found for stream, watchers will wait for a publisher • stream_key="
+                    .yellow(),
+                stream_key.yellow().dimmed(),
+            );
+            return;
+        };
+        if !self.needs_relay(&stream_key) {
+            self.relay_lookups.remove(&stream_key);
+            self.relay_failures.remove(&stream_key);
+            return;
+        }
+        println!(
+            "{}{}{}{}{}{}",
+            "🛰️ Relaying stream from origin • stream_key=".color(FG1),
+            stream_key.color(FG2),
+            " • host=".color(FG1),
+            origin.host.color(FG2),
+            " • port=".color(FG1),
+            origin.port.color(FG2),
+        );
+        self.connect_relay(app_name, stream_key, origin);
+    }
+
+    /// Resolves and connects to the origin of a relayed stream off the
+    /// event loop. The outcome is handled in
+    /// [`Self::relay_connect_finished`].
+    fn connect_relay(&self, app_name: String, stream_key: String, origin: Origin) {
+        let notifications_tx = self.notifications_tx.clone();
+        tokio::spawn(async move {
+            let result = match tokio::time::timeout(
+                RELAY_CONNECT_TIMEOUT,
+                tokio::net::TcpStream::connect((origin.host.as_str(), origin.port)),
+            )
+            .await
+            {
+                Ok(result) => result.and_then(|stream| stream.into_std()),
+                Err(_) => Err(io::Error::new(
+                    io::ErrorKind::TimedOut,
+                    format!("Timed out after {:?}", RELAY_CONNECT_TIMEOUT),
+                )),
+            };
+            // The server is shutting down
+            let _ = notifications_tx.send(ServerNotification::RelayConnectFinished {
+                app_name,
+                stream_key,
+                result,
+            });
+        });
+    }
+
+    fn relay_connect_finished(
+        &mut self,
+        app_name: String,
+        stream_key: String,
+        result: io::Result<std::net::TcpStream>,
+        server_results: &mut Vec<ServerResult>,
+    ) {
+        self.relay_lookups.remove(&stream_key);
+        match result {
+            Ok(stream) if self.needs_relay(&stream_key) => {
+                server_results.push(ServerResult::RelayConnected {
+                    app_name,
+                    stream_key,
+                    stream,
+                });
+            }
+            // The watchers left meanwhile
+            Ok(_) => {
+                self.relay_failures.remove(&stream_key);
+            }
+            Err(e) => self.relay_client_failed(app_name, stream_key, &e.to_string()),
+        }
+    }
+
+    /// Retries a relay whose connection to the origin could not be
+    /// established.
+    pub fn relay_client_failed(&mut self, app_name: String, stream_key: String, error: &str) {
+        eprintln!(
+            "{}{}{}{}",
+            "❌ Failed to connect to origin • stream_key=".red(),
+            stream_key.red().dimmed(),
+            " • error=".red(),
+            error.red().dimmed(),
+        );
+        self.retry_relay(app_name, stream_key);
+    }
+
+    /// Looks up the origin of a relayed stream again after a backoff while
+    /// it still has watchers, or gives up on it otherwise.
+    fn retry_relay(&mut self, app_name: String, stream_key: String) {
+        if !self.needs_relay(&stream_key) {
+            self.relay_failures.remove(&stream_key);
+            return;
+        }
+        let failures = self.relay_failures.entry(stream_key.clone()).or_default();
+        let backoff = RELAY_INITIAL_BACKOFF
+            .saturating_mul(2u32.saturating_pow(*failures))
+            .min(RELAY_MAX_BACKOFF);
+        *failures += 1;
+        eprintln!(
+            "{}{}{}{}{}{}",
+            "⚠️ Retrying relay • stream_key=".yellow(),
+            stream_key.yellow().dimmed(),
+            " • attempt=".yellow(),
+            failures.to_string().yellow().dimmed(),
+            " • backoff=".yellow(),
+            humantime::format_duration(backoff)
+                .to_string()
+                .yellow()
+                .dimmed(),
+        );
+        self.maybe_find_origin(app_name, stream_key, backoff);
     }
 
     fn handle_pull_session_results(
         &mut self,
+        connection_id: usize,
         session_results: Vec<ClientSessionResult>,
         server_results: &mut Vec<ServerResult>,
     ) {
         let mut new_results = Vec::new();
         let mut events = Vec::new();
-        if let Some(ref mut client) = self.pull_client {
+        if let Some(client) = self.pull_clients.get_mut(&connection_id) {
             for result in session_results {
                 match result {
                     ClientSessionResult::OutboundResponse(packet) => {
@@ -1211,32 +3293,47 @@ impl Server {
                 // initiate the connect to the RTMP app
                 client.state = PullState::Connecting;
 
-                let result = client
+                let pull_app = client.pull_app.clone();
+                match client
                     .session
                     .as_mut()
-                    .unwrap()
-                    .request_connection(client.pull_app.clone())
-                    .unwrap();
-                new_results.push(result);
+                    .map(|session| session.request_connection(pull_app))
+                {
+                    Some(Ok(result)) => new_results.push(result),
+                    Some(Err(error)) => {
+                        eprintln!(
+                            "{}",
+                            format!(
+                                "❌ Failed to request connection for pull client: {:?}",
+                                error
+                            )
+                            .red()
+                        );
+                        server_results.push(ServerResult::DisconnectConnection { connection_id });
+                        return;
+                    }
+                    None => {}
+                }
             }
         }
 
         if !new_results.is_empty() {
-            self.handle_pull_session_results(new_results, server_results);
+            self.handle_pull_session_results(connection_id, new_results, server_results);
         }
 
         for event in events {
             match event {
                 ClientSessionEvent::ConnectionRequestAccepted => {
-                    self.handle_pull_connection_accepted_event(server_results);
+                    self.handle_pull_connection_accepted_event(connection_id, server_results);
                 }
 
                 ClientSessionEvent::PlaybackRequestAccepted => {
-                    self.handle_pull_playback_accepted_event(server_results);
+                    self.handle_pull_playback_accepted_event(connection_id, server_results);
                 }
 
                 ClientSessionEvent::VideoDataReceived { data, timestamp } => {
                     self.handle_pull_audio_video_data_received(
+                        connection_id,
                         data,
                         ReceivedDataType::Video,
                         timestamp,
@@ -1246,6 +3343,7 @@ impl Server {
 
                 ClientSessionEvent::AudioDataReceived { data, timestamp } => {
                     self.handle_pull_audio_video_data_received(
+                        connection_id,
                         data,
                         ReceivedDataType::Audio,
                         timestamp,
@@ -1254,7 +3352,7 @@ impl Server {
                 }
 
                 ClientSessionEvent::StreamMetadataReceived { metadata } => {
-                    self.handle_pull_metadata_received(metadata, server_results);
+                    self.handle_pull_metadata_received(connection_id, metadata, server_results);
                 }
 
                 x => eprintln!("{}", format!("❌ Unhandled event raised: {:?}", x).yellow()),
@@ -1262,49 +3360,67 @@ impl Server {
         }
     }
 
-    fn handle_pull_connection_accepted_event(&mut self, server_results: &mut Vec<ServerResult>) {
+    fn handle_pull_connection_accepted_event(
+        &mut self,
+        connection_id: usize,
+        server_results: &mut Vec<ServerResult>,
+    ) {
         let mut new_results = Vec::new();
-        if let Some(ref mut client) = self.pull_client {
+        if let Some(client) = self.pull_clients.get_mut(&connection_id) {
             eprintln!(
                 "{}",
                 format!("Pull accepted for app '{}'", client.pull_app).yellow()
             );
             client.state = PullState::Connected;
 
-            let result = client
+            let pull_stream = client.pull_stream.clone();
+            match client
                 .session
                 .as_mut()
-                .unwrap()
-                .request_playback(client.pull_stream.clone())
-                .unwrap();
-            let mut results = vec![result];
-            new_results.append(&mut results);
+                .map(|session| session.request_playback(pull_stream))
+            {
+                Some(Ok(result)) => new_results.push(result),
+                Some(Err(error)) => {
+                    eprintln!(
+                        "{}",
+                        format!("❌ Failed to request playback for pull client: {:?}", error).red()
+                    );
+                    server_results.push(ServerResult::DisconnectConnection { connection_id });
+                }
+                None => {}
+            }
         }
 
         if !new_results.is_empty() {
-            self.handle_pull_session_results(new_results, server_results);
+            self.handle_pull_session_results(connection_id, new_results, server_results);
         }
     }
 
-    fn handle_pull_playback_accepted_event(&mut self, _server_results: &mut Vec<ServerResult>) {
-        if let Some(ref mut client) = self.pull_client {
+    fn handle_pull_playback_accepted_event(
+        &mut self,
+        connection_id: usize,
+        _server_results: &mut Vec<ServerResult>,
+    ) {
+        if let Some(client) = self.pull_clients.get_mut(&connection_id) {
             println!(
                 "{}",
                 format!("Playback accepted for stream '{}'", client.pull_stream).green()
             );
             client.state = PullState::Pulling;
+            self.relay_failures.remove(&client.pull_target_stream);
         }
     }
 
     fn handle_pull_audio_video_data_received(
         &mut self,
+        connection_id: usize,
         data: Bytes,
         data_type: ReceivedDataType,
         timestamp: RtmpTimestamp,
         server_results: &mut Vec<ServerResult>,
     ) {
-        let stream_key = match self.pull_client {
-            Some(ref client) => client.pull_target_stream.clone(),
+        let stream_key = match self.pull_clients.get(&connection_id) {
+            Some(client) => client.pull_target_stream.clone(),
             None => return,
         };
 
@@ -1319,30 +3435,119 @@ impl Server {
 
     fn handle_pull_metadata_received(
         &mut self,
+        connection_id: usize,
         metadata: StreamMetadata,
         server_results: &mut Vec<ServerResult>,
     ) {
-        let (app_name, stream_key) = match self.pull_client {
-            Some(ref client) => (client.pull_target_stream.clone(), client.pull_app.clone()),
+        let (app_name, stream_key) = match self.pull_clients.get(&connection_id) {
+            Some(client) => (client.pull_app.clone(), client.pull_target_stream.clone()),
             None => return,
         };
 
         self.handle_metadata_received(app_name, stream_key, metadata, server_results);
     }
 
+    fn handle_pull_data_message_received(
+        &mut self,
+        connection_id: usize,
+        message: DataMessage,
+        server_results: &mut Vec<ServerResult>,
+    ) {
+        let stream_key = match self.pull_clients.get(&connection_id) {
+            Some(client) if client.state == PullState::Pulling => client.pull_target_stream.clone(),
+            _ => return,
+        };
+
+        self.handle_data_message_received(stream_key, message, server_results);
+    }
+
+    /// Starts pushing a newly published stream to the static push
+    /// destination and to every destination of its app.
+    fn start_pushes(
+        &mut self,
+        app_name: &str,
+        stable_id: &str,
+        stream_key: &str,
+        route: &AppRoute,
+    ) {
+        let mut destinations = Vec::new();
+        if let Some((ref app, ref source_stream, ref destination)) = self.static_push
+            && app_name == app
+            && stream_key == source_stream
+        {
+            destinations.push(destination.clone());
+        }
+        destinations.extend(route.push.iter().map(|destination| PushDestination {
+            stream: destination.stream.replace("{stable_id}", stable_id),
+            ..destination.clone()
+        }));
+
+        for destination in destinations {
+            self.start_push(stream_key, destination);
+        }
+    }
+
+    /// Starts pushing the stream published on `stream_key` to
+    /// `destination`, returning the id of the push.
+    fn start_push(&mut self, stream_key: &str, destination: PushDestination) -> usize {
+        println!(
+            "{}{}{}{}{}{}",
+            "📤 Pushing stream • stream_key=".color(FG1),
+            stream_key.color(FG2),
+            " • host=".color(FG1),
+            destination.host.color(FG2),
+            " • target=".color(FG1),
+            format!("{}/{}", destination.app, destination.stream).color(FG2),
+        );
+        let overrides = self
+            .channels
+            .get(stream_key)
+            .and_then(|channel| channel.app_name.as_deref())
+            .and_then(|app_name| self.routes.get(app_name))
+            .and_then(|route| route.push_session.as_ref());
+        let session_config = self.tuning.push_config(&destination, overrides);
+        let client = PushClient {
+            session: None,
+            connection_id: None,
+            push_host: destination.host,
+            push_app: destination.app,
+            push_source_stream: stream_key.to_string(),
+            push_target_stream: destination.stream,
+            state: PushState::Inactive,
+            stream_id: None,
+            chunk_size: 0,
+            session_config,
+            ticket: self.next_push_ticket,
+            failed_attempts: 0,
+            stopping: false,
+        };
+        self.next_push_ticket += 1;
+        let push_id = self.push_clients.insert(client);
+        self.push_clients[push_id].set_state(PushState::WaitingForConnection, &self.events);
+        self.connect_push(push_id, Duration::ZERO);
+        push_id
+    }
+
     fn handle_push_session_results(
         &mut self,
+        push_id: usize,
         session_results: Vec<ClientSessionResult>,
         server_results: &mut Vec<ServerResult>,
     ) {
         let mut new_results = Vec::new();
         let mut events = Vec::new();
-        if let Some(ref mut client) = self.push_client {
+        if let Some(client) = self.push_clients.get_mut(push_id) {
+            let Some(connection_id) = client.connection_id else {
+                return;
+            };
             for result in session_results {
                 match result {
                     ClientSessionResult::OutboundResponse(packet) => {
+                        if client.state == PushState::Pushing && client.stream_id.is_none() {
+                            client.stream_id = data::media_stream_id(&packet);
+                        }
                         server_results.push(ServerResult::OutboundPacket {
-                            target_connection_id: client.connection_id.unwrap(),
+                            target_connection_id: connection_id,
                             packet,
                         });
                     }
@@ -1361,44 +3566,36 @@ impl Server {
             if client.state == PushState::Handshaking {
                 // Since we got here we know handshaking was successful, so we need
                 // to initiate the connection process
-                client.state = PushState::Connecting;
+                client.set_state(PushState::Connecting, &self.events);
 
-                let result = match client
+                let push_app = client.push_app.clone();
+                match client
                     .session
                     .as_mut()
-                    .unwrap()
-                    .request_connection(client.push_app.clone())
+                    .map(|session| session.request_connection(push_app))
                 {
-                    Ok(result) => result,
-                    Err(error) => {
-                        eprintln!(
-                            "{}",
-                            format!(
-                                "❌ Failed to request connection for push client: {:?}",
-                                error
-                            )
-                            .red()
-                        );
+                    Some(Ok(result)) => new_results.push(result),
+                    Some(Err(error)) => {
+                        client.abort("request connection", &error, server_results);
                         return;
                     }
-                };
-
-                new_results.push(result);
+                    None => {}
+                }
             }
         }
 
         if !new_results.is_empty() {
-            self.handle_push_session_results(new_results, server_results);
+            self.handle_push_session_results(push_id, new_results, server_results);
         }
 
         for event in events {
             match event {
                 ClientSessionEvent::ConnectionRequestAccepted => {
-                    self.handle_push_connection_accepted_event(server_results);
+                    self.handle_push_connection_accepted_event(push_id, server_results);
                 }
 
                 ClientSessionEvent::PublishRequestAccepted => {
-                    self.handle_push_publish_accepted_event(server_results);
+                    self.handle_push_publish_accepted_event(push_id, server_results);
                 }
 
                 x => eprintln!("{}", format!("Push event raised: {:?}", x).green()),
@@ -1406,94 +3603,112 @@ impl Server {
         }
     }
 
-    fn handle_push_connection_accepted_event(&mut self, server_results: &mut Vec<ServerResult>) {
+    fn handle_push_connection_accepted_event(
+        &mut self,
+        push_id: usize,
+        server_results: &mut Vec<ServerResult>,
+    ) {
         let mut new_results = Vec::new();
-        if let Some(ref mut client) = self.push_client {
+        if let Some(client) = self.push_clients.get_mut(push_id) {
             eprintln!(
                 "{}",
                 format!("push accepted for app '{}'", client.push_app).green()
             );
-            client.state = PushState::Connected;
+            client.set_state(PushState::Connected, &self.events);
 
-            let result = client
+            let target_stream = client.push_target_stream.clone();
+            match client
                 .session
                 .as_mut()
-                .unwrap()
-                .request_publishing(client.push_target_stream.clone(), PublishRequestType::Live)
-                .unwrap();
-
-            let mut results = vec![result];
-            new_results.append(&mut results);
+                .map(|session| session.request_publishing(target_stream, PublishRequestType::Live))
+            {
+                Some(Ok(result)) => new_results.push(result),
+                Some(Err(error)) => client.abort("request publishing", &error, server_results),
+                None => {}
+            }
         }
 
         if !new_results.is_empty() {
-            self.handle_push_session_results(new_results, server_results);
+            self.handle_push_session_results(push_id, new_results, server_results);
         }
     }
 
-    fn handle_push_publish_accepted_event(&mut self, server_results: &mut Vec<ServerResult>) {
+    /// Starts sending media once the destination accepted the publish. The
+    /// source's metadata and sequence headers are sent first, so a push
+    /// that reconnected mid-stream can be decoded again.
+    fn handle_push_publish_accepted_event(
+        &mut self,
+        push_id: usize,
+        server_results: &mut Vec<ServerResult>,
+    ) {
         let mut new_results = Vec::new();
-        if let Some(ref mut client) = self.push_client {
+        if let Some(client) = self.push_clients.get_mut(push_id) {
             println!(
                 "{}{}",
                 "✔️ Publish accepted for push • stream_key=".color(FG1),
                 client.push_target_stream.color(FG2),
             );
-            client.state = PushState::Pushing;
+            client.failed_attempts = 0;
+            client.set_state(PushState::Pushing, &self.events);
 
             // Send out any metadata or header information if we have any
-            if let Some(channel) = self.channels.get(&client.push_source_stream) {
+            if let (Some(channel), Some(session)) = (
+                self.channels.get(&client.push_source_stream),
+                client.session.as_mut(),
+            ) {
+                let mut replayed = Vec::new();
                 if let Some(ref metadata) = channel.metadata {
-                    let result = client
-                        .session
-                        .as_mut()
-                        .unwrap()
-                        .publish_metadata(metadata)
-                        .unwrap();
-                    new_results.push(result);
+                    replayed.push(session.publish_metadata(metadata));
                 }
-
                 if let Some(ref bytes) = channel.video_sequence_header {
-                    let result = client
-                        .session
-                        .as_mut()
-                        .unwrap()
-                        .publish_video_data(bytes.clone(), RtmpTimestamp::new(0), false)
-                        .unwrap();
-
-                    new_results.push(result);
+                    replayed.push(session.publish_video_data(
+                        bytes.clone(),
+                        RtmpTimestamp::new(0),
+                        false,
+                    ));
                 }
-
                 if let Some(ref bytes) = channel.audio_sequence_header {
-                    let result = client
-                        .session
-                        .as_mut()
-                        .unwrap()
-                        .publish_audio_data(bytes.clone(), RtmpTimestamp::new(0), false)
-                        .unwrap();
-
-                    new_results.push(result);
+                    replayed.push(session.publish_audio_data(
+                        bytes.clone(),
+                        RtmpTimestamp::new(0),
+                        false,
+                    ));
+                }
+                match replayed.into_iter().collect::<Result<Vec<_>, _>>() {
+                    Ok(mut results) => new_results.append(&mut results),
+                    Err(error) => client.abort("replay sequence headers", &error, server_results),
                 }
             }
         }
 
         if !new_results.is_empty() {
-            self.handle_push_session_results(new_results, server_results);
+            self.handle_push_session_results(push_id, new_results, server_results);
         }
     }
 }
 
-fn is_video_sequence_header(data: Bytes) -> bool {
-    // This is assuming h264.
-    data.len() >= 2 && data[0] == 0x17 && data[1] == 0x00
-}
-
-fn is_audio_sequence_header(data: Bytes) -> bool {
-    // This is assuming aac
-    data.len() >= 2 && data[0] == 0xaf && data[1] == 0x00
+/// Adjusts the `strim_stream_media_info` gauge, which counts the streams
+/// of an app published with each media info. Streams are not labelled
+/// individually, so that their series do not outlive them; the media info
+/// of each stream is in the admin API and its `Strim`'s annotations.
+fn add_to_media_info_gauge(app_name: &str, media: &MediaInfo, delta: f64) {
+    if media == &MediaInfo::default() {
+        return;
+    }
+    gauge!(
+        "strim_stream_media_info",
+        "app" => app_name.to_string(),
+        "video" => media.video.as_ref().map(ToString::to_string).unwrap_or_default(),
+        "audio" => media.audio.as_ref().map(ToString::to_string).unwrap_or_default(),
+    )
+    .increment(delta);
 }
 
-fn is_video_keyframe(data: Bytes) -> bool {
-    // assumings h264
-    data.len() >= 2 && data[0] == 0x17 && data[1] != 0x00 // 0x00 is the sequence header, don't count that for now
+/// Maps the mode of an RTMP publish to how its `Strim` is recorded.
+fn recording(mode: &PublishMode) -> Option<StrimRecording> {
+    match mode {
+        PublishMode::Live => None,
+        PublishMode::Record => Some(StrimRecording::Record),
+        PublishMode::Append => Some(StrimRecording::Append),
+    }
 }
@@ -0,0 +1,91 @@
+use rml_rtmp::sessions::{ClientSessionConfig, ServerSessionConfig};
+
+use crate::args::RtmpArgs;
+use crate::routes::{PushDestination, PushSession};
+
+/// Server-wide settings of the RTMP sessions. Inbound sessions are created
+/// before the client names its app, so only pushes can be tuned per app.
+#[derive(Debug, Clone)]
+pub struct SessionTuning {
+    pub chunk_size: u32,
+    pub window_ack_size: Option<u32>,
+    pub peer_bandwidth: u32,
+    pub push_flash_version: String,
+    pub push_tc_url: Option<String>,
+}
+
+impl Default for SessionTuning {
+    /// The settings rml_rtmp uses on its own.
+    fn default() -> Self {
+        let server = ServerSessionConfig::new();
+        let client = ClientSessionConfig::new();
+        Self {
+            chunk_size: server.chunk_size,
+            window_ack_size: None,
+            peer_bandwidth: server.peer_bandwidth,
+            push_flash_version: client.flash_version,
+            push_tc_url: client.tc_url,
+        }
+    }
+}
+
+impl From<&RtmpArgs> for SessionTuning {
+    fn from(args: &RtmpArgs) -> Self {
+        Self {
+            chunk_size: args.rtmp_chunk_size,
+            window_ack_size: args.rtmp_window_ack_size,
+            peer_bandwidth: args.rtmp_peer_bandwidth,
+            push_flash_version: args.push_flash_version.clone(),
+            push_tc_url: args.push_tc_url.clone(),
+        }
+    }
+}
+
+impl SessionTuning {
+    /// Config of the sessions of clients connecting to this server.
+    pub fn server_config(&self) -> ServerSessionConfig {
+        let mut config = ServerSessionConfig::new();
+        config.chunk_size = self.chunk_size;
+        config.peer_bandwidth = self.peer_bandwidth;
+        if let Some(window_ack_size) = self.window_ack_size {
+            config.window_ack_size = window_ack_size;
+        }
+        config
+    }
+
+    /// Config of the sessions pulling streams from other servers.
+    pub fn pull_config(&self) -> ClientSessionConfig {
+        let mut config = ClientSessionConfig::new();
+        config.chunk_size = self.chunk_size;
+        if let Some(window_ack_size) = self.window_ack_size {
+            config.window_ack_size = window_ack_size;
+        }
+        config
+    }
+
+    /// Config of a push to `destination`, with the overrides of the app
+    /// the pushed stream was published to.
+    pub fn push_config(
+        &self,
+        destination: &PushDestination,
+        overrides: Option<&PushSession>,
+    ) -> ClientSessionConfig {
+        let mut config = self.pull_config();
+        config.flash_version = self.push_flash_version.clone();
+        let mut tc_url = self.push_tc_url.as_ref();
+        if let Some(overrides) = overrides {
+            config.chunk_size = overrides.chunk_size.unwrap_or(config.chunk_size);
+            config.window_ack_size = overrides.window_ack_size.unwrap_or(config.window_ack_size);
+            if let Some(ref flash_version) = overrides.flash_version {
+                config.flash_version = flash_version.clone();
+            }
+            tc_url = overrides.tc_url.as_ref().or(tc_url);
+        }
+        config.tc_url = tc_url.map(|tc_url| {
+            tc_url
+                .replace("{host}", &destination.host)
+                .replace("{app}", &destination.app)
+        });
+        config
+    }
+}
@@ -0,0 +1,244 @@
+use hmac::{Hmac, Mac};
+use metrics::counter;
+use owo_colors::OwoColorize;
+use serde::{Deserialize, Serialize};
+use sha2::Sha256;
+use std::{sync::Arc, time::Duration};
+use tokio::sync::mpsc;
+
+use crate::{
+    colors::{FG1, FG2},
+    events::Event,
+    server::PublishDecision,
+};
+
+/// Header carrying the hex-encoded HMAC-SHA256 of the request body.
+const SIGNATURE_HEADER: &str = "X-Strim-Signature";
+
+/// Header carrying the event subject, e.g. `publish.started`.
+const EVENT_HEADER: &str = "X-Strim-Event";
+
+/// Header carrying the event id, so receivers can deduplicate retries.
+const EVENT_ID_HEADER: &str = "X-Strim-Event-Id";
+
+/// Upper bound for the delay between delivery attempts.
+const MAX_BACKOFF: Duration = Duration::from_secs(30);
+
+/// Initial delay between delivery attempts, doubled after every failure.
+const INITIAL_BACKOFF: Duration = Duration::from_millis(500);
+
+/// Delivery settings shared by every webhook endpoint.
+#[derive(Clone)]
+pub struct WebhookConfig {
+    pub secret: Option<String>,
+    pub timeout: Duration,
+    pub max_attempts: u32,
+}
+
+/// Returns the value of the signature header for `body`.
+fn sign(secret: &str, body: &[u8]) -> String {
+    let mut mac =
+        Hmac::<Sha256>::new_from_slice(secret.as_bytes()).expect("HMAC accepts keys of any size");
+    mac.update(body);
+    format!("sha256={}", hex::encode(mac.finalize().into_bytes()))
+}
+
+fn signed_post(
+    client: &reqwest::Client,
+    url: &str,
+    secret: Option<&str>,
+    body: Vec<u8>,
+) -> reqwest::RequestBuilder {
+    let mut request = client
+        .post(url)
+        .header(http::header::CONTENT_TYPE, "application/json");
+    if let Some(secret) = secret {
+        request = request.header(SIGNATURE_HEADER, sign(secret, &body));
+    }
+    request.body(body)
+}
+
+/// Spawns a worker that delivers every event received on `rx` to `url`.
+///
+/// Events are delivered in order. Failed deliveries are retried with
+/// exponential backoff up to `max_attempts` times before being dropped.
+/// The queue behind `rx` is bounded, so a slow endpoint only ever causes
+/// its own events to be dropped.
+pub fn spawn_worker(url: String, config: WebhookConfig, mut rx: mpsc::Receiver<Arc<Event>>) {
+    println!(
+        "{}{}",
+        "🪝 Delivering lifecycle events to webhook • url=".color(FG1),
+        url.color(FG2),
+    );
+    let client = reqwest::Client::builder()
+        .timeout(config.timeout)
+        .build()
+        .expect("Failed to build webhook HTTP client");
+    tokio::spawn(async move {
+        while let Some(event) = rx.recv().await {
+            deliver(&client, &url, &config, &event).await;
+        }
+    });
+}
+
+async fn deliver(client: &reqwest::Client, url: &str, config: &WebhookConfig, event: &Event) {
+    let body = match serde_json::to_vec(event) {
+        Ok(body) => body,
+        Err(e) => {
+            eprintln!(
+                "{}{}",
+                "❌ Failed to serialize webhook payload • error=".red(),
+                format!("{:?}", e).red().dimmed(),
+            );
+            return;
+        }
+    };
+    let subject = event.kind.subject();
+    let mut backoff = INITIAL_BACKOFF;
+    for attempt in 1..=config.max_attempts.max(1) {
+        let result = signed_post(client, url, config.secret.as_deref(), body.clone())
+            .header(EVENT_HEADER, subject)
+            .header(EVENT_ID_HEADER, event.id.to_string())
+            .send()
+            .await
+            .and_then(|response| response.error_for_status());
+        match result {
+            Ok(_) => {
+                counter!("strim_webhook_deliveries_total", "result" => "success").increment(1);
+                return;
+            }
+            Err(e) => {
+                eprintln!(
+                    "{}{}{}{}{}{}{}{}",
+                    "⚠️ Webhook delivery failed • url=".yellow(),
+                    url.yellow().dimmed(),
+                    " • event=".yellow(),
+                    subject.yellow().dimmed(),
+                    " • attempt=".yellow(),
+                    attempt.to_string().yellow().dimmed(),
+                    " • error=".yellow(),
+                    e.to_string().yellow().dimmed(),
+                );
+                if attempt < config.max_attempts {
+                    tokio::time::sleep(backoff).await;
+                    backoff = (backoff * 2).min(MAX_BACKOFF);
+                }
+            }
+        }
+    }
+    counter!("strim_webhook_deliveries_total", "result" => "dropped").increment(1);
+}
+
+/// Payload sent to the `on_publish` webhook before a publish is accepted.
+#[derive(Serialize, Clone, Debug)]
+pub struct PublishAuthorizationRequest {
+    pub pod_name: String,
+    pub connection_id: usize,
+    pub app_name: String,
+    pub stable_id: String,
+    pub stream_key: String,
+}
+
+/// Optional response body of the `on_publish` webhook. A non-2xx status
+/// denies the publish even without a body.
+#[derive(Deserialize, Debug)]
+struct PublishAuthorizationResponse {
+    #[serde(default = "default_allow")]
+    allow: bool,
+    reason: Option<String>,
+}
+
+impl Default for PublishAuthorizationResponse {
+    fn default() -> Self {
+        Self {
+            allow: true,
+            reason: None,
+        }
+    }
+}
+
+fn default_allow() -> bool {
+    true
+}
+
+/// Webhook consulted before a publish is accepted, able to veto it.
+#[derive(Clone)]
+pub struct OnPublishHook {
+    client: reqwest::Client,
+    url: String,
+    secret: Option<String>,
+    fail_open: bool,
+}
+
+impl OnPublishHook {
+    pub fn new(url: String, secret: Option<String>, timeout: Duration, fail_open: bool) -> Self {
+        println!(
+            "{}{}{}{}",
+            "🪝 Authorizing publishes with webhook • url=".color(FG1),
+            url.color(FG2),
+            " • fail_open=".color(FG1),
+            fail_open.to_string().color(FG2),
+        );
+        Self {
+            client: reqwest::Client::builder()
+                .timeout(timeout)
+                .build()
+                .expect("Failed to build on_publish HTTP client"),
+            url,
+            secret,
+            fail_open,
+        }
+    }
+
+    /// Asks the webhook whether the publish may proceed.
+    pub async fn authorize(&self, request: &PublishAuthorizationRequest) -> PublishDecision {
+        match request_decision(&self.client, &self.url, self.secret.as_deref(), request).await {
+            Ok(decision) => decision,
+            Err(e) => {
+                eprintln!(
+                    "{}{}{}{}",
+                    "❌ on_publish webhook failed • url=".red(),
+                    self.url.red().dimmed(),
+                    " • error=".red(),
+                    format!("{:?}", e).red().dimmed(),
+                );
+                if self.fail_open {
+                    PublishDecision::Allowed
+                } else {
+                    PublishDecision::Denied {
+                        reason: "Publish authorization unavailable".to_string(),
+                    }
+                }
+            }
+        }
+    }
+}
+
+async fn request_decision(
+    client: &reqwest::Client,
+    url: &str,
+    secret: Option<&str>,
+    request: &PublishAuthorizationRequest,
+) -> anyhow::Result<PublishDecision> {
+    let body = serde_json::to_vec(request)?;
+    let response = signed_post(client, url, secret, body)
+        .header(EVENT_HEADER, "publish.authorize")
+        .send()
+        .await?;
+    let status = response.status();
+    if status.is_server_error() {
+        anyhow::bail!("on_publish webhook returned {}", status);
+    }
+    let allowed_by_status = status.is_success();
+    let bytes = response.bytes().await?;
+    let body: PublishAuthorizationResponse = serde_json::from_slice(&bytes).unwrap_or_default();
+    Ok(if allowed_by_status && body.allow {
+        PublishDecision::Allowed
+    } else {
+        PublishDecision::Denied {
+            reason: body
+                .reason
+                .unwrap_or_else(|| format!("Publish rejected by webhook ({})", status)),
+        }
+    })
+}
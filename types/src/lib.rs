@@ -25,6 +25,18 @@ pub struct StrimTarget {
     pub delete_old_segments_after: Option<String>,
 }
 
+/// Segmenting settings for the HLS output of a [`Strim`].
+#[derive(Deserialize, Serialize, Clone, Debug, Default, PartialEq, JsonSchema)]
+pub struct StrimHls {
+    /// Target duration of each segment, in seconds.
+    #[serde(rename = "segmentDuration")]
+    pub segment_duration: Option<u32>,
+
+    /// Number of segments kept in the playlist.
+    #[serde(rename = "listSize")]
+    pub list_size: Option<u32>,
+}
+
 #[derive(CustomResource, Serialize, Deserialize, Default, Debug, PartialEq, Clone, JsonSchema)]
 #[kube(
     group = "strim.beebs.dev",
@@ -42,6 +54,24 @@ pub struct StrimTarget {
 #[kube(
     printcolumn = "{\"jsonPath\": \".status.lastUpdated\", \"name\": \"AGE\", \"type\": \"date\" }"
 )]
+#[kube(
+    printcolumn = "{\"jsonPath\": \".status.stats.publishedAt\", \"name\": \"UPTIME\", \"type\": \"date\" }"
+)]
+#[kube(
+    printcolumn = "{\"jsonPath\": \".status.stats.watchers\", \"name\": \"WATCHERS\", \"type\": \"integer\" }"
+)]
+#[kube(
+    printcolumn = "{\"jsonPath\": \".status.stats.bitrateKbps\", \"name\": \"KBPS\", \"type\": \"integer\" }"
+)]
+#[kube(
+    printcolumn = "{\"jsonPath\": \".status.stats.video\", \"name\": \"VIDEO\", \"type\": \"string\", \"priority\": 1 }"
+)]
+#[kube(
+    printcolumn = "{\"jsonPath\": \".status.stats.audio\", \"name\": \"AUDIO\", \"type\": \"string\", \"priority\": 1 }"
+)]
+#[kube(
+    printcolumn = "{\"jsonPath\": \".status.stats.publisherIp\", \"name\": \"PUBLISHER\", \"type\": \"string\", \"priority\": 1 }"
+)]
 pub struct StrimSpec {
     pub source: StrimSource,
 
@@ -49,12 +79,84 @@ pub struct StrimSpec {
 
     #[serde(default)]
     pub transcribe: bool,
+
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub hls: Option<StrimHls>,
+
+    /// Set when the publisher asked for the stream to be recorded rather
+    /// than only streamed live.
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub recording: Option<StrimRecording>,
+}
+
+/// How a [`Strim`] is recorded, from the mode of its RTMP publish.
+#[derive(Deserialize, Serialize, Clone, Copy, Debug, PartialEq, JsonSchema)]
+#[serde(rename_all = "camelCase")]
+pub enum StrimRecording {
+    /// Every segment is kept and listed in the playlist.
+    Record,
+
+    /// As [`StrimRecording::Record`], continuing the playlist left by
+    /// earlier recordings of the same stream.
+    Append,
+}
+
+/// Routes streams of some owners or apps to their own storage target, so
+/// that one ingest fleet can serve several teams.
+#[derive(CustomResource, Serialize, Deserialize, Default, Debug, PartialEq, Clone, JsonSchema)]
+#[kube(
+    group = "strim.beebs.dev",
+    version = "v1",
+    kind = "StrimIngest",
+    plural = "strimingests",
+    derive = "PartialEq",
+    namespaced
+)]
+#[kube(derive = "Default")]
+pub struct StrimIngestSpec {
+    /// Stable ids of the stream-key owners routed to this target. Matches
+    /// every owner when empty.
+    #[serde(default)]
+    pub owners: Vec<String>,
+
+    /// Apps routed to this target. Matches every app when empty.
+    #[serde(default)]
+    pub apps: Vec<String>,
+
+    /// Breaks ties between ingests matching a stream equally specifically.
+    /// The highest priority wins.
+    #[serde(default)]
+    pub priority: i32,
+
+    pub target: StrimTarget,
+
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub hls: Option<StrimHls>,
+
+    #[serde(default)]
+    pub transcribe: bool,
+}
+
+impl StrimIngestSpec {
+    /// How specifically this ingest matches a stream, or `None` if it does
+    /// not match. Owner matches are more specific than app matches.
+    pub fn specificity(&self, app_name: &str, stable_id: &str) -> Option<u8> {
+        let owner_matches = self.owners.iter().any(|owner| owner == stable_id);
+        let app_matches = self.apps.iter().any(|app| app == app_name);
+        if (!self.owners.is_empty() && !owner_matches) || (!self.apps.is_empty() && !app_matches) {
+            return None;
+        }
+        Some(2 * owner_matches as u8 + app_matches as u8)
+    }
 }
 
 /// Status object for the [`Strim`] resource.
 #[derive(Deserialize, Serialize, Clone, Debug, PartialEq, Default, JsonSchema)]
 pub struct StrimStatus {
     /// A short description of the [`Strim`] resource's current state.
+    /// Defaulted so the strim server can write `stats` before the
+    /// operator first sets a phase.
+    #[serde(default)]
     pub phase: StrimPhase,
 
     /// A human-readable message indicating details about why the
@@ -64,6 +166,55 @@ pub struct StrimStatus {
     /// Timestamp of when the [`StrimStatus`] object was last updated.
     #[serde(rename = "lastUpdated")]
     pub last_updated: Option<String>,
+
+    /// Live statistics of the stream, written periodically by the strim
+    /// server its publisher is connected to.
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub stats: Option<StrimStats>,
+}
+
+/// Live statistics of a published [`Strim`].
+#[derive(Deserialize, Serialize, Clone, Debug, PartialEq, Default, JsonSchema)]
+pub struct StrimStats {
+    /// Address the publisher is connected from.
+    #[serde(rename = "publisherIp")]
+    pub publisher_ip: Option<String>,
+
+    /// When the publish started.
+    #[serde(rename = "publishedAt")]
+    pub published_at: Option<String>,
+
+    /// Ingest bitrate measured over the last few seconds, or the bitrate
+    /// declared by the publisher until one is measured.
+    #[serde(rename = "bitrateKbps")]
+    pub bitrate_kbps: Option<u32>,
+
+    /// Number of RTMP clients playing the stream.
+    pub watchers: u32,
+
+    /// Summary of the video track, e.g. `h264 High@4.2 1920x1080 60fps`.
+    pub video: Option<String>,
+
+    /// Summary of the audio track, e.g. `aac LC 48kHz stereo`.
+    pub audio: Option<String>,
+
+    /// Destinations the stream is pushed to.
+    #[serde(default, skip_serializing_if = "Vec::is_empty")]
+    pub pushes: Vec<StrimPushStats>,
+}
+
+/// State of a push of a published [`Strim`] to another RTMP server.
+#[derive(Deserialize, Serialize, Clone, Debug, PartialEq, Default, JsonSchema)]
+pub struct StrimPushStats {
+    /// `rtmp://` url the stream is pushed to.
+    pub url: String,
+
+    /// Current state of the push, e.g. `pushing` or `reconnecting`.
+    pub state: String,
+
+    /// Connection attempts that failed since the push last succeeded.
+    #[serde(rename = "failedAttempts")]
+    pub failed_attempts: u32,
 }
 
 /// A short description of the [`Strim`] resource's current state.